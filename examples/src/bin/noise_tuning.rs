@@ -0,0 +1,91 @@
+use na::dimension::{U1, U2, U4};
+use na::{OMatrix, OVector, Vector2, Vector4};
+use nalgebra as na;
+use nalgebra_rand_mvn::rand_mvn;
+
+use adskalman::optimize::{maximize_likelihood, NelderMeadOptions};
+use adskalman::ObservationModelLinear;
+
+use adskalman_examples::linear_observation_model;
+use adskalman_examples::motion_model;
+
+type MyType = f64;
+
+// the main program --------
+
+fn main() -> Result<(), anyhow::Error> {
+    env_logger::init();
+
+    let dt = 0.01;
+    let true_initial_state = OVector::<MyType, U4>::new(0.0, 0.0, 10.0, -5.0);
+
+    #[rustfmt::skip]
+    let initial_covariance = OMatrix::<MyType,U4,U4>::new(0.1, 0.0, 0.0, 0.0,
+        0.0, 0.1, 0.0, 0.0,
+        0.0, 0.0, 0.1, 0.0,
+        0.0, 0.0, 0.0, 0.1);
+
+    let true_noise_scale = 100.0;
+    let true_observation_variance = 0.01;
+    let motion_model = motion_model::ConstantVelocity2DModel::new(dt, true_noise_scale);
+    let observation_model =
+        linear_observation_model::PositionObservationModel::new(true_observation_variance);
+
+    // Create some fake data with our model.
+    let mut current_state = true_initial_state;
+    let mut state = vec![];
+    let zero4 = Vector4::<MyType>::zeros();
+    let mut cur_time = 0.0;
+    while cur_time < 5.0 {
+        state.push(current_state);
+        let noise_sample: OMatrix<MyType, U1, U4> =
+            rand_mvn(&zero4, motion_model.transition_noise_covariance).unwrap();
+        let noise_sample_col: OVector<MyType, U4> = noise_sample.transpose();
+        current_state = motion_model.transition_model * current_state + noise_sample_col;
+        cur_time += dt;
+    }
+
+    // Create noisy observations.
+    let mut observation = vec![];
+    let zero2 = Vector2::<MyType>::zeros();
+    for current_state in state.iter() {
+        let noise_sample: OMatrix<MyType, U1, U2> =
+            rand_mvn(&zero2, observation_model.observation_noise_covariance).unwrap();
+        let noise_sample_col = noise_sample.transpose();
+        let current_observation = observation_model.evaluate(current_state) + noise_sample_col;
+        observation.push(current_observation);
+    }
+
+    // Fit the noise scale and the observation variance, starting from a poor
+    // guess. The parameters are optimized in log space to keep them positive.
+    let initial_estimate =
+        adskalman::StateAndCovariance::new(true_initial_state, initial_covariance);
+    let result = maximize_likelihood(
+        |p: &[MyType]| {
+            (
+                motion_model::ConstantVelocity2DModel::new(dt, p[0].exp()),
+                linear_observation_model::PositionObservationModel::new(p[1].exp()),
+            )
+        },
+        &initial_estimate,
+        &[&observation],
+        &[1.0f64.ln(), 1.0f64.ln()],
+        &NelderMeadOptions::default(),
+    )?;
+
+    println!(
+        "noise_scale: {} (true: {})",
+        result.parameters[0].exp(),
+        true_noise_scale
+    );
+    println!(
+        "observation variance: {} (true: {})",
+        result.parameters[1].exp(),
+        true_observation_variance
+    );
+    println!(
+        "log-likelihood: {}, converged: {} after {} iterations",
+        result.log_likelihood, result.converged, result.iterations
+    );
+    Ok(())
+}
@@ -0,0 +1,482 @@
+//! Adaptive online estimation of process and observation noise
+//!
+//! When the noise covariances of a system drift over time, a filter with fixed
+//! `Q` and `R` becomes over- or under-confident. The adaptive filter here
+//! re-estimates `R` and/or `Q` after each update from recent innovations.
+//!
+//! Two schemes are available:
+//!
+//! - Sage-Husa estimation, in which the noise estimates are exponentially
+//!   weighted averages with a forgetting factor. See A. P. Sage and G. W. Husa,
+//!   "Adaptive filtering with unknown prior statistics", Joint Automatic
+//!   Control Conference, 1969.
+//! - Innovation covariance matching, in which the sample covariance of the
+//!   innovations over a sliding window is matched to its theoretical value.
+//!   See A. H. Mohamed and K. P. Schwarz, "Adaptive Kalman filtering for
+//!   INS/GPS", Journal of Geodesy, 1999.
+//!
+//! Noise estimates which would not be positive definite are rejected, in which
+//! case the previous estimate is kept.
+
+use std::collections::VecDeque;
+
+use log::trace;
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, RealField};
+use na::{OMatrix, OVector};
+use nalgebra as na;
+
+use crate::{
+    is_missing, outer_product, CoverianceUpdateMethod, Error, ObservationModelLinear,
+    StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// Specifies the scheme used to adapt the noise covariances
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AdaptationMethod<R: RealField> {
+    /// Sage-Husa estimation with the given forgetting factor in `(0, 1)`.
+    ///
+    /// Values close to one adapt slowly and average over many steps. The
+    /// effective number of steps averaged is approximately
+    /// `1/(1-forgetting_factor)`.
+    SageHusa {
+        /// The forgetting factor.
+        forgetting_factor: R,
+    },
+    /// Innovation covariance matching over a sliding window of the given
+    /// number of steps, which must be at least one.
+    CovarianceMatching {
+        /// The number of recent innovations used.
+        window: usize,
+    },
+}
+
+/// Specifies which noise covariances are adapted
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AdaptedNoise {
+    /// Adapt only the observation noise covariance `R`.
+    Observation,
+    /// Adapt only the transition noise covariance `Q`.
+    Transition,
+    /// Adapt both `Q` and `R`.
+    Both,
+}
+
+impl AdaptedNoise {
+    fn observation(&self) -> bool {
+        matches!(self, AdaptedNoise::Observation | AdaptedNoise::Both)
+    }
+    fn transition(&self) -> bool {
+        matches!(self, AdaptedNoise::Transition | AdaptedNoise::Both)
+    }
+}
+
+/// A Kalman filter which adapts its noise covariances online
+///
+/// This is like [`KalmanFilterNoControl`](../struct.KalmanFilterNoControl.html)
+/// but keeps its own estimates of the transition noise covariance `Q` and the
+/// observation noise covariance `R`, which are initialized from the models and
+/// updated after each step. The state transition model `F` and the
+/// observation model are taken from the models.
+pub struct AdaptiveKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    method: AdaptationMethod<R>,
+    adapted: AdaptedNoise,
+    transition_noise_covariance: OMatrix<R, SS, SS>,
+    observation_noise_covariance: OMatrix<R, OS, OS>,
+    // forgetting_factor^k after k updates; this underflows to zero rather than
+    // overflowing like a count of the updates.
+    forgetting_power: R,
+    recent_innovations: VecDeque<OMatrix<R, OS, OS>>,
+}
+
+impl<'a, R, SS, OS> AdaptiveKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `AdaptiveKalmanFilter` struct.
+    ///
+    /// The initial noise estimates are the noise covariances of
+    /// `transition_model` and `observation_model`.
+    ///
+    /// Panics if the forgetting factor of `AdaptationMethod::SageHusa` is not
+    /// in `(0, 1)` or the window of `AdaptationMethod::CovarianceMatching` is
+    /// zero.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        method: AdaptationMethod<R>,
+        adapted: AdaptedNoise,
+    ) -> Self {
+        match method {
+            AdaptationMethod::SageHusa { forgetting_factor } => assert!(
+                forgetting_factor > R::zero() && forgetting_factor < R::one(),
+                "the forgetting factor must be in (0, 1)"
+            ),
+            AdaptationMethod::CovarianceMatching { window } => {
+                assert!(window > 0, "the window must not be empty")
+            }
+        }
+        Self {
+            transition_model,
+            observation_model,
+            method,
+            adapted,
+            transition_noise_covariance: transition_model.transition_noise_covariance().clone(),
+            observation_noise_covariance: observation_model.observation_noise_covariance().clone(),
+            forgetting_power: R::one(),
+            recent_innovations: VecDeque::new(),
+        }
+    }
+
+    /// Get the current estimate of the transition noise covariance `Q`.
+    #[inline]
+    pub fn transition_noise_covariance(&self) -> &OMatrix<R, SS, SS> {
+        &self.transition_noise_covariance
+    }
+
+    /// Get the current estimate of the observation noise covariance `R`.
+    #[inline]
+    pub fn observation_noise_covariance(&self) -> &OMatrix<R, OS, OS> {
+        &self.observation_noise_covariance
+    }
+
+    /// Perform Kalman prediction and update steps and adapt the noise estimates
+    ///
+    /// If any component of the observation is NaN (not a number), the prior is
+    /// returned as the posterior and the noise estimates are not changed.
+    ///
+    /// This is a convenience method that calls
+    /// [step_with_options](struct.AdaptiveKalmanFilter.html#method.step_with_options)
+    /// with the `CoverianceUpdateMethod::OptimalKalmanForcedSymmetric`
+    /// covariance update method.
+    pub fn step(
+        &mut self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        self.step_with_options(
+            previous_estimate,
+            observation,
+            CoverianceUpdateMethod::OptimalKalmanForcedSymmetric,
+        )
+    }
+
+    /// Perform Kalman prediction and update steps and adapt the noise estimates
+    ///
+    /// The prediction and update use the current noise estimates. Afterwards,
+    /// the noise estimates are updated from the innovation.
+    pub fn step_with_options(
+        &mut self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_update_method: CoverianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let transition_model = TransitionNoiseOverride {
+            model: self.transition_model,
+            transition_noise_covariance: &self.transition_noise_covariance,
+        };
+        let prior = transition_model.predict(previous_estimate);
+        if is_missing(observation)? {
+            return Ok(prior);
+        }
+        let observation_model = ObservationNoiseOverride {
+            model: self.observation_model,
+            observation_noise_covariance: &self.observation_noise_covariance,
+        };
+        let innovation = observation_model.innovation(&prior, observation)?;
+        let posterior = observation_model.update(&prior, observation, covariance_update_method)?;
+
+        let residual = innovation.residual();
+        let residual_outer = outer_product(residual, residual);
+        // H*P*H' of the prior, the predicted part of the innovation covariance
+        let hpht = innovation.covariance() - &self.observation_noise_covariance;
+        let k_gain: OMatrix<R, SS, OS> = prior.covariance()
+            * self.observation_model.observation_matrix_transpose()
+            * innovation.covariance_inverse();
+        // F*P*F' of the previous estimate, the predicted part of the prior covariance
+        let fpft = prior.covariance() - &self.transition_noise_covariance;
+
+        let (new_r, new_q) = match self.method {
+            AdaptationMethod::SageHusa { forgetting_factor } => {
+                let one = R::one();
+                self.forgetting_power *= forgetting_factor;
+                let d = (one - forgetting_factor) / (one - self.forgetting_power);
+                let new_r =
+                    &self.observation_noise_covariance * (one - d) + (&residual_outer - &hpht) * d;
+                let new_q = &self.transition_noise_covariance * (one - d)
+                    + (&k_gain * &residual_outer * k_gain.transpose() + posterior.covariance()
+                        - fpft)
+                        * d;
+                (new_r, new_q)
+            }
+            AdaptationMethod::CovarianceMatching { window } => {
+                self.recent_innovations.push_back(residual_outer);
+                while self.recent_innovations.len() > window {
+                    self.recent_innovations.pop_front();
+                }
+                let n: R = na::convert(self.recent_innovations.len() as f64);
+                let sample_covariance = self
+                    .recent_innovations
+                    .iter()
+                    .fold(OMatrix::<R, OS, OS>::zeros(), |acc, x| acc + x)
+                    / n;
+                let new_r = &sample_covariance - &hpht;
+                let new_q = &k_gain * &sample_covariance * k_gain.transpose();
+                (new_r, new_q)
+            }
+        };
+
+        if self.adapted.observation() {
+            if let Some(r) = positive_definite_or_none(new_r) {
+                self.observation_noise_covariance = r;
+            } else {
+                trace!("rejected observation noise estimate");
+            }
+        }
+        if self.adapted.transition() {
+            if let Some(q) = positive_definite_or_none(new_q) {
+                self.transition_noise_covariance = q;
+            } else {
+                trace!("rejected transition noise estimate");
+            }
+        }
+        Ok(posterior)
+    }
+}
+
+/// Symmetrize a covariance matrix and return it if it is positive definite.
+fn positive_definite_or_none<R, D>(m: OMatrix<R, D, D>) -> Option<OMatrix<R, D, D>>
+where
+    R: RealField,
+    D: DimName,
+    DefaultAllocator: Allocator<R, D, D>,
+{
+    let half: R = na::convert(0.5);
+    let m = (&m + m.transpose()) * half;
+    na::linalg::Cholesky::new(m.clone()).map(|_| m)
+}
+
+/// A transition model with its noise covariance replaced
+struct TransitionNoiseOverride<'a, R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    transition_noise_covariance: &'a OMatrix<R, SS, SS>,
+}
+
+impl<'a, R, SS> TransitionModelLinearNoControl<R, SS> for TransitionNoiseOverride<'a, R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    fn transition_model(&self) -> &OMatrix<R, SS, SS> {
+        self.model.transition_model()
+    }
+    fn transition_model_transpose(&self) -> &OMatrix<R, SS, SS> {
+        self.model.transition_model_transpose()
+    }
+    fn transition_noise_covariance(&self) -> &OMatrix<R, SS, SS> {
+        self.transition_noise_covariance
+    }
+}
+
+/// An observation model with its noise covariance replaced
+struct ObservationNoiseOverride<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    observation_noise_covariance: &'a OMatrix<R, OS, OS>,
+}
+
+impl<'a, R, SS, OS> ObservationModelLinear<R, SS, OS> for ObservationNoiseOverride<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    fn evaluate(&self, state: &OVector<R, SS>) -> OVector<R, OS> {
+        self.model.evaluate(state)
+    }
+    fn observation_matrix(&self) -> &OMatrix<R, OS, SS> {
+        self.model.observation_matrix()
+    }
+    fn observation_matrix_transpose(&self) -> &OMatrix<R, SS, OS> {
+        self.model.observation_matrix_transpose()
+    }
+    fn observation_noise_covariance(&self) -> &OMatrix<R, OS, OS> {
+        self.observation_noise_covariance
+    }
+}
+
+#[test]
+fn test_adaptive_observation_noise() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::U1;
+    use rand_core::SeedableRng;
+
+    let true_q: f64 = 0.01;
+    let true_r: f64 = 4.0;
+    let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(42);
+    let mut normal = || crate::particle::standard_normal::<f64>(&mut rng);
+    let mut x = 0.0;
+    let mut observations = Vec::new();
+    for _ in 0..3000 {
+        x += true_q.sqrt() * normal();
+        observations.push(OVector::<f64, U1>::new(x + true_r.sqrt() * normal()));
+    }
+
+    let transition_model = LinearTransitionModel::<f64, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(true_q),
+    );
+    // Start with a badly wrong observation noise.
+    let observation_model = LinearObservationModel::<f64, U1, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(0.1),
+    );
+    let initial_estimate = StateAndCovariance::new(
+        OVector::<f64, U1>::new(0.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+
+    for method in [
+        AdaptationMethod::SageHusa {
+            forgetting_factor: 0.995,
+        },
+        AdaptationMethod::CovarianceMatching { window: 500 },
+    ]
+    .iter()
+    {
+        let mut kf = AdaptiveKalmanFilter::new(
+            &transition_model,
+            &observation_model,
+            *method,
+            AdaptedNoise::Observation,
+        );
+        let mut estimate = initial_estimate.clone();
+        for observation in observations.iter() {
+            estimate = kf.step(&estimate, observation).unwrap();
+        }
+        let r = kf.observation_noise_covariance()[(0, 0)];
+        assert!((r - true_r).abs() < 1.0, "{:?}: r = {}", method, r);
+        // Q was not adapted.
+        assert_eq!(kf.transition_noise_covariance()[(0, 0)], true_q);
+    }
+}
+
+#[test]
+fn test_adaptive_transition_noise() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::U1;
+    use rand_core::SeedableRng;
+
+    let true_q: f64 = 0.5;
+    let true_r: f64 = 1.0;
+    let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(7);
+    let mut normal = || crate::particle::standard_normal::<f64>(&mut rng);
+    let mut x = 0.0;
+    let mut observations = Vec::new();
+    for _ in 0..3000 {
+        x += true_q.sqrt() * normal();
+        observations.push(OVector::<f64, U1>::new(x + true_r.sqrt() * normal()));
+    }
+
+    // Start with a badly wrong transition noise.
+    let transition_model = LinearTransitionModel::<f64, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(0.01),
+    );
+    let observation_model = LinearObservationModel::<f64, U1, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(true_r),
+    );
+    let initial_estimate = StateAndCovariance::new(
+        OVector::<f64, U1>::new(0.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+
+    for method in [
+        AdaptationMethod::SageHusa {
+            forgetting_factor: 0.995,
+        },
+        AdaptationMethod::CovarianceMatching { window: 500 },
+    ]
+    .iter()
+    {
+        let mut kf = AdaptiveKalmanFilter::new(
+            &transition_model,
+            &observation_model,
+            *method,
+            AdaptedNoise::Transition,
+        );
+        let mut estimate = initial_estimate.clone();
+        for observation in observations.iter() {
+            estimate = kf.step(&estimate, observation).unwrap();
+        }
+        let q = kf.transition_noise_covariance()[(0, 0)];
+        assert!((q - true_q).abs() < 0.25, "{:?}: q = {}", method, q);
+        // R was not adapted.
+        assert_eq!(kf.observation_noise_covariance()[(0, 0)], true_r);
+    }
+}
+
+#[test]
+#[should_panic(expected = "the forgetting factor must be in (0, 1)")]
+fn test_adaptive_invalid_forgetting_factor() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::U1;
+
+    let transition_model = LinearTransitionModel::<f64, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    let observation_model = LinearObservationModel::<f64, U1, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    AdaptiveKalmanFilter::new(
+        &transition_model,
+        &observation_model,
+        AdaptationMethod::SageHusa {
+            forgetting_factor: 1.0,
+        },
+        AdaptedNoise::Both,
+    );
+}
@@ -0,0 +1,368 @@
+use na::allocator::Allocator;
+use na::dimension::{DimMin, DimNameAdd, DimNameSum};
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_missing, CoverianceUpdateMethod, Error, Innovation, LinearObservationModel,
+    LinearTransitionModel, ObservationModelLinear, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// First-order Markov (colored) observation noise
+///
+/// The observation noise evolves as `v_k = Psi v_{k-1} + xi_{k-1}`, where the
+/// driving noise `xi` is white with covariance `Q_v`. `Psi` and `Q_v` describe
+/// the shaping filter of the noise.
+///
+/// Colored noise can be handled either by augmenting the state with the noise
+/// (see
+/// [`augment_transition_model`](struct.ColoredObservationNoise.html#method.augment_transition_model))
+/// or, keeping the state size unchanged, by measurement differencing with
+/// [`MeasurementDifferencingKalmanFilter`](struct.MeasurementDifferencingKalmanFilter.html).
+#[derive(Debug, Clone)]
+pub struct ColoredObservationNoise<R, OS>
+where
+    R: RealField,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    transition: OMatrix<R, OS, OS>,
+    driving_noise_covariance: OMatrix<R, OS, OS>,
+}
+
+impl<R, OS> ColoredObservationNoise<R, OS>
+where
+    R: RealField,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    /// Create new colored noise from the shaping filter `Psi` and the driving
+    /// noise covariance `Q_v`.
+    pub fn new(
+        transition: OMatrix<R, OS, OS>,
+        driving_noise_covariance: OMatrix<R, OS, OS>,
+    ) -> Self {
+        Self {
+            transition,
+            driving_noise_covariance,
+        }
+    }
+
+    /// Create new stationary colored noise with transition `Psi` and
+    /// covariance `covariance`.
+    ///
+    /// The driving noise covariance is `Q_v = C - Psi C Psi'`, so that the
+    /// noise covariance stays `C` at all times. For a scalar noise with
+    /// correlation time `tau` sampled at interval `dt`, `Psi` is
+    /// `exp(-dt/tau)`.
+    pub fn stationary(transition: OMatrix<R, OS, OS>, covariance: &OMatrix<R, OS, OS>) -> Self {
+        let driving_noise_covariance =
+            covariance - &transition * covariance * transition.transpose();
+        Self::new(transition, driving_noise_covariance)
+    }
+
+    /// Get the noise transition `Psi`.
+    #[inline]
+    pub fn transition(&self) -> &OMatrix<R, OS, OS> {
+        &self.transition
+    }
+
+    /// Get the driving noise covariance `Q_v`.
+    #[inline]
+    pub fn driving_noise_covariance(&self) -> &OMatrix<R, OS, OS> {
+        &self.driving_noise_covariance
+    }
+
+    /// Augment a transition model with the noise state.
+    ///
+    /// The augmented state is `[x; v]`, with transition `diag(F, Psi)` and
+    /// transition noise covariance `diag(Q, Q_v)`.
+    pub fn augment_transition_model<SS>(
+        &self,
+        transition_model: &dyn TransitionModelLinearNoControl<R, SS>,
+    ) -> LinearTransitionModel<R, DimNameSum<SS, OS>>
+    where
+        SS: DimName + DimNameAdd<OS>,
+        DefaultAllocator: Allocator<R, SS, SS>,
+        DefaultAllocator: Allocator<R, SS>,
+        DefaultAllocator: Allocator<R, DimNameSum<SS, OS>, DimNameSum<SS, OS>>,
+        DefaultAllocator: Allocator<R, DimNameSum<SS, OS>>,
+    {
+        let n = SS::dim();
+        let f = transition_model.transition_model();
+        let q = transition_model.transition_noise_covariance();
+        let block_diagonal = |a: &OMatrix<R, SS, SS>, b: &OMatrix<R, OS, OS>| {
+            OMatrix::<R, DimNameSum<SS, OS>, DimNameSum<SS, OS>>::from_fn(|i, j| {
+                if i < n && j < n {
+                    a[(i, j)]
+                } else if i >= n && j >= n {
+                    b[(i - n, j - n)]
+                } else {
+                    R::zero()
+                }
+            })
+        };
+        LinearTransitionModel::new(
+            block_diagonal(f, &self.transition),
+            block_diagonal(q, &self.driving_noise_covariance),
+        )
+    }
+
+    /// Augment an observation model with the noise state.
+    ///
+    /// The augmented observation matrix is `[H I]`. All observation noise is
+    /// in the augmented state, so the observation noise covariance of the
+    /// augmented model is zero.
+    pub fn augment_observation_model<SS>(
+        &self,
+        observation_model: &dyn ObservationModelLinear<R, SS, OS>,
+    ) -> LinearObservationModel<R, DimNameSum<SS, OS>, OS>
+    where
+        SS: DimName + DimNameAdd<OS>,
+        OS: DimMin<OS, Output = OS>,
+        DefaultAllocator: Allocator<R, SS, SS>,
+        DefaultAllocator: Allocator<R, SS>,
+        DefaultAllocator: Allocator<R, OS, SS>,
+        DefaultAllocator: Allocator<R, SS, OS>,
+        DefaultAllocator: Allocator<(usize, usize), OS>,
+        DefaultAllocator: Allocator<R, OS, DimNameSum<SS, OS>>,
+        DefaultAllocator: Allocator<R, DimNameSum<SS, OS>, OS>,
+    {
+        let n = SS::dim();
+        let h = observation_model.observation_matrix();
+        let augmented = OMatrix::<R, OS, DimNameSum<SS, OS>>::from_fn(|i, j| {
+            if j < n {
+                h[(i, j)]
+            } else if i == j - n {
+                R::one()
+            } else {
+                R::zero()
+            }
+        });
+        LinearObservationModel::new(augmented, OMatrix::<R, OS, OS>::zeros())
+    }
+
+    /// Augment a state estimate with the noise state.
+    ///
+    /// The noise has mean zero, covariance `noise_covariance` and is
+    /// uncorrelated with the state.
+    pub fn augment_estimate<SS>(
+        &self,
+        estimate: &StateAndCovariance<R, SS>,
+        noise_covariance: &OMatrix<R, OS, OS>,
+    ) -> StateAndCovariance<R, DimNameSum<SS, OS>>
+    where
+        SS: DimName + DimNameAdd<OS>,
+        DefaultAllocator: Allocator<R, SS, SS>,
+        DefaultAllocator: Allocator<R, SS>,
+        DefaultAllocator: Allocator<R, DimNameSum<SS, OS>, DimNameSum<SS, OS>>,
+        DefaultAllocator: Allocator<R, DimNameSum<SS, OS>>,
+    {
+        let n = SS::dim();
+        let state = OVector::<R, DimNameSum<SS, OS>>::from_fn(|i, _| {
+            if i < n {
+                estimate.state()[i]
+            } else {
+                R::zero()
+            }
+        });
+        let p = estimate.covariance();
+        let covariance = OMatrix::<R, DimNameSum<SS, OS>, DimNameSum<SS, OS>>::from_fn(|i, j| {
+            if i < n && j < n {
+                p[(i, j)]
+            } else if i >= n && j >= n {
+                noise_covariance[(i - n, j - n)]
+            } else {
+                R::zero()
+            }
+        });
+        StateAndCovariance::new(state, covariance)
+    }
+}
+
+/// A Kalman filter for colored observation noise using measurement
+/// differencing
+///
+/// Instead of augmenting the state with the noise, this filter uses the
+/// differenced observation `z_k - Psi z_{k-1}`, whose noise
+/// `H w_{k-1} + xi_{k-1}` is white but correlated with the process noise. See
+/// A. E. Bryson and L. J. Henrikson, "Estimation using sampled data containing
+/// sequentially correlated noise", Journal of Spacecraft and Rockets, 1968,
+/// and D. Simon, "Optimal State Estimation", section 7.2.
+///
+/// The estimates are the same as the state part of the augmented filter, but
+/// the state size is unchanged.
+///
+/// The observation model must be linear. Its observation noise covariance is
+/// the covariance of the colored noise `v_k` and is only used for an
+/// observation without a previous observation to difference with, such as
+/// the first observation. After a missing observation this is an
+/// approximation, because it ignores what earlier observations tell about
+/// the noise.
+pub struct MeasurementDifferencingKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    noise: &'a ColoredObservationNoise<R, OS>,
+}
+
+impl<'a, R, SS, OS> MeasurementDifferencingKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `MeasurementDifferencingKalmanFilter` struct.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        noise: &'a ColoredObservationNoise<R, OS>,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            noise,
+        }
+    }
+
+    /// Perform Kalman prediction and update steps
+    ///
+    /// `previous_observation` is the observation used for
+    /// `previous_estimate`. If any of its components is NaN (not a number),
+    /// `observation` is used without differencing. If any component of
+    /// `observation` is NaN, the prior is returned as the posterior.
+    pub fn step(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        previous_observation: &OVector<R, OS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let prior = self.transition_model.predict(previous_estimate);
+        if is_missing(observation)? {
+            return Ok(prior);
+        }
+        if is_missing(previous_observation)? {
+            return self.observation_model.update(
+                &prior,
+                observation,
+                CoverianceUpdateMethod::OptimalKalmanForcedSymmetric,
+            );
+        }
+
+        let f = self.transition_model.transition_model();
+        let q = self.transition_model.transition_noise_covariance();
+        let h = self.observation_model.observation_matrix();
+        let ht = self.observation_model.observation_matrix_transpose();
+        let psi = &self.noise.transition;
+        let p = previous_estimate.covariance();
+
+        // z_k - Psi z_{k-1} = (H F - Psi H) x_{k-1} + H w_{k-1} + xi_{k-1}
+        let hd: OMatrix<R, OS, SS> = h * f - psi * h;
+        let qht = q * ht;
+        let s = &hd * p * hd.transpose() + h * &qht + &self.noise.driving_noise_covariance;
+        let residual = observation - psi * previous_observation - &hd * previous_estimate.state();
+        let innovation = Innovation::new(residual, s)?;
+
+        // covariance of x_k with the differenced observation
+        let g: OMatrix<R, SS, OS> = f * p * hd.transpose() + qht;
+        let k_gain = &g * innovation.covariance_inverse();
+        let state = prior.state() + &k_gain * innovation.residual();
+        let covariance = prior.covariance() - &k_gain * g.transpose();
+        let half: R = na::convert(0.5);
+        Ok(StateAndCovariance::new(
+            state,
+            (&covariance + covariance.transpose()) * half,
+        ))
+    }
+
+    /// Kalman filter
+    ///
+    /// Operates on entire time series and returns a vector of state estimates.
+    /// If any observation has a NaN component, it is treated as missing.
+    #[cfg(feature = "std")]
+    pub fn filter(
+        &self,
+        initial_estimate: &StateAndCovariance<R, SS>,
+        observations: &[OVector<R, OS>],
+    ) -> Result<Vec<StateAndCovariance<R, SS>>, Error> {
+        let mut previous_estimate = initial_estimate.clone();
+        let mut previous_observation = OVector::<R, OS>::from_element(na::convert(f64::NAN));
+        let mut state_estimates = Vec::with_capacity(observations.len());
+        for (index, observation) in observations.iter().enumerate() {
+            previous_estimate = self
+                .step(&previous_estimate, &previous_observation, observation)
+                .map_err(|e| e.with_index(index))?;
+            previous_observation = observation.clone();
+            state_estimates.push(previous_estimate.clone());
+        }
+        Ok(state_estimates)
+    }
+}
+
+#[test]
+fn test_colored_noise() {
+    use crate::KalmanFilterNoControl;
+    use na::{U1, U2};
+
+    // Constant velocity model observing position with first-order Markov
+    // noise.
+    let transition_model = LinearTransitionModel::new(
+        OMatrix::<f64, U2, U2>::new(1.0, 1.0, 0.0, 1.0),
+        OMatrix::<f64, U2, U2>::new(1.0 / 3.0, 0.5, 0.5, 1.0) * 0.01,
+    );
+    let noise_covariance = OMatrix::<f64, U1, U1>::new(0.5);
+    let observation_model =
+        LinearObservationModel::new(OMatrix::<f64, U1, U2>::new(1.0, 0.0), noise_covariance);
+    let noise =
+        ColoredObservationNoise::stationary(OMatrix::<f64, U1, U1>::new(0.8), &noise_covariance);
+    assert!((noise.driving_noise_covariance()[0] - 0.18).abs() < 1e-12);
+
+    let initial = StateAndCovariance::new(
+        OVector::<f64, U2>::new(0.0, 1.0),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    let observations: Vec<_> = [1.3, 2.1, 2.6, 4.2, 5.5, 5.8, f64::NAN]
+        .iter()
+        .map(|z| OVector::<f64, U1>::new(*z))
+        .collect();
+
+    // The augmented filter is exact.
+    let augmented_transition_model = noise.augment_transition_model(&transition_model);
+    let augmented_observation_model = noise.augment_observation_model(&observation_model);
+    let augmented_initial = noise.augment_estimate(&initial, &noise_covariance);
+    assert_eq!(augmented_initial.state().len(), 3);
+    let augmented =
+        KalmanFilterNoControl::new(&augmented_transition_model, &augmented_observation_model)
+            .filter(&augmented_initial, &observations)
+            .unwrap();
+
+    let kf =
+        MeasurementDifferencingKalmanFilter::new(&transition_model, &observation_model, &noise);
+    let differenced = kf.filter(&initial, &observations).unwrap();
+    for (a, d) in augmented.iter().zip(differenced.iter()) {
+        let state = a.state().fixed_rows::<2>(0);
+        let covariance = a.covariance().fixed_slice::<2, 2>(0, 0);
+        assert!((d.state() - state).abs().max() < 1e-9);
+        assert!((d.covariance() - covariance).abs().max() < 1e-9);
+    }
+
+    // Ignoring the noise correlation gives a different estimate.
+    let white = KalmanFilterNoControl::new(&transition_model, &observation_model)
+        .filter(&initial, &observations)
+        .unwrap();
+    assert!((white[5].state() - differenced[5].state()).abs().max() > 1e-3);
+}
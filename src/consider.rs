@@ -0,0 +1,190 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use num_traits::identities::One;
+
+use crate::{
+    is_missing, Error, ObservationModelLinear, StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// A Schmidt-Kalman filter with consider states
+///
+/// Consider states, such as sensor biases, are part of the state so that
+/// their uncertainty affects the estimates of the other states, but they are
+/// not updated by observations. The update computes the optimal Kalman gain
+/// and then zeroes its rows for the consider states. Because this gain is not
+/// optimal for the full state, the covariance is always updated in Joseph
+/// form, which is correct for any gain. The consider states keep their prior
+/// mean and covariance while their cross-covariances with the other states
+/// are updated.
+///
+/// See S. F. Schmidt, "Application of state-space methods to navigation
+/// problems", Advances in Control Systems, 1966.
+pub struct SchmidtKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    // one for states that are updated, zero for consider states
+    update_mask: OVector<R, SS>,
+}
+
+impl<'a, R, SS, OS> SchmidtKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `SchmidtKalmanFilter` struct.
+    ///
+    /// `consider_states` are the indices of the states which are not updated.
+    ///
+    /// Panics if an index is not less than the state size.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        consider_states: &[usize],
+    ) -> Self {
+        let mut update_mask = OVector::<R, SS>::from_element(R::one());
+        for &i in consider_states.iter() {
+            update_mask[i] = R::zero();
+        }
+        Self {
+            transition_model,
+            observation_model,
+            update_mask,
+        }
+    }
+
+    /// Return whether state `i` is a consider state.
+    #[inline]
+    pub fn is_consider_state(&self, i: usize) -> bool {
+        self.update_mask[i] == R::zero()
+    }
+
+    /// Given a prior state and an observation, compute a posterior state
+    /// estimate without updating the consider states.
+    pub fn update(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let innovation = self.observation_model.innovation(prior, observation)?;
+        let h = self.observation_model.observation_matrix();
+        let r = self.observation_model.observation_noise_covariance();
+
+        let mut k_gain: OMatrix<R, SS, OS> = prior.covariance()
+            * self.observation_model.observation_matrix_transpose()
+            * innovation.covariance_inverse();
+        for (mut row, mask) in k_gain.row_iter_mut().zip(self.update_mask.iter()) {
+            row *= *mask;
+        }
+
+        let state = prior.state() + &k_gain * innovation.residual();
+        let one_minus_kh = OMatrix::<R, SS, SS>::one() - &k_gain * h;
+        let left = &one_minus_kh * prior.covariance() * one_minus_kh.transpose();
+        let right = &k_gain * r * k_gain.transpose();
+        let covariance = left + right;
+        let half: R = na::convert(0.5);
+        Ok(StateAndCovariance::new(
+            state,
+            (&covariance + covariance.transpose()) * half,
+        ))
+    }
+
+    /// Perform Kalman prediction and update steps
+    ///
+    /// If any component of the observation is NaN (not a number), the prior
+    /// is returned as the posterior.
+    pub fn step(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let prior = self.transition_model.predict(previous_estimate);
+        if is_missing(observation)? {
+            Ok(prior)
+        } else {
+            self.update(&prior, observation)
+        }
+    }
+
+    /// Schmidt-Kalman filter
+    ///
+    /// Operates on entire time series and returns a vector of state estimates.
+    /// If any observation has a NaN component, it is treated as missing.
+    #[cfg(feature = "std")]
+    pub fn filter(
+        &self,
+        initial_estimate: &StateAndCovariance<R, SS>,
+        observations: &[OVector<R, OS>],
+    ) -> Result<Vec<StateAndCovariance<R, SS>>, Error> {
+        let mut previous_estimate = initial_estimate.clone();
+        let mut state_estimates = Vec::with_capacity(observations.len());
+        for (index, observation) in observations.iter().enumerate() {
+            previous_estimate = self
+                .step(&previous_estimate, observation)
+                .map_err(|e| e.with_index(index))?;
+            state_estimates.push(previous_estimate.clone());
+        }
+        Ok(state_estimates)
+    }
+}
+
+#[test]
+fn test_schmidt_kalman_filter() {
+    use crate::{KalmanFilterNoControl, LinearObservationModel, LinearTransitionModel};
+    use na::{U1, U2};
+
+    // A random walk observed with a constant but unknown sensor bias.
+    let transition_model = LinearTransitionModel::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::new(0.1, 0.0, 0.0, 0.0),
+    );
+    let observation_model = LinearObservationModel::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 1.0),
+        OMatrix::<f64, U1, U1>::new(0.5),
+    );
+    let initial = StateAndCovariance::new(
+        OVector::<f64, U2>::new(0.0, 0.2),
+        OMatrix::<f64, U2, U2>::new(1.0, 0.0, 0.0, 0.3),
+    );
+    let observations: Vec<_> = [0.4, 0.7, f64::NAN, 0.5, 0.9]
+        .iter()
+        .map(|z| OVector::<f64, U1>::new(*z))
+        .collect();
+
+    let skf = SchmidtKalmanFilter::new(&transition_model, &observation_model, &[1]);
+    assert!(!skf.is_consider_state(0));
+    assert!(skf.is_consider_state(1));
+    let kf = KalmanFilterNoControl::new(&transition_model, &observation_model);
+    let considered = skf.filter(&initial, &observations).unwrap();
+    let full = kf.filter(&initial, &observations).unwrap();
+
+    // The first update of the estimated state is the optimal one.
+    assert!((considered[0].state()[0] - full[0].state()[0]).abs() < 1e-12);
+    assert!((considered[0].covariance()[(0, 0)] - full[0].covariance()[(0, 0)]).abs() < 1e-12);
+
+    for (c, f) in considered.iter().zip(full.iter()) {
+        // The bias is not updated.
+        assert_eq!(c.state()[1], 0.2);
+        assert!((c.covariance()[(1, 1)] - 0.3).abs() < 1e-12);
+        // Its uncertainty is accounted for in the cross-covariance.
+        assert!(c.covariance()[(0, 1)] < 0.0);
+        // Not estimating the bias loses information.
+        assert!(c.covariance()[(0, 0)] >= f.covariance()[(0, 0)] - 1e-12);
+    }
+}
@@ -0,0 +1,684 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField, U2};
+use nalgebra as na;
+
+use crate::{
+    outer_product, CoverianceUpdateMethod, Error, ErrorKind, KalmanFilterNoControl,
+    ObservationModelLinear, StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// A linear equality constraint `D x = d` on the state
+///
+/// The generic type `CS` is the number of constraint equations.
+///
+/// A constraint may be hard, in which case estimates are projected onto the
+/// constraint surface, or soft, in which case it is applied as a
+/// pseudo-measurement `d = D x + e` with noise `e` of the given covariance. A
+/// soft constraint with zero covariance is a perfect pseudo-measurement, which
+/// is equivalent to the hard constraint.
+#[derive(Debug, Clone)]
+pub struct LinearConstraint<R, SS, CS>
+where
+    R: RealField,
+    SS: DimName,
+    CS: DimName,
+    DefaultAllocator: Allocator<R, CS, SS>,
+    DefaultAllocator: Allocator<R, SS, CS>,
+    DefaultAllocator: Allocator<R, CS, CS>,
+    DefaultAllocator: Allocator<R, CS>,
+{
+    matrix: OMatrix<R, CS, SS>,
+    matrix_transpose: OMatrix<R, SS, CS>,
+    value: OVector<R, CS>,
+    covariance: OMatrix<R, CS, CS>,
+}
+
+impl<R, SS, CS> LinearConstraint<R, SS, CS>
+where
+    R: RealField,
+    SS: DimName,
+    CS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, CS, SS>,
+    DefaultAllocator: Allocator<R, SS, CS>,
+    DefaultAllocator: Allocator<R, CS, CS>,
+    DefaultAllocator: Allocator<R, CS>,
+{
+    /// Create a new hard constraint `matrix * x = value`.
+    ///
+    /// The rows of `matrix` must be linearly independent.
+    pub fn new(matrix: OMatrix<R, CS, SS>, value: OVector<R, CS>) -> Self {
+        Self::new_soft(matrix, value, OMatrix::<R, CS, CS>::zeros())
+    }
+
+    /// Create a new soft constraint `matrix * x = value + e`, where `e` has
+    /// covariance `covariance`.
+    pub fn new_soft(
+        matrix: OMatrix<R, CS, SS>,
+        value: OVector<R, CS>,
+        covariance: OMatrix<R, CS, CS>,
+    ) -> Self {
+        let matrix_transpose = matrix.transpose();
+        Self {
+            matrix,
+            matrix_transpose,
+            value,
+            covariance,
+        }
+    }
+
+    /// Get the constraint matrix `D`.
+    #[inline]
+    pub fn matrix(&self) -> &OMatrix<R, CS, SS> {
+        &self.matrix
+    }
+
+    /// Get the constraint value `d`.
+    #[inline]
+    pub fn value(&self) -> &OVector<R, CS> {
+        &self.value
+    }
+
+    /// Get the covariance of the constraint noise, which is zero for a hard
+    /// constraint.
+    #[inline]
+    pub fn covariance(&self) -> &OMatrix<R, CS, CS> {
+        &self.covariance
+    }
+
+    /// Compute the constraint residual `D x - d`.
+    pub fn residual(&self, state: &OVector<R, SS>) -> OVector<R, CS> {
+        &self.matrix * state - &self.value
+    }
+
+    /// Apply the constraint to an estimate.
+    ///
+    /// For a hard constraint, this is the projection of the state onto the
+    /// constraint surface which minimizes the change in state weighted by the
+    /// inverse covariance, and the covariance is reduced accordingly (so that
+    /// it is singular in the constrained directions). For a soft constraint,
+    /// this is a Kalman update with the pseudo-measurement. See D. Simon,
+    /// "Kalman filtering with state constraints: a survey of linear and
+    /// nonlinear algorithms", IET Control Theory & Applications, 2010.
+    pub fn apply(
+        &self,
+        estimate: &StateAndCovariance<R, SS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let p = estimate.covariance();
+        let pdt: OMatrix<R, SS, CS> = p * &self.matrix_transpose;
+        let s = &self.matrix * &pdt + &self.covariance;
+        let s_chol = match na::linalg::Cholesky::new(s.clone()) {
+            Some(v) => v,
+            None => {
+                // The estimate may already be certain in the constrained
+                // directions (for example, when smoothing constrained
+                // estimates), in which case `s` is singular. Regularize so
+                // that the correction is negligible in these directions.
+                let epsilon = R::default_epsilon() * (R::one() + s.trace().abs());
+                let regularized = s + OMatrix::<R, CS, CS>::identity() * epsilon;
+                match na::linalg::Cholesky::new(regularized.clone()) {
+                    Some(v) => v,
+                    None => {
+                        return Err(Error::from(
+                            ErrorKind::InnovationCovarianceNotPositiveDefinite,
+                        )
+                        .with_condition_estimate(crate::repair::condition_estimate(&regularized)));
+                    }
+                }
+            }
+        };
+        let gain: OMatrix<R, SS, CS> = &pdt * s_chol.inverse();
+        let state = estimate.state() - &gain * self.residual(estimate.state());
+        let covariance = p - &gain * pdt.transpose();
+        let half: R = na::convert(0.5);
+        Ok(StateAndCovariance::new(
+            state,
+            (&covariance + covariance.transpose()) * half,
+        ))
+    }
+}
+
+/// A Kalman filter whose estimates are constrained by a linear equality
+/// constraint
+///
+/// After each update, the posterior estimate is constrained with
+/// [`LinearConstraint::apply`](struct.LinearConstraint.html#method.apply). The
+/// smoother constrains the smoothed estimates in the same way.
+pub struct ConstrainedKalmanFilter<'a, R, SS, OS, CS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    CS: DimName,
+    DefaultAllocator: Allocator<R, CS, SS>,
+    DefaultAllocator: Allocator<R, SS, CS>,
+    DefaultAllocator: Allocator<R, CS, CS>,
+    DefaultAllocator: Allocator<R, CS>,
+{
+    kf: KalmanFilterNoControl<'a, R, SS, OS>,
+    constraint: &'a LinearConstraint<R, SS, CS>,
+}
+
+impl<'a, R, SS, OS, CS> ConstrainedKalmanFilter<'a, R, SS, OS, CS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    CS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+    DefaultAllocator: Allocator<R, CS, SS>,
+    DefaultAllocator: Allocator<R, SS, CS>,
+    DefaultAllocator: Allocator<R, CS, CS>,
+    DefaultAllocator: Allocator<R, CS>,
+{
+    /// Initialize a new `ConstrainedKalmanFilter` struct.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        constraint: &'a LinearConstraint<R, SS, CS>,
+    ) -> Self {
+        Self {
+            kf: KalmanFilterNoControl::new(transition_model, observation_model),
+            constraint,
+        }
+    }
+
+    /// Perform Kalman prediction and update steps and constrain the result.
+    ///
+    /// If any component of the observation is NaN (not a number), the
+    /// constrained prior is returned.
+    pub fn step(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        self.step_with_options(
+            previous_estimate,
+            observation,
+            CoverianceUpdateMethod::OptimalKalmanForcedSymmetric,
+        )
+    }
+
+    /// Perform Kalman prediction and update steps with the specified
+    /// covariance update method and constrain the result.
+    pub fn step_with_options(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_update_method: CoverianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let posterior =
+            self.kf
+                .step_with_options(previous_estimate, observation, covariance_update_method)?;
+        self.constraint.apply(&posterior)
+    }
+
+    /// Constrained Kalman filter
+    ///
+    /// Operates on entire time series and returns a vector of constrained state
+    /// estimates.
+    #[cfg(feature = "std")]
+    pub fn filter(
+        &self,
+        initial_estimate: &StateAndCovariance<R, SS>,
+        observations: &[OVector<R, OS>],
+    ) -> Result<Vec<StateAndCovariance<R, SS>>, Error> {
+        let mut previous_estimate = initial_estimate.clone();
+        let mut state_estimates = Vec::with_capacity(observations.len());
+        for (index, observation) in observations.iter().enumerate() {
+            previous_estimate = self
+                .step(&previous_estimate, observation)
+                .map_err(|e| e.with_index(index))?;
+            state_estimates.push(previous_estimate.clone());
+        }
+        Ok(state_estimates)
+    }
+
+    /// Constrained Rauch-Tung-Striebel (RTS) smoother
+    ///
+    /// Runs the constrained filter, smooths the constrained estimates and
+    /// constrains each smoothed estimate.
+    #[cfg(feature = "std")]
+    pub fn smooth(
+        &self,
+        initial_estimate: &StateAndCovariance<R, SS>,
+        observations: &[OVector<R, OS>],
+    ) -> Result<Vec<StateAndCovariance<R, SS>>, Error> {
+        let forward_results = self.filter(initial_estimate, observations)?;
+        self.kf
+            .smooth_from_filtered(forward_results)?
+            .iter()
+            .enumerate()
+            .map(|(index, estimate)| {
+                self.constraint
+                    .apply(estimate)
+                    .map_err(|e| e.with_index(index))
+            })
+            .collect()
+    }
+}
+
+/// Specifies how an inequality constraint is applied to an estimate
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum InequalityMethod {
+    /// Project the mean onto the feasible set, minimizing the change in state
+    /// weighted by the inverse covariance. The covariance is reduced as for
+    /// equality constraints in the directions of the active constraints.
+    Projection,
+    /// Truncate the Gaussian at each constraint in turn and approximate the
+    /// result by a Gaussian with the same mean and covariance. This moves the
+    /// mean into the feasible set and shrinks the covariance even when a
+    /// constraint is not violated by the mean, but it is not an exact
+    /// projection. See D. Simon and D. L. Simon, "Constrained Kalman filtering
+    /// via density function truncation for turbofan engine health estimation",
+    /// International Journal of Systems Science, 2010.
+    TruncatedGaussian,
+}
+
+/// Maximum number of sweeps over the constraints in the projection.
+const MAX_PROJECTION_SWEEPS: usize = 1000;
+
+/// Constrain an estimate by the inequality constraints `a_i' x <= b_i`.
+///
+/// `row(i)` returns `a_i` and `b_i`, or `None` if row `i` is unbounded.
+/// `multipliers` must have one entry per row and is used as scratch space.
+fn apply_inequalities<R, SS>(
+    estimate: &StateAndCovariance<R, SS>,
+    n_rows: usize,
+    row: &dyn Fn(usize) -> Option<(OVector<R, SS>, R)>,
+    multipliers: &mut [R],
+    method: InequalityMethod,
+) -> StateAndCovariance<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    let mut state = estimate.state().clone();
+    let mut covariance = estimate.covariance().clone();
+    let tiny = R::default_epsilon();
+    match method {
+        InequalityMethod::Projection => {
+            // Hildreth's method: coordinate ascent on the dual of the
+            // quadratic program, with x = x_prior - P * sum(lambda_i * a_i) and
+            // all lambda_i >= 0.
+            for m in multipliers.iter_mut() {
+                *m = R::zero();
+            }
+            for _ in 0..MAX_PROJECTION_SWEEPS {
+                let mut max_change = R::zero();
+                for (i, multiplier) in multipliers.iter_mut().enumerate() {
+                    if let Some((a, b)) = row(i) {
+                        let pa = estimate.covariance() * &a;
+                        let apa = a.dot(&pa);
+                        if apa <= tiny {
+                            continue;
+                        }
+                        let violation = a.dot(&state) - b;
+                        let mut updated = *multiplier + violation / apa;
+                        if updated < R::zero() {
+                            updated = R::zero();
+                        }
+                        let change = updated - *multiplier;
+                        if change != R::zero() {
+                            state -= pa * change;
+                            *multiplier = updated;
+                            if change.abs() * apa.sqrt() > max_change {
+                                max_change = change.abs() * apa.sqrt();
+                            }
+                        }
+                    }
+                }
+                if max_change <= tiny * (R::one() + state.norm()) {
+                    break;
+                }
+            }
+            // condition the covariance on the active constraints
+            for (i, multiplier) in multipliers.iter().enumerate() {
+                if *multiplier > R::zero() {
+                    if let Some((a, _)) = row(i) {
+                        let pa = &covariance * &a;
+                        let apa = a.dot(&pa);
+                        if apa > tiny {
+                            covariance -= outer_product(&pa, &pa) / apa;
+                        }
+                    }
+                }
+            }
+        }
+        InequalityMethod::TruncatedGaussian => {
+            for i in 0..n_rows {
+                if let Some((a, b)) = row(i) {
+                    let pa = &covariance * &a;
+                    let variance = a.dot(&pa);
+                    if variance <= tiny {
+                        continue;
+                    }
+                    let sigma = variance.sqrt();
+                    let mean = a.dot(&state);
+                    let alpha = (b - mean) / sigma;
+                    // moments of N(mean, variance) truncated to (-inf, b]
+                    let ratio = inverse_mills_ratio(alpha);
+                    let truncated_mean = mean - sigma * ratio;
+                    let mut truncated_variance =
+                        variance * (R::one() - alpha * ratio - ratio * ratio);
+                    if truncated_variance < R::zero() {
+                        truncated_variance = R::zero();
+                    }
+                    state += &pa * ((truncated_mean - mean) / variance);
+                    covariance += outer_product(&pa, &pa)
+                        * ((truncated_variance - variance) / (variance * variance));
+                }
+            }
+        }
+    }
+    let half: R = na::convert(0.5);
+    StateAndCovariance::new(state, (&covariance + covariance.transpose()) * half)
+}
+
+/// Compute `phi(alpha) / Phi(alpha)`, the ratio of the standard normal density
+/// to its cumulative distribution, without underflow for negative `alpha`.
+fn inverse_mills_ratio<R: RealField>(alpha: R) -> R {
+    let c = |x: f64| -> R { na::convert(x) };
+    // Complementary error function approximation with fractional error below
+    // 1.2e-7 (Numerical Recipes, erfcc): erfc(z) = t * exp(-z^2 + poly(t)).
+    let z = alpha.abs() / c(2.0).sqrt();
+    let t = R::one() / (R::one() + c(0.5) * z);
+    let coefficients = [
+        -1.26551223,
+        1.00002368,
+        0.37409196,
+        0.09678418,
+        -0.18628806,
+        0.27886807,
+        -1.13520398,
+        1.48851587,
+        -0.82215223,
+        0.17087277,
+    ];
+    let mut poly = R::zero();
+    for coefficient in coefficients.iter().rev() {
+        poly = poly * t + c(*coefficient);
+    }
+    if alpha < R::zero() {
+        // Phi(alpha) = erfc(z) / 2, and the factor exp(-alpha^2 / 2) cancels.
+        (c(2.0) / R::pi()).sqrt() / (t * poly.exp())
+    } else {
+        let half_erfc = c(0.5) * t * (-z * z + poly).exp();
+        let density = (-alpha * alpha * c(0.5)).exp() / R::two_pi().sqrt();
+        density / (R::one() - half_erfc)
+    }
+}
+
+/// Linear inequality constraints `A x <= b` on the state
+///
+/// The generic type `CS` is the number of inequalities.
+#[derive(Debug, Clone)]
+pub struct LinearInequalityConstraint<R, SS, CS>
+where
+    R: RealField,
+    SS: DimName,
+    CS: DimName,
+    DefaultAllocator: Allocator<R, CS, SS>,
+    DefaultAllocator: Allocator<R, CS>,
+{
+    matrix: OMatrix<R, CS, SS>,
+    value: OVector<R, CS>,
+}
+
+impl<R, SS, CS> LinearInequalityConstraint<R, SS, CS>
+where
+    R: RealField,
+    SS: DimName,
+    CS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, CS, SS>,
+    DefaultAllocator: Allocator<R, CS>,
+{
+    /// Create new constraints `matrix * x <= value`.
+    pub fn new(matrix: OMatrix<R, CS, SS>, value: OVector<R, CS>) -> Self {
+        Self { matrix, value }
+    }
+
+    /// Whether a state satisfies all constraints to within `tolerance`.
+    pub fn is_satisfied(&self, state: &OVector<R, SS>, tolerance: R) -> bool {
+        (&self.matrix * state - &self.value)
+            .iter()
+            .all(|v| *v <= tolerance)
+    }
+
+    /// Apply the constraints to an estimate.
+    ///
+    /// With `InequalityMethod::Projection`, the constraints must be feasible.
+    pub fn apply(
+        &self,
+        estimate: &StateAndCovariance<R, SS>,
+        method: InequalityMethod,
+    ) -> StateAndCovariance<R, SS> {
+        let row = |i: usize| Some((self.matrix.row(i).transpose(), self.value[i]));
+        let mut multipliers = OVector::<R, CS>::zeros();
+        apply_inequalities(
+            estimate,
+            CS::dim(),
+            &row,
+            multipliers.as_mut_slice(),
+            method,
+        )
+    }
+}
+
+/// Lower and upper bounds on each state component
+///
+/// Components may be left unbounded by using `R::max_value()` as the upper
+/// bound or its negative as the lower bound.
+#[derive(Debug, Clone)]
+pub struct BoxConstraint<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    lower: OVector<R, SS>,
+    upper: OVector<R, SS>,
+}
+
+impl<R, SS> BoxConstraint<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, SS, U2>,
+{
+    /// Create a new `BoxConstraint` with `lower <= x <= upper`.
+    pub fn new(lower: OVector<R, SS>, upper: OVector<R, SS>) -> Self {
+        Self { lower, upper }
+    }
+
+    /// Create a new `BoxConstraint` requiring the given components to be
+    /// non-negative and leaving the others unbounded.
+    pub fn non_negative(components: &[usize]) -> Self {
+        let mut lower = OVector::<R, SS>::from_element(-R::max_value());
+        for i in components.iter() {
+            lower[*i] = R::zero();
+        }
+        Self::new(lower, OVector::<R, SS>::from_element(R::max_value()))
+    }
+
+    /// Whether a state satisfies all bounds to within `tolerance`.
+    pub fn is_satisfied(&self, state: &OVector<R, SS>, tolerance: R) -> bool {
+        state
+            .iter()
+            .zip(self.lower.iter().zip(self.upper.iter()))
+            .all(|(x, (l, u))| *x >= *l - tolerance && *x <= *u + tolerance)
+    }
+
+    /// Apply the bounds to an estimate.
+    pub fn apply(
+        &self,
+        estimate: &StateAndCovariance<R, SS>,
+        method: InequalityMethod,
+    ) -> StateAndCovariance<R, SS> {
+        let n = SS::dim();
+        // rows 0..n are the upper bounds and rows n..2n the lower bounds
+        let row = |i: usize| {
+            let (j, sign, bound) = if i < n {
+                (i, R::one(), self.upper[i])
+            } else {
+                (i - n, -R::one(), -self.lower[i - n])
+            };
+            if bound >= R::max_value() {
+                return None;
+            }
+            let mut a = OVector::<R, SS>::zeros();
+            a[j] = sign;
+            Some((a, bound))
+        };
+        // one column of multipliers for each set of bounds
+        let mut multipliers = OMatrix::<R, SS, U2>::zeros();
+        apply_inequalities(estimate, 2 * n, &row, multipliers.as_mut_slice(), method)
+    }
+}
+
+#[test]
+fn test_equality_constraint() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{U1, U2};
+
+    // A point on the line x0 + x1 = 1, observed in noise.
+    let transition_model = LinearTransitionModel::<f64, U2>::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::identity() * 0.01,
+    );
+    let observation_model = LinearObservationModel::<f64, U2, U2>::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::new(0.1, 0.0, 0.0, 0.2),
+    );
+    let hard = LinearConstraint::<f64, U2, U1>::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 1.0),
+        OVector::<f64, U1>::new(1.0),
+    );
+    let initial = StateAndCovariance::new(
+        OVector::<f64, U2>::new(0.5, 0.5),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    let observations = vec![
+        OVector::<f64, U2>::new(0.3, 0.9),
+        OVector::<f64, U2>::new(0.2, 0.7),
+        OVector::<f64, U2>::new(f64::NAN, f64::NAN),
+        OVector::<f64, U2>::new(0.4, 0.8),
+    ];
+
+    let ckf = ConstrainedKalmanFilter::new(&transition_model, &observation_model, &hard);
+    for estimates in [
+        ckf.filter(&initial, &observations).unwrap(),
+        ckf.smooth(&initial, &observations).unwrap(),
+    ]
+    .iter()
+    {
+        assert_eq!(estimates.len(), observations.len());
+        for estimate in estimates.iter() {
+            assert!(hard.residual(estimate.state())[0].abs() < 1e-12);
+            // no uncertainty remains in the constrained direction
+            let d = hard.matrix();
+            assert!((d * estimate.covariance() * d.transpose())[0].abs() < 1e-12);
+        }
+    }
+
+    // A soft constraint pulls the estimate only partially towards the line, and
+    // a perfect pseudo-measurement is the same as the hard constraint.
+    let estimate = StateAndCovariance::new(
+        OVector::<f64, U2>::new(0.5, 1.0),
+        OMatrix::<f64, U2, U2>::new(0.1, 0.0, 0.0, 0.2),
+    );
+    let soft = LinearConstraint::<f64, U2, U1>::new_soft(
+        OMatrix::<f64, U1, U2>::new(1.0, 1.0),
+        OVector::<f64, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(0.3),
+    );
+    let residual = soft.residual(soft.apply(&estimate).unwrap().state())[0];
+    assert!(residual > 0.1 && residual < 0.5);
+    let hard_estimate = hard.apply(&estimate).unwrap();
+    // the more uncertain component moves more
+    assert!((hard_estimate.state()[0] - 0.5 + 1.0 / 6.0).abs() < 1e-12);
+    assert!((hard_estimate.state()[1] - 1.0 + 1.0 / 3.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_inequality_constraint() {
+    use na::{U1, U2};
+
+    // The first component (e.g. a radius) must be non-negative, but the
+    // estimate is negative. The components are correlated.
+    let estimate = StateAndCovariance::new(
+        OVector::<f64, U2>::new(-0.5, 1.0),
+        OMatrix::<f64, U2, U2>::new(1.0, 0.5, 0.5, 1.0),
+    );
+    let bounds = BoxConstraint::<f64, U2>::non_negative(&[0]);
+    assert!(!bounds.is_satisfied(estimate.state(), 0.0));
+
+    // The projection puts the first component on the bound and moves the
+    // second by the regression on the first. It is the same as the equality
+    // constraint on the active bound.
+    let projected = bounds.apply(&estimate, InequalityMethod::Projection);
+    assert!(bounds.is_satisfied(projected.state(), 1e-12));
+    let equality = LinearConstraint::<f64, U2, U1>::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OVector::<f64, U1>::new(0.0),
+    )
+    .apply(&estimate)
+    .unwrap();
+    assert!((projected.state() - equality.state()).norm() < 1e-12);
+    assert!((projected.covariance() - equality.covariance()).norm() < 1e-12);
+    assert!((projected.state()[1] - 1.25).abs() < 1e-12);
+
+    // An estimate already inside the bounds is unchanged by the projection.
+    let inside = StateAndCovariance::new(OVector::<f64, U2>::new(0.5, 1.0), *estimate.covariance());
+    let projected = bounds.apply(&inside, InequalityMethod::Projection);
+    assert!((projected.state() - inside.state()).norm() < 1e-12);
+    assert!((projected.covariance() - inside.covariance()).norm() < 1e-12);
+
+    // Truncation moves the mean inside and shrinks the variance. For a
+    // standard normal truncated at zero, the mean is sqrt(2/pi) and the
+    // variance 1 - 2/pi.
+    let standard = StateAndCovariance::new(
+        OVector::<f64, U2>::new(0.0, 0.0),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    let truncated = bounds.apply(&standard, InequalityMethod::TruncatedGaussian);
+    let two_over_pi = 2.0 / std::f64::consts::PI;
+    assert!((truncated.state()[0] - two_over_pi.sqrt()).abs() < 1e-6);
+    assert!((truncated.covariance()[(0, 0)] - (1.0 - two_over_pi)).abs() < 1e-6);
+    assert!((truncated.covariance()[(1, 1)] - 1.0).abs() < 1e-12);
+    // far in the tail, the truncated mean is close to the bound
+    let tail = StateAndCovariance::new(
+        OVector::<f64, U2>::new(-40.0, 0.0),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    let truncated = bounds.apply(&tail, InequalityMethod::TruncatedGaussian);
+    assert!(truncated.state()[0] > 0.0 && truncated.state()[0] < 0.03);
+
+    // general linear inequalities: x0 + x1 <= 1 and x0 - x1 <= 0
+    let constraints = LinearInequalityConstraint::<f64, U2, U2>::new(
+        OMatrix::<f64, U2, U2>::new(1.0, 1.0, 1.0, -1.0),
+        OVector::<f64, U2>::new(1.0, 0.0),
+    );
+    let estimate = StateAndCovariance::new(
+        OVector::<f64, U2>::new(2.0, 0.5),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    let projected = constraints.apply(&estimate, InequalityMethod::Projection);
+    assert!(constraints.is_satisfied(projected.state(), 1e-9));
+    // both constraints are active at the solution
+    assert!((projected.state() - OVector::<f64, U2>::new(0.5, 0.5)).norm() < 1e-9);
+}
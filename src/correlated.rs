@@ -0,0 +1,385 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use num_traits::identities::One;
+
+use crate::{
+    is_missing, CoverianceUpdateMethod, Error, Innovation, ObservationModelLinear,
+    StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// A Kalman filter for correlated process and observation noise
+///
+/// The system is
+///
+/// ```text
+/// x_k = F x_{k-1} + w_{k-1}
+/// z_k = H x_k + v_k
+/// ```
+///
+/// where the process noise `w_{k-1}` (covariance `Q`) and observation noise
+/// `v_k` (covariance `R`) have the cross-covariance `E[w_{k-1} v_k'] = S`. This
+/// is the case, for example, when both are driven by the same vibration. With
+/// `S` zero, this is the same as
+/// [`KalmanFilterNoControl`](struct.KalmanFilterNoControl.html).
+///
+/// The prediction step is unchanged. The update step uses the gain
+/// `K = (P H' + S) (H P H' + H S + S' H' + R)^-1`. See D. Simon, "Optimal State
+/// Estimation", section 7.1.
+pub struct CorrelatedNoiseKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, OS>,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    cross_covariance: OMatrix<R, SS, OS>,
+}
+
+impl<'a, R, SS, OS> CorrelatedNoiseKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `CorrelatedNoiseKalmanFilter` struct.
+    ///
+    /// `cross_covariance` is `S = E[w_{k-1} v_k']`, the covariance between the
+    /// process noise of the transition into a time step and the observation
+    /// noise at that time step.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        cross_covariance: OMatrix<R, SS, OS>,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            cross_covariance,
+        }
+    }
+
+    /// Get the cross-covariance `S` between process and observation noise.
+    #[inline]
+    pub fn cross_covariance(&self) -> &OMatrix<R, SS, OS> {
+        &self.cross_covariance
+    }
+
+    /// Compute `P H' + S`, the covariance between the state and the predicted
+    /// observation.
+    fn state_observation_covariance(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+    ) -> OMatrix<R, SS, OS> {
+        prior.covariance() * self.observation_model.observation_matrix_transpose()
+            + &self.cross_covariance
+    }
+
+    /// Given a prior state and an observation, compute the innovation.
+    ///
+    /// The innovation covariance is `H P H' + H S + S' H' + R`.
+    pub fn innovation(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<Innovation<R, OS>, Error> {
+        let h = self.observation_model.observation_matrix();
+        let hs = h * &self.cross_covariance;
+        let s = h * prior.covariance() * self.observation_model.observation_matrix_transpose()
+            + &hs
+            + hs.transpose()
+            + self.observation_model.observation_noise_covariance();
+        let residual = observation - self.observation_model.evaluate(prior.state());
+        Innovation::new(residual, s)
+    }
+
+    /// Given a prior state and an observation, compute a posterior state
+    /// estimate.
+    pub fn update(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_method: CoverianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let innovation = self.innovation(prior, observation)?;
+        let pht_s = self.state_observation_covariance(prior);
+        let k_gain: OMatrix<R, SS, OS> = &pht_s * innovation.covariance_inverse();
+        let state = prior.state() + &k_gain * innovation.residual();
+
+        let h = self.observation_model.observation_matrix();
+        let one_minus_kh = OMatrix::<R, SS, SS>::one() - &k_gain * h;
+        let covariance = match covariance_method {
+            CoverianceUpdateMethod::JosephForm => {
+                // The prior error is correlated with the observation noise, so
+                // the Joseph form has the additional cross terms.
+                let r = self.observation_model.observation_noise_covariance();
+                let cross = &one_minus_kh * &self.cross_covariance * k_gain.transpose();
+                &one_minus_kh * prior.covariance() * one_minus_kh.transpose()
+                    + &k_gain * r * k_gain.transpose()
+                    - &cross
+                    - cross.transpose()
+            }
+            CoverianceUpdateMethod::OptimalKalman => {
+                prior.covariance() - &k_gain * pht_s.transpose()
+            }
+            CoverianceUpdateMethod::OptimalKalmanForcedSymmetric => {
+                let covariance1 = prior.covariance() - &k_gain * pht_s.transpose();
+                let half: R = na::convert(0.5);
+                (&covariance1 + &covariance1.transpose()) * half
+            }
+        };
+        Ok(StateAndCovariance::new(state, covariance))
+    }
+
+    /// Perform Kalman prediction and update steps with default values
+    ///
+    /// If any component of the observation is NaN (not a number), the prior
+    /// is returned as the posterior.
+    pub fn step(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        self.step_with_options(
+            previous_estimate,
+            observation,
+            CoverianceUpdateMethod::OptimalKalmanForcedSymmetric,
+        )
+    }
+
+    /// Perform Kalman prediction and update steps with the specified
+    /// covariance update method.
+    pub fn step_with_options(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_update_method: CoverianceUpdateMethod,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let prior = self.transition_model.predict(previous_estimate);
+        if is_missing(observation)? {
+            Ok(prior)
+        } else {
+            self.update(&prior, observation, covariance_update_method)
+        }
+    }
+
+    /// Kalman filter
+    ///
+    /// Operates on entire time series and returns a vector of state estimates.
+    /// If any observation has a NaN component, it is treated as missing.
+    #[cfg(feature = "std")]
+    pub fn filter(
+        &self,
+        initial_estimate: &StateAndCovariance<R, SS>,
+        observations: &[OVector<R, OS>],
+    ) -> Result<Vec<StateAndCovariance<R, SS>>, Error> {
+        let mut previous_estimate = initial_estimate.clone();
+        let mut state_estimates = Vec::with_capacity(observations.len());
+        for (index, observation) in observations.iter().enumerate() {
+            previous_estimate = self
+                .step(&previous_estimate, observation)
+                .map_err(|e| e.with_index(index))?;
+            state_estimates.push(previous_estimate.clone());
+        }
+        Ok(state_estimates)
+    }
+
+    /// Smoother for correlated noise
+    ///
+    /// Because the observation `z_{k+1}` depends on the process noise `w_k`,
+    /// the state `x_k` is not independent of `z_{k+1}` given `x_{k+1}` and the
+    /// Rauch-Tung-Striebel smoother does not apply directly. Instead, each
+    /// backward step conditions on `x_{k+1}` together with all observations up
+    /// to and including `z_{k+1}`, using the one-step lag estimate of `x_k`
+    /// and its cross-covariance with `x_{k+1}`. With `S` zero, this gives the
+    /// same result as the Rauch-Tung-Striebel smoother.
+    ///
+    /// If any observation has a NaN component, it is treated as missing.
+    #[cfg(feature = "std")]
+    pub fn smooth(
+        &self,
+        initial_estimate: &StateAndCovariance<R, SS>,
+        observations: &[OVector<R, OS>],
+    ) -> Result<Vec<StateAndCovariance<R, SS>>, Error> {
+        let forward_results = self.filter(initial_estimate, observations)?;
+        let mut smoothed = forward_results.clone();
+        for k in (0..forward_results.len().saturating_sub(1)).rev() {
+            smoothed[k] = self
+                .smooth_step(
+                    &smoothed[k + 1],
+                    &forward_results[k + 1],
+                    &forward_results[k],
+                    &observations[k + 1],
+                )
+                .map_err(|e| e.with_index(k))?;
+        }
+        Ok(smoothed)
+    }
+
+    #[cfg(feature = "std")]
+    fn smooth_step(
+        &self,
+        smooth_future: &StateAndCovariance<R, SS>,
+        filt_future: &StateAndCovariance<R, SS>,
+        filt: &StateAndCovariance<R, SS>,
+        future_observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        // covariance between x_k and x_{k+1} given observations up to z_k
+        let pft = filt.covariance() * self.transition_model.transition_model_transpose();
+
+        // Condition x_k on z_{k+1} to get the one-step lag estimate and the
+        // cross-covariance given observations up to z_{k+1}.
+        let (lag, cross) = if is_missing(future_observation)? {
+            (filt.clone(), pft)
+        } else {
+            let prior = self.transition_model.predict(filt);
+            let innovation = self.innovation(&prior, future_observation)?;
+            let lag_gain: OMatrix<R, SS, OS> = &pft
+                * self.observation_model.observation_matrix_transpose()
+                * innovation.covariance_inverse();
+            let state = filt.state() + &lag_gain * innovation.residual();
+            let covariance =
+                filt.covariance() - &lag_gain * innovation.covariance() * lag_gain.transpose();
+            let cross = &pft - &lag_gain * self.state_observation_covariance(&prior).transpose();
+            (StateAndCovariance::new(state, covariance), cross)
+        };
+
+        let v_chol = match na::linalg::Cholesky::new(filt_future.covariance().clone()) {
+            Some(v) => v,
+            None => {
+                return Err(
+                    Error::from(crate::ErrorKind::SmootherPriorNotPositiveDefinite)
+                        .with_condition_estimate(crate::repair::condition_estimate(
+                            filt_future.covariance(),
+                        )),
+                );
+            }
+        };
+        let j = cross * v_chol.inverse();
+
+        let state = lag.state() + &j * (smooth_future.state() - filt_future.state());
+        let covar_residuals = smooth_future.covariance() - filt_future.covariance();
+        let covariance = lag.covariance() + &j * (covar_residuals * j.transpose());
+        Ok(StateAndCovariance::new(state, covariance))
+    }
+}
+
+#[test]
+fn test_correlated_noise() {
+    use crate::{KalmanFilterNoControl, LinearObservationModel, LinearTransitionModel};
+    use na::{U1, U11, U2, U3, U9};
+
+    // Constant velocity model observing position, with process and observation
+    // noise driven by the same source.
+    let f = OMatrix::<f64, U2, U2>::new(1.0, 1.0, 0.0, 1.0);
+    let q = OMatrix::<f64, U2, U2>::new(1.0 / 3.0, 0.5, 0.5, 1.0);
+    let h = OMatrix::<f64, U1, U2>::new(1.0, 0.0);
+    let r = OMatrix::<f64, U1, U1>::new(1.0);
+    let s = OMatrix::<f64, U2, U1>::new(0.3, 0.2);
+    let transition_model = LinearTransitionModel::new(f, q);
+    let observation_model = LinearObservationModel::new(h, r);
+    let initial = StateAndCovariance::new(
+        OVector::<f64, U2>::new(1.0, -0.5),
+        OMatrix::<f64, U2, U2>::new(2.0, 0.3, 0.3, 0.5),
+    );
+    let observations = vec![
+        OVector::<f64, U1>::new(0.8),
+        OVector::<f64, U1>::new(-0.4),
+        OVector::<f64, U1>::new(0.1),
+    ];
+
+    // Batch solution: write the states x_1..x_3 and observations z_1..z_3 as
+    // linear functions of x_0, (w_0, v_1), (w_1, v_2) and (w_2, v_3), then
+    // condition the states on the observations.
+    let mut mean_u = OVector::<f64, U11>::zeros();
+    mean_u.fixed_rows_mut::<2>(0).copy_from(initial.state());
+    let mut cov_u = OMatrix::<f64, U11, U11>::zeros();
+    cov_u
+        .fixed_slice_mut::<2, 2>(0, 0)
+        .copy_from(initial.covariance());
+    let mut map = OMatrix::<f64, U9, U11>::zeros();
+    let mut a = OMatrix::<f64, U2, U11>::zeros();
+    a.fixed_slice_mut::<2, 2>(0, 0)
+        .copy_from(&OMatrix::<f64, U2, U2>::identity());
+    for k in 0..3 {
+        let col = 2 + 3 * k;
+        let mut block = OMatrix::<f64, U3, U3>::zeros();
+        block.fixed_slice_mut::<2, 2>(0, 0).copy_from(&q);
+        block.fixed_slice_mut::<2, 1>(0, 2).copy_from(&s);
+        block
+            .fixed_slice_mut::<1, 2>(2, 0)
+            .copy_from(&s.transpose());
+        block.fixed_slice_mut::<1, 1>(2, 2).copy_from(&r);
+        cov_u.fixed_slice_mut::<3, 3>(col, col).copy_from(&block);
+
+        a = f * a;
+        a[(0, col)] += 1.0;
+        a[(1, col + 1)] += 1.0;
+        map.fixed_slice_mut::<2, 11>(2 * k, 0).copy_from(&a);
+        let mut z = h * a;
+        z[(0, col + 2)] += 1.0;
+        map.fixed_slice_mut::<1, 11>(6 + k, 0).copy_from(&z);
+    }
+    let mean = map * mean_u;
+    let cov = map * cov_u * map.transpose();
+    let cov_xz = cov.fixed_slice::<6, 3>(0, 6);
+    let cov_zz_inv = cov.fixed_slice::<3, 3>(6, 6).try_inverse().unwrap();
+    let z = OVector::<f64, U3>::from_fn(|i, _| observations[i][0]);
+    let gain = cov_xz * cov_zz_inv;
+    let batch_mean = mean.fixed_rows::<6>(0) + gain * (z - mean.fixed_rows::<3>(6));
+    let batch_cov = cov.fixed_slice::<6, 6>(0, 0) - gain * cov_xz.transpose();
+
+    let kf = CorrelatedNoiseKalmanFilter::new(&transition_model, &observation_model, s);
+    let filtered = kf.filter(&initial, &observations).unwrap();
+    let smoothed = kf.smooth(&initial, &observations).unwrap();
+    assert_eq!(smoothed.len(), 3);
+    for (k, estimate) in smoothed.iter().enumerate() {
+        let batch_state = batch_mean.fixed_rows::<2>(2 * k);
+        let batch_covariance = batch_cov.fixed_slice::<2, 2>(2 * k, 2 * k);
+        assert!((estimate.state() - batch_state).abs().max() < 1e-9);
+        assert!((estimate.covariance() - batch_covariance).abs().max() < 1e-9);
+    }
+    assert!(
+        (filtered[2].state() - batch_mean.fixed_rows::<2>(4))
+            .abs()
+            .max()
+            < 1e-9
+    );
+
+    // The Joseph form gives the same covariance with the optimal gain.
+    let prior = transition_model.predict(&initial);
+    let joseph = kf
+        .update(&prior, &observations[0], CoverianceUpdateMethod::JosephForm)
+        .unwrap();
+    assert!((joseph.covariance() - filtered[0].covariance()).abs().max() < 1e-12);
+
+    // Without correlation, this is the standard filter and smoother.
+    let uncorrelated =
+        CorrelatedNoiseKalmanFilter::new(&transition_model, &observation_model, na::zero());
+    let standard = KalmanFilterNoControl::new(&transition_model, &observation_model);
+    let mut observations = observations;
+    observations[1][0] = f64::NAN;
+    let expected = standard.smooth(&initial, &observations).unwrap();
+    let actual = uncorrelated.smooth(&initial, &observations).unwrap();
+    for (e, a) in expected.iter().zip(actual.iter()) {
+        assert!((e.state() - a.state()).abs().max() < 1e-9);
+        assert!((e.covariance() - a.covariance()).abs().max() < 1e-9);
+    }
+
+    // An infinite observation is an error at its index.
+    observations[2][0] = f64::INFINITY;
+    let err = kf.smooth(&initial, &observations).unwrap_err();
+    assert_eq!(err.kind(), &crate::ErrorKind::NonFiniteInput);
+    assert_eq!(err.index(), Some(2));
+}
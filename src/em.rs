@@ -0,0 +1,521 @@
+//! Expectation-maximization (EM) learning of linear Gaussian model parameters
+//!
+//! Given one or more recorded time series of observations, the functions here
+//! alternate between running the Kalman filter and Rauch-Tung-Striebel
+//! smoother with the current parameters (the E step) and re-estimating the
+//! parameters in closed form from the smoothed sufficient statistics (the M
+//! step). Each iteration does not decrease the likelihood of the
+//! observations.
+//!
+//! The method follows R. H. Shumway and D. S. Stoffer, "An approach to time
+//! series smoothing and forecasting using the EM algorithm", Journal of Time
+//! Series Analysis, 1982.
+//!
+//! As elsewhere in this crate, the initial estimate describes the state one
+//! time step before the first observation.
+
+use log::trace;
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, RealField};
+use na::{OMatrix, OVector};
+use nalgebra as na;
+
+use crate::repair::condition_estimate;
+use crate::{
+    is_missing, is_nan, outer_product, CoverianceUpdateMethod, Error, ErrorKind,
+    LinearObservationModel, LinearTransitionModel, ObservationModelLinear, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// Specifies how a covariance matrix is re-estimated
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CovarianceConstraint {
+    /// Re-estimate the full (symmetric) covariance matrix.
+    Full,
+    /// Re-estimate only the diagonal. Off-diagonal elements are zero.
+    Diagonal,
+    /// Keep the covariance matrix at its initial value.
+    Fixed,
+}
+
+/// Options for [`expectation_maximization`](fn.expectation_maximization.html)
+#[derive(Debug, Clone)]
+pub struct EmOptions<R: RealField> {
+    /// Maximum number of M steps.
+    pub max_iterations: usize,
+    /// Convergence is declared when the log-likelihood changes by less than
+    /// this amount between iterations.
+    pub tolerance: R,
+    /// How to re-estimate the transition noise covariance `Q`.
+    pub transition_noise_covariance: CovarianceConstraint,
+    /// How to re-estimate the observation noise covariance `R`.
+    pub observation_noise_covariance: CovarianceConstraint,
+    /// How to re-estimate the covariance of the initial estimate.
+    pub initial_covariance: CovarianceConstraint,
+    /// Whether to re-estimate the mean of the initial estimate.
+    pub estimate_initial_state: bool,
+    /// Whether to re-estimate the state transition model `F`.
+    pub estimate_transition_model: bool,
+    /// Whether to re-estimate the observation matrix `H`.
+    pub estimate_observation_matrix: bool,
+}
+
+impl<R: RealField> Default for EmOptions<R> {
+    fn default() -> Self {
+        Self {
+            max_iterations: 100,
+            tolerance: na::convert(1e-6),
+            transition_noise_covariance: CovarianceConstraint::Full,
+            observation_noise_covariance: CovarianceConstraint::Full,
+            initial_covariance: CovarianceConstraint::Full,
+            estimate_initial_state: true,
+            estimate_transition_model: false,
+            estimate_observation_matrix: false,
+        }
+    }
+}
+
+/// The result of [`expectation_maximization`](fn.expectation_maximization.html)
+#[derive(Debug, Clone)]
+pub struct EmResult<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    /// The estimated transition model.
+    pub transition_model: LinearTransitionModel<R, SS>,
+    /// The estimated observation model.
+    pub observation_model: LinearObservationModel<R, SS, OS>,
+    /// The estimated initial estimate.
+    pub initial_estimate: StateAndCovariance<R, SS>,
+    /// The log-likelihood of the observations given the estimated parameters.
+    pub log_likelihood: R,
+    /// The log-likelihood at each iteration, starting with the initial parameters.
+    pub log_likelihood_history: Vec<R>,
+    /// The number of M steps performed.
+    pub iterations: usize,
+    /// Whether the log-likelihood converged within the tolerance.
+    pub converged: bool,
+}
+
+struct Parameters<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    transition_model: LinearTransitionModel<R, SS>,
+    observation_model: LinearObservationModel<R, SS, OS>,
+    initial_estimate: StateAndCovariance<R, SS>,
+}
+
+/// Sufficient statistics accumulated over all sequences in the E step
+struct SufficientStatistics<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    /// sum of E[x_t x_t'] for t = 1..T
+    s11: OMatrix<R, SS, SS>,
+    /// sum of E[x_t x_{t-1}'] for t = 1..T
+    s10: OMatrix<R, SS, SS>,
+    /// sum of E[x_{t-1} x_{t-1}'] for t = 1..T
+    s00: OMatrix<R, SS, SS>,
+    n_transitions: usize,
+    /// sum of y_t y_t' for observed t
+    syy: OMatrix<R, OS, OS>,
+    /// sum of y_t E[x_t]' for observed t
+    syx: OMatrix<R, OS, SS>,
+    /// sum of E[x_t x_t'] for observed t
+    sxx: OMatrix<R, SS, SS>,
+    n_observations: usize,
+    /// smoothed initial estimate of each sequence
+    initial: Vec<StateAndCovariance<R, SS>>,
+}
+
+/// Learn model parameters by expectation-maximization
+///
+/// Starting from the given models and initial estimate, re-estimate the
+/// transition noise covariance `Q`, the observation noise covariance `R`, the
+/// initial estimate and, optionally, the transition model `F` and observation
+/// matrix `H` until the log-likelihood of the observations converges. All
+/// sequences share the same parameters, including the initial estimate.
+///
+/// The observation model is treated as linear (observations are predicted as
+/// `H*x`). If any observation has a NaN component, it is treated as missing.
+pub fn expectation_maximization<R, SS, OS>(
+    transition_model: &dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &dyn ObservationModelLinear<R, SS, OS>,
+    initial_estimate: &StateAndCovariance<R, SS>,
+    sequences: &[&[OVector<R, OS>]],
+    options: &EmOptions<R>,
+) -> Result<EmResult<R, SS, OS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    let mut params = Parameters {
+        transition_model: LinearTransitionModel::from_model(transition_model),
+        observation_model: LinearObservationModel::from_model(observation_model),
+        initial_estimate: initial_estimate.clone(),
+    };
+
+    let mut log_likelihood_history = Vec::new();
+    let mut iterations = 0;
+    let mut converged = false;
+    loop {
+        let (log_likelihood, stats) = expectation_step(&params, sequences)?;
+        trace!(
+            "EM iteration {}: log-likelihood {}",
+            iterations,
+            log_likelihood
+        );
+        if let Some(previous) = log_likelihood_history.last() {
+            if (log_likelihood - *previous).abs() <= options.tolerance {
+                converged = true;
+            }
+        }
+        log_likelihood_history.push(log_likelihood);
+        if converged || iterations >= options.max_iterations {
+            break;
+        }
+        params = maximization_step(&params, &stats, options)?;
+        iterations += 1;
+    }
+
+    Ok(EmResult {
+        transition_model: params.transition_model,
+        observation_model: params.observation_model,
+        initial_estimate: params.initial_estimate,
+        log_likelihood: *log_likelihood_history.last().unwrap(),
+        log_likelihood_history,
+        iterations,
+        converged,
+    })
+}
+
+fn expectation_step<R, SS, OS>(
+    params: &Parameters<R, SS, OS>,
+    sequences: &[&[OVector<R, OS>]],
+) -> Result<(R, SufficientStatistics<R, SS, OS>), Error>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    let mut stats = SufficientStatistics {
+        s11: OMatrix::<R, SS, SS>::zeros(),
+        s10: OMatrix::<R, SS, SS>::zeros(),
+        s00: OMatrix::<R, SS, SS>::zeros(),
+        n_transitions: 0,
+        syy: OMatrix::<R, OS, OS>::zeros(),
+        syx: OMatrix::<R, OS, SS>::zeros(),
+        sxx: OMatrix::<R, SS, SS>::zeros(),
+        n_observations: 0,
+        initial: Vec::with_capacity(sequences.len()),
+    };
+    let mut log_likelihood = R::zero();
+
+    let ft = params.transition_model.transition_model_transpose();
+
+    for observations in sequences.iter() {
+        // Forward pass. Index 0 is the initial estimate, index t is the
+        // estimate after observation t-1.
+        let mut priors = Vec::with_capacity(observations.len());
+        let mut filtered = Vec::with_capacity(observations.len() + 1);
+        filtered.push(params.initial_estimate.clone());
+        for (t, observation) in observations.iter().enumerate() {
+            let prior = params.transition_model.predict(filtered.last().unwrap());
+            let posterior = if is_missing(observation).map_err(|e| e.with_index(t))? {
+                prior.clone()
+            } else {
+                let innovation = params
+                    .observation_model
+                    .innovation(&prior, observation)
+                    .map_err(|e| e.with_index(t))?;
+                log_likelihood += innovation.log_likelihood();
+                params
+                    .observation_model
+                    .update(
+                        &prior,
+                        observation,
+                        CoverianceUpdateMethod::OptimalKalmanForcedSymmetric,
+                    )
+                    .map_err(|e| e.with_index(t))?
+            };
+            priors.push(prior);
+            filtered.push(posterior);
+        }
+
+        // Backward pass, also computing the lag-one covariances
+        // Cov(x_{t+1}, x_t) = P_{t+1|T} J_t'.
+        let n = observations.len();
+        let mut smoothed = filtered.clone();
+        let mut lag_one = Vec::with_capacity(n);
+        for t in (0..n).rev() {
+            let prior = &priors[t];
+            let prior_inv = match na::linalg::Cholesky::new(prior.covariance().clone()) {
+                Some(v) => v.inverse(),
+                None => {
+                    return Err(Error::from(ErrorKind::SmootherPriorNotPositiveDefinite)
+                        .with_index(t)
+                        .with_condition_estimate(condition_estimate(prior.covariance())));
+                }
+            };
+            let j = filtered[t].covariance() * ft * prior_inv;
+            let state = filtered[t].state() + &j * (smoothed[t + 1].state() - prior.state());
+            let covariance = filtered[t].covariance()
+                + &j * (smoothed[t + 1].covariance() - prior.covariance()) * j.transpose();
+            lag_one.push(smoothed[t + 1].covariance() * j.transpose());
+            smoothed[t] = StateAndCovariance::new(state, covariance);
+        }
+        lag_one.reverse();
+
+        for t in 1..=n {
+            let x1 = smoothed[t].state();
+            let x0 = smoothed[t - 1].state();
+            let e11 = smoothed[t].covariance() + outer_product(x1, x1);
+            stats.s10 += &lag_one[t - 1] + outer_product(x1, x0);
+            stats.s00 += smoothed[t - 1].covariance() + outer_product(x0, x0);
+            let observation = &observations[t - 1];
+            if !observation.iter().any(|x| is_nan(*x)) {
+                stats.syy += outer_product(observation, observation);
+                stats.syx += outer_product(observation, x1);
+                stats.sxx += &e11;
+                stats.n_observations += 1;
+            }
+            stats.s11 += e11;
+        }
+        stats.n_transitions += n;
+        stats.initial.push(smoothed[0].clone());
+    }
+    Ok((log_likelihood, stats))
+}
+
+fn maximization_step<R, SS, OS>(
+    params: &Parameters<R, SS, OS>,
+    stats: &SufficientStatistics<R, SS, OS>,
+    options: &EmOptions<R>,
+) -> Result<Parameters<R, SS, OS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    // Transition model and its noise.
+    let (f, q) = if stats.n_transitions > 0 {
+        let f = if options.estimate_transition_model {
+            &stats.s10 * cholesky_inverse(stats.s00.clone())?
+        } else {
+            params.transition_model.transition_model().clone()
+        };
+        let n: R = na::convert(stats.n_transitions as f64);
+        let q = (&stats.s11 - &f * stats.s10.transpose() - &stats.s10 * f.transpose()
+            + &f * &stats.s00 * f.transpose())
+            / n;
+        let q = constrain(
+            q,
+            params.transition_model.transition_noise_covariance(),
+            options.transition_noise_covariance,
+        );
+        (f, q)
+    } else {
+        (
+            params.transition_model.transition_model().clone(),
+            params
+                .transition_model
+                .transition_noise_covariance()
+                .clone(),
+        )
+    };
+
+    // Observation model and its noise.
+    let (h, r) = if stats.n_observations > 0 {
+        let h = if options.estimate_observation_matrix {
+            &stats.syx * cholesky_inverse(stats.sxx.clone())?
+        } else {
+            params.observation_model.observation_matrix().clone()
+        };
+        let n: R = na::convert(stats.n_observations as f64);
+        let r = (&stats.syy - &h * stats.syx.transpose() - &stats.syx * h.transpose()
+            + &h * &stats.sxx * h.transpose())
+            / n;
+        let r = constrain(
+            r,
+            params.observation_model.observation_noise_covariance(),
+            options.observation_noise_covariance,
+        );
+        (h, r)
+    } else {
+        (
+            params.observation_model.observation_matrix().clone(),
+            params
+                .observation_model
+                .observation_noise_covariance()
+                .clone(),
+        )
+    };
+
+    // Initial estimate.
+    let n_sequences: R = na::convert(stats.initial.len() as f64);
+    let initial_state = if options.estimate_initial_state && !stats.initial.is_empty() {
+        stats
+            .initial
+            .iter()
+            .fold(OVector::<R, SS>::zeros(), |acc, x| acc + x.state())
+            / n_sequences
+    } else {
+        params.initial_estimate.state().clone()
+    };
+    let initial_covariance = if stats.initial.is_empty() {
+        params.initial_estimate.covariance().clone()
+    } else {
+        let p0 = stats
+            .initial
+            .iter()
+            .fold(OMatrix::<R, SS, SS>::zeros(), |acc, x| {
+                let d = x.state() - &initial_state;
+                acc + x.covariance() + outer_product(&d, &d)
+            })
+            / n_sequences;
+        constrain(
+            p0,
+            params.initial_estimate.covariance(),
+            options.initial_covariance,
+        )
+    };
+
+    Ok(Parameters {
+        transition_model: LinearTransitionModel::new(f, q),
+        observation_model: LinearObservationModel::new(h, r),
+        initial_estimate: StateAndCovariance::new(initial_state, initial_covariance),
+    })
+}
+
+/// Invert a matrix of second moments in the M step.
+fn cholesky_inverse<R, D>(m: OMatrix<R, D, D>) -> Result<OMatrix<R, D, D>, Error>
+where
+    R: RealField,
+    D: DimName,
+    DefaultAllocator: Allocator<R, D, D>,
+    DefaultAllocator: Allocator<R, D>,
+{
+    match na::linalg::Cholesky::new(m.clone()) {
+        Some(v) => Ok(v.inverse()),
+        None => Err(Error::from(ErrorKind::CovarianceNotPositiveSemiDefinite)
+            .with_condition_estimate(condition_estimate(&m))),
+    }
+}
+
+/// Apply a `CovarianceConstraint` to a newly estimated covariance matrix.
+fn constrain<R, D>(
+    estimated: OMatrix<R, D, D>,
+    previous: &OMatrix<R, D, D>,
+    constraint: CovarianceConstraint,
+) -> OMatrix<R, D, D>
+where
+    R: RealField,
+    D: DimName,
+    DefaultAllocator: Allocator<R, D, D>,
+    DefaultAllocator: Allocator<R, D>,
+{
+    match constraint {
+        CovarianceConstraint::Full => {
+            let half: R = na::convert(0.5);
+            (&estimated + estimated.transpose()) * half
+        }
+        CovarianceConstraint::Diagonal => OMatrix::<R, D, D>::from_diagonal(&estimated.diagonal()),
+        CovarianceConstraint::Fixed => previous.clone(),
+    }
+}
+
+#[test]
+fn test_em_recovers_noise_variances() {
+    use na::U1;
+    use rand_core::SeedableRng;
+
+    // A scalar random walk observed with noise.
+    let true_q: f64 = 0.5;
+    let true_r: f64 = 2.0;
+    let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(12345);
+    let mut normal = || crate::particle::standard_normal::<f64>(&mut rng);
+    let mut x = 0.0;
+    let mut observations = Vec::new();
+    for _ in 0..1000 {
+        x += true_q.sqrt() * normal();
+        observations.push(OVector::<f64, U1>::new(x + true_r.sqrt() * normal()));
+    }
+
+    let transition_model = LinearTransitionModel::<f64, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(5.0),
+    );
+    let observation_model = LinearObservationModel::<f64, U1, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(0.1),
+    );
+    let initial_estimate = StateAndCovariance::new(
+        OVector::<f64, U1>::new(0.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    let options = EmOptions {
+        tolerance: 1e-4,
+        ..Default::default()
+    };
+    let result = expectation_maximization(
+        &transition_model,
+        &observation_model,
+        &initial_estimate,
+        &[&observations],
+        &options,
+    )
+    .unwrap();
+
+    // The likelihood must never decrease.
+    for pair in result.log_likelihood_history.windows(2) {
+        assert!(pair[1] >= pair[0] - 1e-6);
+    }
+    let q = result.transition_model.transition_noise_covariance()[(0, 0)];
+    let r = result.observation_model.observation_noise_covariance()[(0, 0)];
+    assert!((q - true_q).abs() < 0.25, "q = {}", q);
+    assert!((r - true_r).abs() < 0.5, "r = {}", r);
+}
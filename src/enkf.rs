@@ -0,0 +1,334 @@
+//! Ensemble Kalman filter
+//!
+//! When the state vector is large, propagating the full state covariance is
+//! too expensive. The [`EnsembleKalmanFilter`](struct.EnsembleKalmanFilter.html)
+//! here instead represents the uncertainty by an ensemble of state vectors and
+//! computes the covariances needed by the update from the ensemble anomalies.
+//! The state covariance itself is only computed when a summary is requested.
+//!
+//! This is the stochastic ("perturbed observations") formulation. See G.
+//! Evensen, "The Ensemble Kalman Filter: theoretical formulation and practical
+//! implementation", Ocean Dynamics, 2003.
+
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+use rand_core::RngCore;
+
+use crate::particle::{standard_normal, TransitionSampler};
+use crate::{
+    is_missing, outer_product, Error, ErrorKind, ObservationModelLinear, StateAndCovariance,
+};
+
+/// Weights for covariance localization
+///
+/// The covariances estimated from a small ensemble contain spurious
+/// correlations between distant variables. Localization multiplies them,
+/// element by element, by weights which decay with distance, such as those of
+/// [`gaspari_cohn`](fn.gaspari_cohn.html).
+#[derive(Debug, Clone)]
+pub struct Localization<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    /// Weights of the covariance between state and observation components.
+    pub state_observation: OMatrix<R, SS, OS>,
+    /// Weights of the covariance between observation components.
+    pub observation_observation: OMatrix<R, OS, OS>,
+}
+
+/// The Gaspari-Cohn localization function
+///
+/// This fifth-order piecewise rational function approximates a Gaussian of
+/// the distance, but has compact support: it is one at zero distance and zero
+/// beyond twice `half_width`. See G. Gaspari and S. E. Cohn, "Construction of
+/// correlation functions in two and three dimensions", Quarterly Journal of the
+/// Royal Meteorological Society, 1999.
+pub fn gaspari_cohn<R: RealField>(distance: R, half_width: R) -> R {
+    let r = distance.abs() / half_width;
+    let c = |x: f64| -> R { na::convert(x) };
+    let r2 = r * r;
+    let r3 = r2 * r;
+    let r4 = r3 * r;
+    let r5 = r4 * r;
+    if r <= R::one() {
+        -r5 * c(0.25) + r4 * c(0.5) + r3 * c(0.625) - r2 * c(5.0 / 3.0) + R::one()
+    } else if r <= c(2.0) {
+        r5 * c(1.0 / 12.0) - r4 * c(0.5) + r3 * c(0.625) + r2 * c(5.0 / 3.0) - r * c(5.0) + c(4.0)
+            - c(2.0 / 3.0) / r
+    } else {
+        R::zero()
+    }
+}
+
+/// Options for the [`EnsembleKalmanFilter`](struct.EnsembleKalmanFilter.html)
+#[derive(Debug, Clone)]
+pub struct EnsembleOptions<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    /// Multiplicative covariance inflation applied to the forecast ensemble
+    /// before each analysis. The anomalies are scaled by the square root of
+    /// this factor, so one means no inflation.
+    pub inflation: R,
+    /// Optional covariance localization.
+    pub localization: Option<Localization<R, SS, OS>>,
+}
+
+impl<R, SS, OS> Default for EnsembleOptions<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    fn default() -> Self {
+        Self {
+            inflation: R::one(),
+            localization: None,
+        }
+    }
+}
+
+/// A stochastic ensemble Kalman filter (EnKF)
+///
+/// The forecast propagates each member through a user-supplied
+/// [`TransitionSampler`](../particle/trait.TransitionSampler.html), which
+/// should add process noise. The analysis uses the observation model's
+/// `evaluate` to compute the forecast observation of each member and its
+/// observation noise covariance to perturb the observation.
+pub struct EnsembleKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    transition: &'a dyn TransitionSampler<R, SS>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    options: EnsembleOptions<R, SS, OS>,
+    members: Vec<OVector<R, SS>>,
+}
+
+impl<'a, R, SS, OS> EnsembleKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `EnsembleKalmanFilter` struct.
+    ///
+    /// At least two ensemble members are required.
+    pub fn new(
+        transition: &'a dyn TransitionSampler<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        members: Vec<OVector<R, SS>>,
+        options: EnsembleOptions<R, SS, OS>,
+    ) -> Self {
+        assert!(members.len() >= 2);
+        Self {
+            transition,
+            observation_model,
+            options,
+            members,
+        }
+    }
+
+    /// Get the ensemble members.
+    #[inline]
+    pub fn members(&self) -> &[OVector<R, SS>] {
+        &self.members
+    }
+
+    /// Perform the forecast and analysis steps.
+    ///
+    /// If any component of the observation is NaN (not a number), the
+    /// observation is treated as missing and only the forecast is performed.
+    pub fn step(
+        &mut self,
+        observation: &OVector<R, OS>,
+        rng: &mut dyn RngCore,
+    ) -> Result<(), Error> {
+        self.forecast(rng);
+        if is_missing(observation)? {
+            Ok(())
+        } else {
+            self.analysis(observation, rng)
+        }
+    }
+
+    /// Propagate each member through the transition sampler.
+    pub fn forecast(&mut self, rng: &mut dyn RngCore) {
+        for member in self.members.iter_mut() {
+            *member = self.transition.sample(member, rng);
+        }
+    }
+
+    /// Update the ensemble with an observation.
+    pub fn analysis(
+        &mut self,
+        observation: &OVector<R, OS>,
+        rng: &mut dyn RngCore,
+    ) -> Result<(), Error> {
+        let n: R = na::convert(self.members.len() as f64);
+        let n_minus_one = n - R::one();
+
+        // inflate the forecast anomalies
+        let mean = self.mean();
+        if self.options.inflation != R::one() {
+            let scale = self.options.inflation.sqrt();
+            for member in self.members.iter_mut() {
+                *member = &mean + (&*member - &mean) * scale;
+            }
+        }
+
+        // forecast observations and their anomalies
+        let predicted: Vec<OVector<R, OS>> = self
+            .members
+            .iter()
+            .map(|m| self.observation_model.evaluate(m))
+            .collect();
+        let mut predicted_mean = OVector::<R, OS>::zeros();
+        for y in predicted.iter() {
+            predicted_mean += y;
+        }
+        predicted_mean /= n;
+
+        let mut pht = OMatrix::<R, SS, OS>::zeros();
+        let mut hpht = OMatrix::<R, OS, OS>::zeros();
+        for (x, y) in self.members.iter().zip(predicted.iter()) {
+            let dx = x - &mean;
+            let dy = y - &predicted_mean;
+            pht += outer_product(&dx, &dy);
+            hpht += outer_product(&dy, &dy);
+        }
+        pht /= n_minus_one;
+        hpht /= n_minus_one;
+        if let Some(localization) = &self.options.localization {
+            pht.component_mul_assign(&localization.state_observation);
+            hpht.component_mul_assign(&localization.observation_observation);
+        }
+
+        let r = self.observation_model.observation_noise_covariance();
+        let s = hpht + r;
+        let s_chol = na::linalg::Cholesky::new(s.clone()).ok_or_else(|| {
+            Error::from(ErrorKind::InnovationCovarianceNotPositiveDefinite)
+                .with_condition_estimate(crate::repair::condition_estimate(&s))
+        })?;
+        let r_chol = na::linalg::Cholesky::new(r.clone())
+            .ok_or(ErrorKind::CovarianceNotPositiveSemiDefinite)?;
+        let r_sqrt = r_chol.l();
+
+        for (member, y) in self.members.iter_mut().zip(predicted.iter()) {
+            let noise = OVector::<R, OS>::from_fn(|_, _| standard_normal(rng));
+            let perturbed = observation + &r_sqrt * noise;
+            let innovation = perturbed - y;
+            *member += &pht * s_chol.solve(&innovation);
+        }
+        Ok(())
+    }
+
+    /// Compute the ensemble mean.
+    pub fn mean(&self) -> OVector<R, SS> {
+        let mut mean = OVector::<R, SS>::zeros();
+        for member in self.members.iter() {
+            mean += member;
+        }
+        mean / na::convert::<_, R>(self.members.len() as f64)
+    }
+
+    /// Summarize the ensemble by its mean and sample covariance.
+    pub fn estimate(&self) -> StateAndCovariance<R, SS> {
+        let mean = self.mean();
+        let mut covariance = OMatrix::<R, SS, SS>::zeros();
+        for member in self.members.iter() {
+            let d = member - &mean;
+            covariance += outer_product(&d, &d);
+        }
+        covariance /= na::convert::<_, R>(self.members.len() as f64 - 1.0);
+        StateAndCovariance::new(mean, covariance)
+    }
+}
+
+#[test]
+fn test_gaspari_cohn() {
+    assert!((gaspari_cohn(0.0f64, 1.0) - 1.0).abs() < 1e-12);
+    // continuous at the breakpoints
+    assert!((gaspari_cohn(1.0f64 - 1e-9, 1.0) - gaspari_cohn(1.0 + 1e-9, 1.0)).abs() < 1e-6);
+    assert!(gaspari_cohn(2.0f64 - 1e-9, 1.0).abs() < 1e-6);
+    assert_eq!(gaspari_cohn(2.5f64, 1.0), 0.0);
+    assert!(gaspari_cohn(0.5f64, 1.0) > gaspari_cohn(1.5, 1.0));
+}
+
+#[test]
+fn test_enkf_matches_kalman_filter() {
+    use crate::{KalmanFilterNoControl, LinearObservationModel, LinearTransitionModel};
+    use na::{U1, U2};
+    use rand_core::SeedableRng;
+
+    // Two uncorrelated random walks, of which only the first is observed.
+    let q: f64 = 0.1;
+    let r = 0.5;
+    let transition = move |x: &OVector<f64, U2>, rng: &mut dyn RngCore| {
+        let w = OVector::<f64, U2>::from_fn(|_, _| q.sqrt() * standard_normal::<f64>(rng));
+        x + w
+    };
+    let observation_model = LinearObservationModel::<f64, U2, U1>::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OMatrix::<f64, U1, U1>::new(r),
+    );
+    let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(0);
+    let members: Vec<_> = (0..4000)
+        .map(|_| OVector::<f64, U2>::from_fn(|_, _| standard_normal(&mut rng)))
+        .collect();
+    // Localization removes the spurious sample correlation between the
+    // unobserved and the observed component.
+    let options = EnsembleOptions {
+        inflation: 1.0,
+        localization: Some(Localization {
+            state_observation: OMatrix::<f64, U2, U1>::new(1.0, 0.0),
+            observation_observation: OMatrix::<f64, U1, U1>::new(1.0),
+        }),
+    };
+    let mut enkf = EnsembleKalmanFilter::new(&transition, &observation_model, members, options);
+
+    let transition_model = LinearTransitionModel::<f64, U2>::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::identity() * q,
+    );
+    let kf = KalmanFilterNoControl::new(&transition_model, &observation_model);
+    let mut kf_estimate = StateAndCovariance::new(
+        OVector::<f64, U2>::zeros(),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+
+    for z in [0.5, 0.8, 0.4, 1.2, 1.0, 1.5].iter() {
+        let z = OVector::<f64, U1>::new(*z);
+        enkf.step(&z, &mut rng).unwrap();
+        kf_estimate = kf.step(&kf_estimate, &z).unwrap();
+        let estimate = enkf.estimate();
+        assert!((estimate.state()[0] - kf_estimate.state()[0]).abs() < 0.05);
+        assert!((estimate.covariance()[(0, 0)] - kf_estimate.covariance()[(0, 0)]).abs() < 0.03);
+        assert!((estimate.covariance()[(1, 1)] - kf_estimate.covariance()[(1, 1)]).abs() < 0.2);
+    }
+}
@@ -1,24 +1,114 @@
+use core::fmt;
+
 /// An error
-#[derive(Debug)]
+///
+/// Besides the [`ErrorKind`](enum.ErrorKind.html), an error may carry the
+/// index of the sample (e.g. the observation in
+/// [`filter_inplace`](struct.KalmanFilterNoControl.html#method.filter_inplace))
+/// at which it occurred and an estimate of the condition number of the matrix
+/// which could not be decomposed.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub struct Error {
     kind: ErrorKind,
+    index: Option<usize>,
+    condition_estimate: Option<f64>,
+}
+
+impl Error {
+    /// Get the kind of error.
+    #[inline]
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+    /// Get the index of the sample at which the error occurred, if known.
+    #[inline]
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+    /// Get the estimated condition number of the failing matrix, if known.
+    ///
+    /// This is the ratio of the largest to the smallest absolute eigenvalue.
+    /// It is infinite for a singular matrix.
+    #[inline]
+    pub fn condition_estimate(&self) -> Option<f64> {
+        self.condition_estimate
+    }
+    /// Set the index of the sample at which the error occurred.
+    ///
+    /// An index which is already set is kept, so that the innermost index is
+    /// reported.
+    pub fn with_index(mut self, index: usize) -> Self {
+        if self.index.is_none() {
+            self.index = Some(index);
+        }
+        self
+    }
+    /// Set the estimated condition number of the failing matrix.
+    pub fn with_condition_estimate(mut self, condition_estimate: f64) -> Self {
+        self.condition_estimate = Some(condition_estimate);
+        self
+    }
 }
 
 /// The kinds of errors
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub enum ErrorKind {
     /// The covariance matrix is not positive semi-definite (or is not symmetric).
     CovarianceNotPositiveSemiDefinite,
+    /// The innovation covariance `S` of an update is not positive definite.
+    InnovationCovarianceNotPositiveDefinite,
+    /// The prior covariance in a smoother step is not positive definite.
+    SmootherPriorNotPositiveDefinite,
+    /// The state contains NaN or infinite values.
+    StateNotFinite,
+    /// The covariance matrix contains NaN or infinite values.
+    CovarianceNotFinite,
+    /// The covariance matrix is not symmetric.
+    CovarianceNotSymmetric,
+    /// An input has infinite values, or a detection has NaN values.
+    NonFiniteInput,
+    /// The lengths of inputs or outputs do not match.
+    DimensionMismatch,
+    /// All particle weights are zero or not finite.
+    DegenerateParticleWeights,
+    /// All mode probabilities are zero or not finite.
+    DegenerateModeProbabilities,
+    /// An iterative algorithm did not converge.
+    DidNotConverge,
+    /// No feasible association hypothesis was found.
+    NoFeasibleHypothesis,
 }
 
-#[cfg(feature = "std")]
-impl std::fmt::Display for ErrorKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use ErrorKind::*;
         let s = match self {
             CovarianceNotPositiveSemiDefinite => {
                 "The covariance matrix is not positive semi-definite (or is not symmetric)"
             }
+            InnovationCovarianceNotPositiveDefinite => {
+                "The innovation covariance is not positive definite"
+            }
+            SmootherPriorNotPositiveDefinite => {
+                "The prior covariance of the smoother is not positive definite"
+            }
+            StateNotFinite => "The state contains NaN or infinite values",
+            CovarianceNotFinite => "The covariance matrix contains NaN or infinite values",
+            CovarianceNotSymmetric => "The covariance matrix is not symmetric",
+            NonFiniteInput => "An input has infinite values",
+            DimensionMismatch => "The lengths of inputs or outputs do not match",
+            DegenerateParticleWeights => "All particle weights are zero or not finite",
+            DegenerateModeProbabilities => "All mode probabilities are zero or not finite",
+            DidNotConverge => "An iterative algorithm did not converge",
+            NoFeasibleHypothesis => "No feasible association hypothesis was found",
         };
         f.write_str(s)
     }
@@ -26,15 +116,26 @@ impl std::fmt::Display for ErrorKind {
 
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
-        Error { kind }
+        Error {
+            kind,
+            index: None,
+            condition_estimate: None,
+        }
     }
 }
 
 #[cfg(feature = "std")]
 impl std::error::Error for Error {}
-#[cfg(feature = "std")]
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Kalman Filter Error: {}", self.kind)
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Kalman Filter Error: {}", self.kind)?;
+        if let Some(index) = self.index {
+            write!(f, " at index {}", index)?;
+        }
+        if let Some(condition_estimate) = self.condition_estimate {
+            write!(f, " (condition estimate {:e})", condition_estimate)?;
+        }
+        Ok(())
     }
 }
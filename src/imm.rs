@@ -0,0 +1,410 @@
+//! Interacting Multiple Model (IMM) estimation
+//!
+//! When a system switches between several modes of motion (for example,
+//! cruising and maneuvering), no single transition model fits all of the
+//! data. The IMM estimator runs one Kalman filter per mode, all sharing the
+//! same state space and observation model, and tracks the probability of each
+//! mode. Mode switches are modelled as a Markov chain.
+//!
+//! Each step consists of mixing the per-mode estimates according to the mode
+//! transition probabilities, filtering with each mode's transition model,
+//! updating the mode probabilities from the likelihood of the observation
+//! under each mode, and combining the per-mode estimates into a single
+//! estimate. See Y. Bar-Shalom, X. R. Li and T. Kirubarajan, "Estimation with
+//! Applications to Tracking and Navigation", Wiley, 2001, section 11.6.6.
+//!
+//! The smoother is the approximate fixed-interval smoother of C.-J. Kim,
+//! "Dynamic linear models with Markov-switching", Journal of Econometrics,
+//! 1994, in which each pair of modes at consecutive time steps is smoothed
+//! with a Rauch-Tung-Striebel step.
+
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_missing, Error, ErrorKind, KalmanFilterNoControl, ObservationModelLinear,
+    StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// State estimate of an IMM estimator
+///
+/// This holds one estimate per mode and the probability of each mode.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "R: serde::Serialize, StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "R: serde::Deserialize<'de>, StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct ImmEstimate<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    estimates: Vec<StateAndCovariance<R, SS>>,
+    mode_probabilities: Vec<R>,
+}
+
+impl<R, SS> ImmEstimate<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Create a new `ImmEstimate` from per-mode estimates and mode probabilities.
+    ///
+    /// Panics if the number of estimates and probabilities differ.
+    pub fn new(estimates: Vec<StateAndCovariance<R, SS>>, mode_probabilities: Vec<R>) -> Self {
+        assert_eq!(estimates.len(), mode_probabilities.len());
+        Self {
+            estimates,
+            mode_probabilities,
+        }
+    }
+    /// Create a new `ImmEstimate` with the same estimate for every mode.
+    pub fn from_estimate(estimate: StateAndCovariance<R, SS>, mode_probabilities: Vec<R>) -> Self {
+        let estimates = vec![estimate; mode_probabilities.len()];
+        Self::new(estimates, mode_probabilities)
+    }
+    /// Get the estimate conditioned on each mode.
+    #[inline]
+    pub fn estimates(&self) -> &[StateAndCovariance<R, SS>] {
+        &self.estimates
+    }
+    /// Get the probability of each mode.
+    #[inline]
+    pub fn mode_probabilities(&self) -> &[R] {
+        &self.mode_probabilities
+    }
+    /// Combine the per-mode estimates into a single estimate.
+    pub fn combined(&self) -> StateAndCovariance<R, SS> {
+        StateAndCovariance::mixture(&self.mode_probabilities, &self.estimates)
+    }
+}
+
+/// An Interacting Multiple Model estimator
+pub struct InteractingMultipleModel<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+{
+    transition_models: Vec<&'a dyn TransitionModelLinearNoControl<R, SS>>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    mode_transition: Vec<Vec<R>>,
+}
+
+impl<'a, R, SS, OS> InteractingMultipleModel<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `InteractingMultipleModel` struct.
+    ///
+    /// `transition_models` has one transition model per mode. The mode
+    /// transition matrix `mode_transition` is the Markov chain of mode
+    /// switches: `mode_transition[i][j]` is the probability of switching from
+    /// mode `i` to mode `j` in one time step, so each row must sum to one.
+    ///
+    /// Panics if `mode_transition` is not square with one row per mode.
+    pub fn new(
+        transition_models: Vec<&'a dyn TransitionModelLinearNoControl<R, SS>>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        mode_transition: Vec<Vec<R>>,
+    ) -> Self {
+        assert!(!transition_models.is_empty());
+        assert_eq!(mode_transition.len(), transition_models.len());
+        for row in mode_transition.iter() {
+            assert_eq!(row.len(), transition_models.len());
+        }
+        Self {
+            transition_models,
+            observation_model,
+            mode_transition,
+        }
+    }
+
+    /// Get the number of modes.
+    #[inline]
+    pub fn n_modes(&self) -> usize {
+        self.transition_models.len()
+    }
+
+    /// Predicted mode probabilities, before accounting for the observation.
+    fn predicted_mode_probabilities(&self, mode_probabilities: &[R]) -> Vec<R> {
+        (0..self.n_modes())
+            .map(|j| {
+                mode_probabilities
+                    .iter()
+                    .zip(self.mode_transition.iter())
+                    .fold(R::zero(), |acc, (mu, row)| acc + row[j] * *mu)
+            })
+            .collect()
+    }
+
+    /// Perform the IMM mixing, filtering, mode probability update and combination steps
+    ///
+    /// If any component of the observation is NaN (not a number), it is
+    /// treated as missing: each mode performs only its prediction step and the
+    /// mode probabilities evolve only according to the mode transition matrix.
+    ///
+    /// Returns an error of kind `DimensionMismatch` if `previous_estimate` does
+    /// not have one estimate per mode, and of kind
+    /// `DegenerateModeProbabilities` if all updated mode probabilities are zero,
+    /// e.g. because all previous mode probabilities are zero.
+    pub fn step(
+        &self,
+        previous_estimate: &ImmEstimate<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<ImmEstimate<R, SS>, Error> {
+        let n = self.n_modes();
+        if previous_estimate.estimates.len() != n {
+            return Err(ErrorKind::DimensionMismatch.into());
+        }
+        let mu = &previous_estimate.mode_probabilities;
+        let predicted = self.predicted_mode_probabilities(mu);
+        let missing = is_missing(observation)?;
+
+        let mut estimates = Vec::with_capacity(n);
+        let mut log_weights = Vec::with_capacity(n);
+        for (j, (transition_model, predicted_j)) in self
+            .transition_models
+            .iter()
+            .zip(predicted.iter())
+            .enumerate()
+        {
+            // Mixing: the initial condition for mode j.
+            let mixing_weights: Vec<R> =
+                (0..n).map(|i| self.mode_transition[i][j] * mu[i]).collect();
+            let mixed = if *predicted_j > R::zero() {
+                StateAndCovariance::mixture(&mixing_weights, &previous_estimate.estimates)
+            } else {
+                previous_estimate.estimates[j].clone()
+            };
+
+            // Mode-matched filtering.
+            let prior = transition_model.predict(&mixed);
+            if missing {
+                estimates.push(prior);
+                log_weights.push(predicted_j.ln());
+            } else {
+                let innovation = self.observation_model.innovation(&prior, observation)?;
+                log_weights.push(innovation.log_likelihood() + predicted_j.ln());
+                let kf = KalmanFilterNoControl::new(*transition_model, self.observation_model);
+                estimates.push(kf.step(&mixed, observation)?);
+            }
+        }
+
+        Ok(ImmEstimate {
+            estimates,
+            mode_probabilities: normalize_log_weights(&log_weights)?,
+        })
+    }
+
+    /// IMM filter over an entire time series
+    ///
+    /// Calls [`step`](struct.InteractingMultipleModel.html#method.step) for
+    /// each observation and returns the estimate after each one.
+    pub fn filter(
+        &self,
+        initial_estimate: &ImmEstimate<R, SS>,
+        observations: &[OVector<R, OS>],
+    ) -> Result<Vec<ImmEstimate<R, SS>>, Error> {
+        let mut estimates: Vec<ImmEstimate<R, SS>> = Vec::with_capacity(observations.len());
+        for (index, observation) in observations.iter().enumerate() {
+            let previous = estimates.last().unwrap_or(initial_estimate);
+            let this_estimate = self
+                .step(previous, observation)
+                .map_err(|e| e.with_index(index))?;
+            estimates.push(this_estimate);
+        }
+        Ok(estimates)
+    }
+
+    /// IMM smoother over an entire time series
+    ///
+    /// Calls [`filter`](struct.InteractingMultipleModel.html#method.filter)
+    /// and then
+    /// [`smooth_from_filtered`](struct.InteractingMultipleModel.html#method.smooth_from_filtered).
+    pub fn smooth(
+        &self,
+        initial_estimate: &ImmEstimate<R, SS>,
+        observations: &[OVector<R, OS>],
+    ) -> Result<Vec<ImmEstimate<R, SS>>, Error> {
+        let forward_results = self.filter(initial_estimate, observations)?;
+        self.smooth_from_filtered(forward_results)
+    }
+
+    /// IMM smoother using already filtered estimates
+    ///
+    /// Returns the smoothed per-mode estimates and mode probabilities.
+    pub fn smooth_from_filtered(
+        &self,
+        mut forward_results: Vec<ImmEstimate<R, SS>>,
+    ) -> Result<Vec<ImmEstimate<R, SS>>, Error> {
+        let n = self.n_modes();
+        forward_results.reverse();
+
+        let mut smoothed_backwards: Vec<ImmEstimate<R, SS>> =
+            Vec::with_capacity(forward_results.len());
+        let mut iter = forward_results.into_iter();
+        let mut smooth_future = match iter.next() {
+            Some(last) => last,
+            None => return Ok(smoothed_backwards),
+        };
+        smoothed_backwards.push(smooth_future.clone());
+        for filt in iter {
+            let predicted = self.predicted_mode_probabilities(&filt.mode_probabilities);
+
+            // joint[i][j] is the smoothed probability of mode i now and mode j
+            // at the next time step.
+            let joint: Vec<Vec<R>> = (0..n)
+                .map(|i| {
+                    (0..n)
+                        .map(|j| {
+                            if predicted[j] > R::zero() {
+                                filt.mode_probabilities[i]
+                                    * self.mode_transition[i][j]
+                                    * smooth_future.mode_probabilities[j]
+                                    / predicted[j]
+                            } else {
+                                R::zero()
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+
+            let mut estimates = Vec::with_capacity(n);
+            let mut mode_probabilities = Vec::with_capacity(n);
+            for (i, joint_i) in joint.iter().enumerate() {
+                let probability = joint_i.iter().fold(R::zero(), |acc, x| acc + *x);
+                if probability > R::zero() {
+                    let mut pair_estimates = Vec::with_capacity(n);
+                    for j in 0..n {
+                        let kf = KalmanFilterNoControl::new(
+                            self.transition_models[j],
+                            self.observation_model,
+                        );
+                        pair_estimates
+                            .push(kf.smooth_step(&smooth_future.estimates[j], &filt.estimates[i])?);
+                    }
+                    estimates.push(StateAndCovariance::mixture(joint_i, &pair_estimates));
+                } else {
+                    estimates.push(filt.estimates[i].clone());
+                }
+                mode_probabilities.push(probability);
+            }
+            let total = mode_probabilities.iter().fold(R::zero(), |acc, x| acc + *x);
+            for p in mode_probabilities.iter_mut() {
+                *p /= total;
+            }
+
+            smooth_future = ImmEstimate {
+                estimates,
+                mode_probabilities,
+            };
+            smoothed_backwards.push(smooth_future.clone());
+        }
+
+        smoothed_backwards.reverse();
+        Ok(smoothed_backwards)
+    }
+}
+
+/// Convert log weights to probabilities which sum to one.
+///
+/// Returns an error if all weights are zero or any is not finite.
+fn normalize_log_weights<R: RealField>(log_weights: &[R]) -> Result<Vec<R>, Error> {
+    let max = log_weights
+        .iter()
+        .fold(R::min_value(), |acc, x| if *x > acc { *x } else { acc });
+    let weights: Vec<R> = log_weights.iter().map(|x| (*x - max).exp()).collect();
+    let total = weights.iter().fold(R::zero(), |acc, x| acc + *x);
+    if !(total > R::zero() && total.is_finite()) {
+        return Err(ErrorKind::DegenerateModeProbabilities.into());
+    }
+    Ok(weights.into_iter().map(|x| x / total).collect())
+}
+
+#[test]
+fn test_imm_detects_maneuver() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{OMatrix, U1};
+
+    // A scalar position which stays constant and then jumps.
+    let quiet = LinearTransitionModel::<f64, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(0.001),
+    );
+    let maneuvering = LinearTransitionModel::<f64, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(10.0),
+    );
+    let observation_model = LinearObservationModel::<f64, U1, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(0.01),
+    );
+    let imm = InteractingMultipleModel::new(
+        vec![&quiet, &maneuvering],
+        &observation_model,
+        vec![vec![0.95, 0.05], vec![0.05, 0.95]],
+    );
+
+    let mut observations: Vec<OVector<f64, U1>> = Vec::new();
+    for i in 0..40 {
+        let x = if i < 20 { 0.0 } else { 5.0 };
+        // small deterministic wiggle
+        let wiggle = if i % 2 == 0 { 0.05 } else { -0.05 };
+        observations.push(OVector::<f64, U1>::new(x + wiggle));
+    }
+    let initial = ImmEstimate::from_estimate(
+        StateAndCovariance::new(
+            OVector::<f64, U1>::new(0.0),
+            OMatrix::<f64, U1, U1>::new(1.0),
+        ),
+        vec![0.5, 0.5],
+    );
+
+    let filtered = imm.filter(&initial, &observations).unwrap();
+    assert_eq!(filtered.len(), observations.len());
+    assert!(filtered[15].mode_probabilities()[0] > 0.5);
+    assert!(filtered[20].mode_probabilities()[1] > 0.5);
+    assert!((filtered[39].combined().state()[0] - 5.0).abs() < 0.1);
+
+    let smoothed = imm.smooth_from_filtered(filtered).unwrap();
+    assert_eq!(smoothed.len(), observations.len());
+    for estimate in smoothed.iter() {
+        let total: f64 = estimate.mode_probabilities().iter().sum();
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+    assert!(smoothed[10].mode_probabilities()[0] > 0.5);
+    assert!((smoothed[10].combined().state()[0]).abs() < 0.1);
+
+    // Invalid estimates are errors rather than panics or NaN probabilities.
+    let single = ImmEstimate::from_estimate(initial.estimates()[0].clone(), vec![1.0]);
+    let err = imm.step(&single, &observations[0]).unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::DimensionMismatch);
+    let impossible = ImmEstimate::new(initial.estimates().to_vec(), vec![0.0, 0.0]);
+    let err = imm.filter(&impossible, &observations).unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::DegenerateModeProbabilities);
+    assert_eq!(err.index(), Some(0));
+}
@@ -0,0 +1,106 @@
+use na::allocator::Allocator;
+use na::{DefaultAllocator, DimName, RealField};
+use na::{OMatrix, OVector};
+use nalgebra as na;
+
+use crate::{Error, ErrorKind};
+
+/// Innovation (measurement residual) of an observation and its covariance
+///
+/// The innovation is the difference between an observation and the
+/// observation predicted from a prior state estimate. Its covariance `S` is
+/// the covariance of the predicted observation plus the observation noise
+/// covariance.
+#[derive(Debug, Clone)]
+pub struct Innovation<R, OS>
+where
+    R: RealField,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    residual: OVector<R, OS>,
+    covariance: OMatrix<R, OS, OS>,
+    covariance_inverse: OMatrix<R, OS, OS>,
+    log_determinant: R,
+}
+
+impl<R, OS> Innovation<R, OS>
+where
+    R: RealField,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    /// Create a new `Innovation` from a residual and its covariance.
+    ///
+    /// The covariance is inverted using the Cholesky decomposition. An error
+    /// is returned if it is not positive definite.
+    pub fn new(residual: OVector<R, OS>, covariance: OMatrix<R, OS, OS>) -> Result<Self, Error> {
+        let chol = match na::linalg::Cholesky::new(covariance.clone()) {
+            Some(v) => v,
+            None => {
+                return Err(
+                    Error::from(ErrorKind::InnovationCovarianceNotPositiveDefinite)
+                        .with_condition_estimate(crate::repair::condition_estimate(&covariance)),
+                );
+            }
+        };
+        let two: R = na::convert(2.0);
+        let log_determinant = chol
+            .l_dirty()
+            .diagonal()
+            .iter()
+            .fold(R::zero(), |acc, x| acc + two * x.ln());
+        let covariance_inverse = chol.inverse();
+        Ok(Self {
+            residual,
+            covariance,
+            covariance_inverse,
+            log_determinant,
+        })
+    }
+    /// Create an `Innovation` with a different residual but the same covariance.
+    ///
+    /// This avoids decomposing the covariance again when several observations
+    /// are compared against the same prior.
+    pub fn with_residual(&self, residual: OVector<R, OS>) -> Self {
+        Self {
+            residual,
+            covariance: self.covariance.clone(),
+            covariance_inverse: self.covariance_inverse.clone(),
+            log_determinant: self.log_determinant,
+        }
+    }
+    /// Get the residual (observation minus predicted observation).
+    #[inline]
+    pub fn residual(&self) -> &OVector<R, OS> {
+        &self.residual
+    }
+    /// Get the innovation covariance `S`.
+    #[inline]
+    pub fn covariance(&self) -> &OMatrix<R, OS, OS> {
+        &self.covariance
+    }
+    /// Get the inverse of the innovation covariance `S`.
+    #[inline]
+    pub fn covariance_inverse(&self) -> &OMatrix<R, OS, OS> {
+        &self.covariance_inverse
+    }
+    /// Get the natural logarithm of the determinant of `S`.
+    #[inline]
+    pub fn log_determinant(&self) -> R {
+        self.log_determinant
+    }
+    /// Squared Mahalanobis distance of the residual.
+    pub fn mahalanobis_squared(&self) -> R {
+        self.residual
+            .dot(&(&self.covariance_inverse * &self.residual))
+    }
+    /// Log-likelihood of the residual under a zero-mean Gaussian with covariance `S`.
+    pub fn log_likelihood(&self) -> R {
+        let half: R = na::convert(0.5);
+        let dim: R = na::convert(OS::dim() as f64);
+        -half * (dim * R::two_pi().ln() + self.log_determinant + self.mahalanobis_squared())
+    }
+}
@@ -79,6 +79,62 @@ pub use error::{Error, ErrorKind};
 mod state_and_covariance;
 pub use state_and_covariance::StateAndCovariance;
 
+mod innovation;
+pub use innovation::Innovation;
+
+mod linear_model;
+pub use linear_model::{LinearObservationModel, LinearTransitionModel};
+
+mod constraint;
+pub use constraint::{
+    BoxConstraint, ConstrainedKalmanFilter, InequalityMethod, LinearConstraint,
+    LinearInequalityConstraint,
+};
+
+mod correlated;
+pub use correlated::CorrelatedNoiseKalmanFilter;
+
+mod colored;
+pub use colored::{ColoredObservationNoise, MeasurementDifferencingKalmanFilter};
+
+mod consider;
+pub use consider::SchmidtKalmanFilter;
+
+mod steady_state;
+pub use steady_state::{solve_dare, SteadyStateKalmanFilter, SteadyStateSolution};
+
+mod repair;
+pub use repair::{repair_covariance, CovarianceRepair, CovarianceRepairDiagnostic};
+
+pub mod models;
+
+#[cfg(feature = "std")]
+pub mod em;
+
+#[cfg(feature = "std")]
+pub mod optimize;
+
+#[cfg(feature = "std")]
+pub mod adaptive;
+
+#[cfg(feature = "std")]
+pub mod imm;
+
+#[cfg(feature = "std")]
+pub mod tracking;
+
+#[cfg(feature = "std")]
+pub mod particle;
+
+#[cfg(feature = "std")]
+pub mod enkf;
+
+#[cfg(feature = "std")]
+pub mod oosm;
+
+#[cfg(feature = "std")]
+pub mod observability;
+
 /// A linear model of process dynamics with no control inputs
 pub trait TransitionModelLinearNoControl<R, SS>
 where
@@ -139,6 +195,23 @@ where
     // TODO: ensure this is positive definite?
     fn observation_noise_covariance(&self) -> &OMatrix<R, OS, OS>;
 
+    /// Given a prior state and an observation, compute the innovation.
+    ///
+    /// The innovation is the difference between the observation and the
+    /// observation predicted from the prior state, together with its
+    /// covariance.
+    fn innovation(
+        &self,
+        prior: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<Innovation<R, OS>, Error> {
+        let s =
+            (self.observation_matrix() * prior.covariance() * self.observation_matrix_transpose())
+                + self.observation_noise_covariance();
+        let residual = observation - self.evaluate(prior.state());
+        Innovation::new(residual, s)
+    }
+
     /// Given a prior state and an observation, compute a posterior state estimate.
     fn update(
         &self,
@@ -170,13 +243,16 @@ where
         trace!("s {}", pretty_print!(s));
 
         // Calculate kalman gain by inverting.
-        let s_chol = match na::linalg::Cholesky::new(s) {
+        let s_chol = match na::linalg::Cholesky::new(s.clone()) {
             Some(v) => v,
             None => {
                 // Maybe state covariance is not symmetric or
                 // for from positive definite? Also, observation
                 // noise should be positive definite.
-                return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into());
+                return Err(
+                    Error::from(ErrorKind::InnovationCovarianceNotPositiveDefinite)
+                        .with_condition_estimate(repair::condition_estimate(&s)),
+                );
             }
         };
         let s_inv: OMatrix<R, OS, OS> = s_chol.inverse();
@@ -232,6 +308,10 @@ where
 
 /// Specifies the approach used for updating the covariance matrix
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
 pub enum CoverianceUpdateMethod {
     /// Assumes optimal Kalman gain.
     ///
@@ -255,6 +335,7 @@ where
 {
     transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
     observation_matrix: &'a dyn ObservationModelLinear<R, SS, OS>,
+    covariance_repair: Option<CovarianceRepair<R>>,
 }
 
 impl<'a, R, SS, OS> KalmanFilterNoControl<'a, R, SS, OS>
@@ -284,7 +365,34 @@ where
         Self {
             transition_model,
             observation_matrix,
+            covariance_repair: None,
+        }
+    }
+
+    /// Repair the covariance automatically after each update.
+    ///
+    /// The posterior covariance of each update step is repaired with
+    /// [`repair_covariance`](fn.repair_covariance.html). Use
+    /// [`step_with_diagnostics`](struct.KalmanFilterNoControl.html#method.step_with_diagnostics)
+    /// to find out whether a repair changed the covariance.
+    ///
+    /// Panics if the initial loading of `CovarianceRepair::DiagonalLoading` is
+    /// not positive, so that the repair cannot fail while filtering.
+    pub fn with_covariance_repair(mut self, covariance_repair: CovarianceRepair<R>) -> Self {
+        if let CovarianceRepair::DiagonalLoading(initial) = covariance_repair {
+            assert!(
+                initial > R::zero(),
+                "the initial diagonal loading must be positive"
+            );
         }
+        self.covariance_repair = Some(covariance_repair);
+        self
+    }
+
+    /// Get the automatic covariance repair method, if any.
+    #[inline]
+    pub fn covariance_repair(&self) -> Option<CovarianceRepair<R>> {
+        self.covariance_repair
     }
 
     /// Perform Kalman prediction and update steps with default values
@@ -321,19 +429,65 @@ where
     ///
     /// This calls the prediction step of the transition model and then, if
     /// there is a (non-`nan`) observation, calls the update step of the
-    /// observation model using the specified covariance update method.
+    /// observation model using the specified covariance update method. An
+    /// observation with infinite components is an error.
     pub fn step_with_options(
         &self,
         previous_estimate: &StateAndCovariance<R, SS>,
         observation: &OVector<R, OS>,
         covariance_update_method: CoverianceUpdateMethod,
     ) -> Result<StateAndCovariance<R, SS>, Error> {
+        self.step_with_diagnostics(previous_estimate, observation, covariance_update_method)
+            .map(|(estimate, _)| estimate)
+    }
+
+    /// Perform Kalman prediction and update steps and report covariance repair
+    ///
+    /// This is the same as
+    /// [step_with_options](struct.KalmanFilterNoControl.html#method.step_with_options)
+    /// but also returns a diagnostic of the automatic covariance repair. The
+    /// diagnostic is `None` if no repair method is set or if no update was
+    /// performed because the observation is missing.
+    #[allow(clippy::type_complexity)]
+    pub fn step_with_diagnostics(
+        &self,
+        previous_estimate: &StateAndCovariance<R, SS>,
+        observation: &OVector<R, OS>,
+        covariance_update_method: CoverianceUpdateMethod,
+    ) -> Result<
+        (
+            StateAndCovariance<R, SS>,
+            Option<CovarianceRepairDiagnostic<R>>,
+        ),
+        Error,
+    > {
         let prior = self.transition_model.predict(previous_estimate);
-        if observation.iter().any(|x| is_nan(*x)) {
-            Ok(prior)
-        } else {
+        if is_missing(observation)? {
+            return Ok((prior, None));
+        }
+        let posterior =
             self.observation_matrix
-                .update(&prior, observation, covariance_update_method)
+                .update(&prior, observation, covariance_update_method)?;
+        Ok(self.repair(posterior))
+    }
+
+    /// Apply the automatic covariance repair, if any.
+    fn repair(
+        &self,
+        estimate: StateAndCovariance<R, SS>,
+    ) -> (
+        StateAndCovariance<R, SS>,
+        Option<CovarianceRepairDiagnostic<R>>,
+    ) {
+        match self.covariance_repair {
+            Some(method) => {
+                let (covariance, diagnostic) = repair_covariance(estimate.covariance(), method);
+                (
+                    StateAndCovariance::new(estimate.state().clone(), covariance),
+                    Some(diagnostic),
+                )
+            }
+            None => (estimate, None),
         }
     }
 
@@ -345,7 +499,9 @@ where
     /// mathematically correct, the interval between observations must be the
     /// `dt` specified in the motion model.
     ///
-    /// If any observation has a NaN component, it is treated as missing.
+    /// If any observation has a NaN component, it is treated as missing. An
+    /// error is returned if `state_estimates` is shorter than `observations`.
+    /// Errors in a step report the index of the observation.
     pub fn filter_inplace(
         &self,
         initial_estimate: &StateAndCovariance<R, SS>,
@@ -353,18 +509,64 @@ where
         state_estimates: &mut [StateAndCovariance<R, SS>],
     ) -> Result<(), Error> {
         let mut previous_estimate = initial_estimate.clone();
-        assert!(state_estimates.len() >= observations.len());
+        if state_estimates.len() < observations.len() {
+            return Err(ErrorKind::DimensionMismatch.into());
+        }
 
-        for (this_observation, state_estimate) in
-            observations.iter().zip(state_estimates.iter_mut())
+        for (index, (this_observation, state_estimate)) in observations
+            .iter()
+            .zip(state_estimates.iter_mut())
+            .enumerate()
         {
-            let this_estimate = self.step(&previous_estimate, this_observation)?;
+            let this_estimate = self
+                .step(&previous_estimate, this_observation)
+                .map_err(|e| e.with_index(index))?;
             *state_estimate = this_estimate.clone();
             previous_estimate = this_estimate;
         }
         Ok(())
     }
 
+    /// Log-likelihood of a time series of observations
+    ///
+    /// Runs the Kalman filter over the observations and sums the log-likelihood
+    /// of each innovation. This is the (log) probability of the observations
+    /// given the models and the initial estimate, which can be used to compare
+    /// or tune models.
+    ///
+    /// If any observation has a NaN component, it is treated as missing and
+    /// does not contribute to the likelihood.
+    pub fn log_likelihood(
+        &self,
+        initial_estimate: &StateAndCovariance<R, SS>,
+        observations: &[OVector<R, OS>],
+    ) -> Result<R, Error> {
+        let mut previous_estimate = initial_estimate.clone();
+        let mut log_likelihood = R::zero();
+        for (index, this_observation) in observations.iter().enumerate() {
+            let prior = self.transition_model.predict(&previous_estimate);
+            if is_missing(this_observation).map_err(|e| e.with_index(index))? {
+                previous_estimate = prior;
+            } else {
+                let innovation = self
+                    .observation_matrix
+                    .innovation(&prior, this_observation)
+                    .map_err(|e| e.with_index(index))?;
+                log_likelihood += innovation.log_likelihood();
+                let posterior = self
+                    .observation_matrix
+                    .update(
+                        &prior,
+                        this_observation,
+                        CoverianceUpdateMethod::OptimalKalmanForcedSymmetric,
+                    )
+                    .map_err(|e| e.with_index(index))?;
+                previous_estimate = self.repair(posterior).0;
+            }
+        }
+        Ok(log_likelihood)
+    }
+
     /// Kalman filter
     ///
     /// This is a convenience function that calls [`filter_inplace`](struct.KalmanFilterNoControl.html#method.filter_inplace).
@@ -421,10 +623,13 @@ where
 
         let mut smoothed_backwards = Vec::with_capacity(forward_results.len());
 
+        let n = forward_results.len();
         let mut smooth_future = forward_results[0].clone();
         smoothed_backwards.push(smooth_future.clone());
-        for filt in forward_results.iter().skip(1) {
-            smooth_future = self.smooth_step(&smooth_future, filt)?;
+        for (i, filt) in forward_results.iter().enumerate().skip(1) {
+            smooth_future = self
+                .smooth_step(&smooth_future, filt)
+                .map_err(|e| e.with_index(n - 1 - i))?;
             smoothed_backwards.push(smooth_future.clone());
         }
 
@@ -443,7 +648,8 @@ where
         let v_chol = match na::linalg::Cholesky::new(prior.covariance().clone()) {
             Some(v) => v,
             None => {
-                return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into());
+                return Err(Error::from(ErrorKind::SmootherPriorNotPositiveDefinite)
+                    .with_condition_estimate(repair::condition_estimate(prior.covariance())));
             }
         };
         let inv_prior_covariance: OMatrix<R, SS, SS> = v_chol.inverse();
@@ -473,6 +679,39 @@ fn is_nan<R: RealField>(x: R) -> bool {
     x.partial_cmp(&R::zero()).is_none()
 }
 
+/// Whether an observation is missing, i.e. any of its components is NaN.
+///
+/// Returns an error of kind `NonFiniteInput` if the observation is not missing
+/// but has infinite components.
+fn is_missing<R, D>(observation: &OVector<R, D>) -> Result<bool, Error>
+where
+    R: RealField,
+    D: DimName,
+    DefaultAllocator: Allocator<R, D>,
+{
+    if observation.iter().any(|x| is_nan(*x)) {
+        Ok(true)
+    } else if observation.iter().all(|x| x.is_finite()) {
+        Ok(false)
+    } else {
+        Err(ErrorKind::NonFiniteInput.into())
+    }
+}
+
+/// Compute the outer product `a*b'` of two column vectors.
+#[inline]
+fn outer_product<R, D1, D2>(a: &OVector<R, D1>, b: &OVector<R, D2>) -> OMatrix<R, D1, D2>
+where
+    R: RealField,
+    D1: DimName,
+    D2: DimName,
+    DefaultAllocator: Allocator<R, D1>,
+    DefaultAllocator: Allocator<R, D2>,
+    DefaultAllocator: Allocator<R, D1, D2>,
+{
+    OMatrix::<R, D1, D2>::from_fn(|i, j| a[i] * b[j])
+}
+
 #[test]
 fn test_is_nan() {
     assert_eq!(is_nan::<f64>(-1.0), false);
@@ -489,3 +728,54 @@ fn test_is_nan() {
     assert_eq!(is_nan::<f32>(-1.0 / 0.0), false);
     assert_eq!(is_nan::<f32>(std::f32::NAN), true);
 }
+
+#[test]
+fn test_error_context() {
+    use na::{U1, U2};
+
+    let transition_model = LinearTransitionModel::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::zeros(),
+    );
+    // a negative observation noise covariance makes S = -1
+    let observation_model = LinearObservationModel::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OMatrix::<f64, U1, U1>::new(-2.0),
+    );
+    let kf = KalmanFilterNoControl::new(&transition_model, &observation_model);
+    let initial = StateAndCovariance::new(
+        OVector::<f64, U2>::zeros(),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    let observations = vec![
+        OVector::<f64, U1>::new(f64::NAN),
+        OVector::<f64, U1>::new(1.0),
+    ];
+
+    let err = kf.filter(&initial, &observations).unwrap_err();
+    assert_eq!(
+        err.kind(),
+        &ErrorKind::InnovationCovarianceNotPositiveDefinite
+    );
+    assert_eq!(err.index(), Some(1));
+    assert!((err.condition_estimate().unwrap() - 1.0).abs() < 1e-12);
+    assert_eq!(
+        format!("{}", err),
+        "Kalman Filter Error: The innovation covariance is not positive definite \
+         at index 1 (condition estimate 1e0)"
+    );
+    let err = kf.log_likelihood(&initial, &observations).unwrap_err();
+    assert_eq!(err.index(), Some(1));
+
+    let mut too_short = vec![initial.clone()];
+    let err = kf
+        .filter_inplace(&initial, &observations, &mut too_short)
+        .unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::DimensionMismatch);
+
+    let err = kf
+        .step(&initial, &OVector::<f64, U1>::new(f64::INFINITY))
+        .unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::NonFiniteInput);
+    assert_eq!(err.index(), None);
+}
@@ -0,0 +1,338 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, RealField};
+use na::{OMatrix, OVector};
+use nalgebra as na;
+
+use crate::{ObservationModelLinear, TransitionModelLinearNoControl};
+
+/// A linear transition model stored as explicit matrices
+///
+/// This stores the state transition model `F` and the transition noise
+/// covariance `Q`.
+#[derive(Debug, Clone)]
+pub struct LinearTransitionModel<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    transition_model: OMatrix<R, SS, SS>,
+    transition_model_transpose: OMatrix<R, SS, SS>,
+    transition_noise_covariance: OMatrix<R, SS, SS>,
+}
+
+impl<R, SS> LinearTransitionModel<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Create a new `LinearTransitionModel` from `F` and `Q`.
+    pub fn new(
+        transition_model: OMatrix<R, SS, SS>,
+        transition_noise_covariance: OMatrix<R, SS, SS>,
+    ) -> Self {
+        Self {
+            transition_model_transpose: transition_model.transpose(),
+            transition_model,
+            transition_noise_covariance,
+        }
+    }
+    /// Copy the matrices of any linear transition model.
+    pub fn from_model(model: &dyn TransitionModelLinearNoControl<R, SS>) -> Self {
+        Self::new(
+            model.transition_model().clone(),
+            model.transition_noise_covariance().clone(),
+        )
+    }
+}
+
+impl<R, SS> TransitionModelLinearNoControl<R, SS> for LinearTransitionModel<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    fn transition_model(&self) -> &OMatrix<R, SS, SS> {
+        &self.transition_model
+    }
+    fn transition_model_transpose(&self) -> &OMatrix<R, SS, SS> {
+        &self.transition_model_transpose
+    }
+    fn transition_noise_covariance(&self) -> &OMatrix<R, SS, SS> {
+        &self.transition_noise_covariance
+    }
+}
+
+/// A linear observation model stored as explicit matrices
+///
+/// This stores the observation matrix `H` and the observation noise covariance
+/// `R`. Observations are predicted as `H*x`.
+#[derive(Debug, Clone)]
+pub struct LinearObservationModel<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    observation_matrix: OMatrix<R, OS, SS>,
+    observation_matrix_transpose: OMatrix<R, SS, OS>,
+    observation_noise_covariance: OMatrix<R, OS, OS>,
+}
+
+impl<R, SS, OS> LinearObservationModel<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+{
+    /// Create a new `LinearObservationModel` from `H` and `R`.
+    pub fn new(
+        observation_matrix: OMatrix<R, OS, SS>,
+        observation_noise_covariance: OMatrix<R, OS, OS>,
+    ) -> Self {
+        Self {
+            observation_matrix_transpose: observation_matrix.transpose(),
+            observation_matrix,
+            observation_noise_covariance,
+        }
+    }
+}
+
+impl<R, SS, OS> LinearObservationModel<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Copy the matrices of any linear observation model.
+    ///
+    /// Note that the resulting model predicts observations as `H*x`, even if
+    /// `model` implements a non-linear `evaluate()`.
+    pub fn from_model(model: &dyn ObservationModelLinear<R, SS, OS>) -> Self {
+        Self::new(
+            model.observation_matrix().clone(),
+            model.observation_noise_covariance().clone(),
+        )
+    }
+}
+
+impl<R, SS, OS> ObservationModelLinear<R, SS, OS> for LinearObservationModel<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    fn evaluate(&self, state: &OVector<R, SS>) -> OVector<R, OS> {
+        &self.observation_matrix * state
+    }
+    fn observation_matrix(&self) -> &OMatrix<R, OS, SS> {
+        &self.observation_matrix
+    }
+    fn observation_matrix_transpose(&self) -> &OMatrix<R, SS, OS> {
+        &self.observation_matrix_transpose
+    }
+    fn observation_noise_covariance(&self) -> &OMatrix<R, OS, OS> {
+        &self.observation_noise_covariance
+    }
+}
+
+// The transposes are not serialized. They are recomputed on deserialization,
+// so that they are always consistent with the stored matrices.
+
+#[cfg(feature = "serde-serialize")]
+#[derive(serde::Serialize)]
+struct TransitionModelRef<'a, T> {
+    transition_model: &'a T,
+    transition_noise_covariance: &'a T,
+}
+
+#[cfg(feature = "serde-serialize")]
+#[derive(serde::Deserialize)]
+struct TransitionModelData<T> {
+    transition_model: T,
+    transition_noise_covariance: T,
+}
+
+#[cfg(feature = "serde-serialize")]
+impl<R, SS> serde::Serialize for LinearTransitionModel<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    OMatrix<R, SS, SS>: serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TransitionModelRef {
+            transition_model: &self.transition_model,
+            transition_noise_covariance: &self.transition_noise_covariance,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde-serialize")]
+impl<'de, R, SS> serde::Deserialize<'de> for LinearTransitionModel<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    OMatrix<R, SS, SS>: serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = TransitionModelData::deserialize(deserializer)?;
+        Ok(Self::new(
+            data.transition_model,
+            data.transition_noise_covariance,
+        ))
+    }
+}
+
+#[cfg(feature = "serde-serialize")]
+#[derive(serde::Serialize)]
+struct ObservationModelRef<'a, H, R> {
+    observation_matrix: &'a H,
+    observation_noise_covariance: &'a R,
+}
+
+#[cfg(feature = "serde-serialize")]
+#[derive(serde::Deserialize)]
+struct ObservationModelData<H, R> {
+    observation_matrix: H,
+    observation_noise_covariance: R,
+}
+
+#[cfg(feature = "serde-serialize")]
+impl<R, SS, OS> serde::Serialize for LinearObservationModel<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    OMatrix<R, OS, SS>: serde::Serialize,
+    OMatrix<R, OS, OS>: serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ObservationModelRef {
+            observation_matrix: &self.observation_matrix,
+            observation_noise_covariance: &self.observation_noise_covariance,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde-serialize")]
+impl<'de, R, SS, OS> serde::Deserialize<'de> for LinearObservationModel<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    OMatrix<R, OS, SS>: serde::Deserialize<'de>,
+    OMatrix<R, OS, OS>: serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = ObservationModelData::deserialize(deserializer)?;
+        Ok(Self::new(
+            data.observation_matrix,
+            data.observation_noise_covariance,
+        ))
+    }
+}
+
+#[cfg(feature = "serde-serialize")]
+#[test]
+fn test_serde() {
+    use crate::{
+        CovarianceRepair, CoverianceUpdateMethod, Error, ErrorKind, KalmanFilterNoControl,
+        StateAndCovariance,
+    };
+    use na::{U1, U2};
+
+    let dt = 0.1;
+    let transition_model = LinearTransitionModel::new(
+        OMatrix::<f64, U2, U2>::new(1.0, dt, 0.0, 1.0),
+        OMatrix::<f64, U2, U2>::new(dt.powi(3) / 3.0, dt.powi(2) / 2.0, dt.powi(2) / 2.0, dt),
+    );
+    let observation_model = LinearObservationModel::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OMatrix::<f64, U1, U1>::new(0.01),
+    );
+    let estimate = StateAndCovariance::new(
+        OVector::<f64, U2>::new(1.0, -1.0),
+        OMatrix::<f64, U2, U2>::new(2.0, 0.5, 0.5, 1.0),
+    );
+
+    // Checkpoint the filter and its estimate and restore them.
+    let json = serde_json::to_string(&transition_model).unwrap();
+    let restored_transition: LinearTransitionModel<f64, U2> = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        restored_transition.transition_model_transpose(),
+        transition_model.transition_model_transpose()
+    );
+    let json = serde_json::to_string(&observation_model).unwrap();
+    let restored_observation: LinearObservationModel<f64, U2, U1> =
+        serde_json::from_str(&json).unwrap();
+    let json = serde_json::to_string(&estimate).unwrap();
+    let restored_estimate: StateAndCovariance<f64, U2> = serde_json::from_str(&json).unwrap();
+
+    let observation = OVector::<f64, U1>::new(0.9);
+    let kf = KalmanFilterNoControl::new(&transition_model, &observation_model);
+    let restored_kf = KalmanFilterNoControl::new(&restored_transition, &restored_observation);
+    let expected = kf.step(&estimate, &observation).unwrap();
+    let actual = restored_kf.step(&restored_estimate, &observation).unwrap();
+    assert_eq!(actual.state(), expected.state());
+    assert_eq!(actual.covariance(), expected.covariance());
+
+    let method = CoverianceUpdateMethod::JosephForm;
+    let json = serde_json::to_string(&method).unwrap();
+    assert_eq!(
+        serde_json::from_str::<CoverianceUpdateMethod>(&json).unwrap(),
+        method
+    );
+    let repair = CovarianceRepair::ClipEigenvalues(1e-6);
+    let json = serde_json::to_string(&repair).unwrap();
+    assert_eq!(
+        serde_json::from_str::<CovarianceRepair<f64>>(&json).unwrap(),
+        repair
+    );
+    let solution = crate::solve_dare(&transition_model, &observation_model).unwrap();
+    let json = serde_json::to_string(&solution).unwrap();
+    let restored: crate::SteadyStateSolution<f64, U2, U1> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.gain(), solution.gain());
+    let error = Error::from(ErrorKind::InnovationCovarianceNotPositiveDefinite)
+        .with_index(3)
+        .with_condition_estimate(1e17);
+    let json = serde_json::to_string(&error).unwrap();
+    assert_eq!(serde_json::from_str::<Error>(&json).unwrap(), error);
+}
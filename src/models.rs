@@ -0,0 +1,762 @@
+//! Standard motion models
+//!
+//! The kinematic models describe each of `D` spatial dimensions (typically 1,
+//! 2 or 3) independently with the same dynamics. The state is ordered by
+//! derivative, so that a constant velocity model in two dimensions has the
+//! state `[x, y, vx, vy]`.
+//!
+//! Each model can be created with one of two forms of the transition noise
+//! covariance `Q`, see Y. Bar-Shalom, X. R. Li and T. Kirubarajan, "Estimation
+//! with Applications to Tracking and Navigation", Wiley, 2001, section 6.2 and
+//! 6.3:
+//!
+//! * `continuous_white_noise` integrates white noise with the given power
+//!   spectral density `q` acting on the first derivative not in the state.
+//! * `discrete_white_noise` adds a random, piecewise constant perturbation
+//!   with the given variance at each step.
+//!
+//! For maneuvering targets, the [`SingerModel`](struct.SingerModel.html) and
+//! the
+//! [`IntegratedOrnsteinUhlenbeckModel`](struct.IntegratedOrnsteinUhlenbeckModel.html)
+//! model the acceleration or velocity as a correlated random process which
+//! reverts to zero.
+
+use na::allocator::Allocator;
+use na::dimension::{DimNameMul, DimNameProd, U2, U3};
+use na::{DefaultAllocator, DimName, OMatrix, RealField};
+use nalgebra as na;
+
+use crate::{LinearTransitionModel, TransitionModelLinearNoControl};
+
+/// Constant position (random walk) model
+///
+/// The state is the position. With continuous white noise of spectral density
+/// `q` on the velocity, `Q = q dt`. With discrete white noise, the velocity is
+/// constant over each interval with variance `sigma^2`, so `Q = sigma^2 dt^2`.
+#[derive(Debug, Clone)]
+pub struct ConstantPositionModel<R, D>
+where
+    R: RealField,
+    D: DimName,
+    DefaultAllocator: Allocator<R, D, D>,
+    DefaultAllocator: Allocator<R, D>,
+{
+    model: LinearTransitionModel<R, D>,
+}
+
+impl<R, D> ConstantPositionModel<R, D>
+where
+    R: RealField,
+    D: DimName,
+    DefaultAllocator: Allocator<R, D, D>,
+    DefaultAllocator: Allocator<R, D>,
+{
+    /// Create a model with continuous white noise of spectral density `q`.
+    pub fn continuous_white_noise(dt: R, q: R) -> Self {
+        Self {
+            model: kinematic_model(1, dt, |i, j| continuous_noise(1, dt, i, j) * q),
+        }
+    }
+    /// Create a model with discrete white noise of variance `variance`.
+    pub fn discrete_white_noise(dt: R, variance: R) -> Self {
+        let gain = [dt];
+        Self {
+            model: kinematic_model(1, dt, |i, j| gain[i] * gain[j] * variance),
+        }
+    }
+}
+
+impl<R, D> TransitionModelLinearNoControl<R, D> for ConstantPositionModel<R, D>
+where
+    R: RealField,
+    D: DimName,
+    DefaultAllocator: Allocator<R, D, D>,
+    DefaultAllocator: Allocator<R, D>,
+{
+    fn transition_model(&self) -> &OMatrix<R, D, D> {
+        self.model.transition_model()
+    }
+    fn transition_model_transpose(&self) -> &OMatrix<R, D, D> {
+        self.model.transition_model_transpose()
+    }
+    fn transition_noise_covariance(&self) -> &OMatrix<R, D, D> {
+        self.model.transition_noise_covariance()
+    }
+}
+
+/// Constant velocity model
+///
+/// The state is position and velocity. With continuous white noise
+/// acceleration (CWNA) of spectral density `q`, in one dimension
+///
+/// ```text
+/// Q = q [dt^3/3 dt^2/2]
+///       [dt^2/2 dt    ]
+/// ```
+///
+/// With discrete white noise acceleration (DWNA), the acceleration is constant
+/// over each interval with variance `sigma^2`, so `Q = sigma^2 G G'` with
+/// `G = [dt^2/2, dt]'`.
+#[derive(Debug, Clone)]
+pub struct ConstantVelocityModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U2>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>, DimNameProd<D, U2>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>>,
+{
+    model: LinearTransitionModel<R, DimNameProd<D, U2>>,
+}
+
+impl<R, D> ConstantVelocityModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U2>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>, DimNameProd<D, U2>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>>,
+{
+    /// Create a model with continuous white noise acceleration of spectral
+    /// density `q`.
+    pub fn continuous_white_noise(dt: R, q: R) -> Self {
+        Self {
+            model: kinematic_model(2, dt, |i, j| continuous_noise(2, dt, i, j) * q),
+        }
+    }
+    /// Create a model with discrete white noise acceleration of variance
+    /// `variance`.
+    pub fn discrete_white_noise(dt: R, variance: R) -> Self {
+        let gain = [dt * dt * na::convert(0.5), dt];
+        Self {
+            model: kinematic_model(2, dt, |i, j| gain[i] * gain[j] * variance),
+        }
+    }
+}
+
+impl<R, D> TransitionModelLinearNoControl<R, DimNameProd<D, U2>> for ConstantVelocityModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U2>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>, DimNameProd<D, U2>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>>,
+{
+    fn transition_model(&self) -> &OMatrix<R, DimNameProd<D, U2>, DimNameProd<D, U2>> {
+        self.model.transition_model()
+    }
+    fn transition_model_transpose(&self) -> &OMatrix<R, DimNameProd<D, U2>, DimNameProd<D, U2>> {
+        self.model.transition_model_transpose()
+    }
+    fn transition_noise_covariance(&self) -> &OMatrix<R, DimNameProd<D, U2>, DimNameProd<D, U2>> {
+        self.model.transition_noise_covariance()
+    }
+}
+
+/// Constant acceleration model
+///
+/// The state is position, velocity and acceleration. With continuous white
+/// noise jerk of spectral density `q`, in one dimension
+///
+/// ```text
+/// Q = q [dt^5/20 dt^4/8 dt^3/6]
+///       [dt^4/8  dt^3/3 dt^2/2]
+///       [dt^3/6  dt^2/2 dt    ]
+/// ```
+///
+/// The discrete form is the discrete Wiener process acceleration (DWPA)
+/// model, in which the acceleration changes by a random increment with
+/// variance `sigma^2` at each step, so `Q = sigma^2 G G'` with
+/// `G = [dt^2/2, dt, 1]'`.
+#[derive(Debug, Clone)]
+pub struct ConstantAccelerationModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U3>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>, DimNameProd<D, U3>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>>,
+{
+    model: LinearTransitionModel<R, DimNameProd<D, U3>>,
+}
+
+impl<R, D> ConstantAccelerationModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U3>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>, DimNameProd<D, U3>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>>,
+{
+    /// Create a model with continuous white noise jerk of spectral density
+    /// `q`.
+    pub fn continuous_white_noise(dt: R, q: R) -> Self {
+        Self {
+            model: kinematic_model(3, dt, |i, j| continuous_noise(3, dt, i, j) * q),
+        }
+    }
+    /// Create a model with discrete Wiener process acceleration, whose
+    /// increments have variance `variance`.
+    pub fn discrete_white_noise(dt: R, variance: R) -> Self {
+        let gain = [dt * dt * na::convert(0.5), dt, R::one()];
+        Self {
+            model: kinematic_model(3, dt, |i, j| gain[i] * gain[j] * variance),
+        }
+    }
+}
+
+impl<R, D> TransitionModelLinearNoControl<R, DimNameProd<D, U3>> for ConstantAccelerationModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U3>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>, DimNameProd<D, U3>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>>,
+{
+    fn transition_model(&self) -> &OMatrix<R, DimNameProd<D, U3>, DimNameProd<D, U3>> {
+        self.model.transition_model()
+    }
+    fn transition_model_transpose(&self) -> &OMatrix<R, DimNameProd<D, U3>, DimNameProd<D, U3>> {
+        self.model.transition_model_transpose()
+    }
+    fn transition_noise_covariance(&self) -> &OMatrix<R, DimNameProd<D, U3>, DimNameProd<D, U3>> {
+        self.model.transition_noise_covariance()
+    }
+}
+
+/// Singer model of a maneuvering target
+///
+/// The state is position, velocity and acceleration. The acceleration is
+/// exponentially correlated with time constant `tau`, following
+/// `da = -a/tau dt + dw`, where `w` is white noise of spectral density
+/// `2 sigma_m^2 / tau`, so that `sigma_m^2` is the stationary variance of the
+/// acceleration. The transition model and noise covariance are the exact
+/// discretization, see R. A. Singer, "Estimating optimal tracking filter
+/// performance for manned maneuvering targets", IEEE Transactions on Aerospace
+/// and Electronic Systems, 1970.
+///
+/// The closed form suffers from cancellation if `dt` is shorter than `tau`, so
+/// the Taylor series in `dt / tau` is used instead when `dt < tau`.
+#[derive(Debug, Clone)]
+pub struct SingerModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U3>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>, DimNameProd<D, U3>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>>,
+{
+    model: LinearTransitionModel<R, DimNameProd<D, U3>>,
+}
+
+impl<R, D> SingerModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U3>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>, DimNameProd<D, U3>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>>,
+{
+    /// Create a model with the given time constant `tau` of the maneuvers and
+    /// the variance `sigma_m^2` of the acceleration.
+    pub fn new(dt: R, time_constant: R, acceleration_variance: R) -> Self {
+        let two: R = na::convert(2.0);
+        let three: R = na::convert(3.0);
+        let four: R = na::convert(4.0);
+        let alpha = R::one() / time_constant;
+        let at = alpha * dt;
+        let e1 = (-at).exp();
+        let e2 = (-two * at).exp();
+        let alpha2 = alpha * alpha;
+
+        let (f13, f23, q) = if at < na::convert(SERIES_THRESHOLD) {
+            let q = |i, j| decaying_noise_series(3, alpha, dt, i, j);
+            let (q12, q13, q23) = (q(0, 1), q(0, 2), q(1, 2));
+            (
+                dt * dt * decay_series(2, at),
+                dt * decay_series(1, at),
+                [
+                    [q(0, 0), q12, q13],
+                    [q12, q(1, 1), q23],
+                    [q13, q23, q(2, 2)],
+                ],
+            )
+        } else {
+            let q11 = (R::one() - e2 + two * at + two * at.powi(3) / three
+                - two * at * at
+                - four * at * e1)
+                / (two * alpha.powi(5));
+            let q12 = (e2 + R::one() - two * e1 + two * at * e1 - two * at + at * at)
+                / (two * alpha.powi(4));
+            let q13 = (R::one() - e2 - two * at * e1) / (two * alpha.powi(3));
+            let q22 = (four * e1 - three - e2 + two * at) / (two * alpha.powi(3));
+            let q23 = (e2 + R::one() - two * e1) / (two * alpha2);
+            let q33 = (R::one() - e2) / (two * alpha);
+            (
+                (at - R::one() + e1) / alpha2,
+                (R::one() - e1) / alpha,
+                [[q11, q12, q13], [q12, q22, q23], [q13, q23, q33]],
+            )
+        };
+        #[rustfmt::skip]
+        let f = [
+            [R::one(), dt, f13],
+            [R::zero(), R::one(), f23],
+            [R::zero(), R::zero(), e1],
+        ];
+        let intensity = two * alpha * acceleration_variance;
+
+        Self {
+            model: per_dimension_model(3, |i, j| f[i][j], |i, j| q[i][j] * intensity),
+        }
+    }
+}
+
+impl<R, D> TransitionModelLinearNoControl<R, DimNameProd<D, U3>> for SingerModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U3>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>, DimNameProd<D, U3>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U3>>,
+{
+    fn transition_model(&self) -> &OMatrix<R, DimNameProd<D, U3>, DimNameProd<D, U3>> {
+        self.model.transition_model()
+    }
+    fn transition_model_transpose(&self) -> &OMatrix<R, DimNameProd<D, U3>, DimNameProd<D, U3>> {
+        self.model.transition_model_transpose()
+    }
+    fn transition_noise_covariance(&self) -> &OMatrix<R, DimNameProd<D, U3>, DimNameProd<D, U3>> {
+        self.model.transition_noise_covariance()
+    }
+}
+
+/// Integrated Ornstein-Uhlenbeck model
+///
+/// The state is position and velocity. The velocity is an Ornstein-Uhlenbeck
+/// process with time constant `tau`, following `dv = -v/tau dt + dw`, where
+/// `w` is white noise of spectral density `2 sigma_v^2 / tau`, so that
+/// `sigma_v^2` is the stationary variance of the velocity. With
+/// `beta = 1/tau`, the exact discretization is
+///
+/// ```text
+/// F = [1 (1 - e^(-beta dt))/beta]
+///     [0 e^(-beta dt)           ]
+/// ```
+///
+/// and, for white noise of unit spectral density,
+///
+/// ```text
+/// Q_11 = (dt - 2 (1 - e^(-beta dt))/beta + (1 - e^(-2 beta dt))/(2 beta)) / beta^2
+/// Q_12 = (1 - e^(-beta dt))^2 / (2 beta^2)
+/// Q_22 = (1 - e^(-2 beta dt)) / (2 beta)
+/// ```
+///
+/// See e.g. Y. Bar-Shalom, X. R. Li and T. Kirubarajan, "Estimation with
+/// Applications to Tracking and Navigation", Wiley, 2001. As for the
+/// [`SingerModel`](struct.SingerModel.html), the Taylor series in `dt / tau` is
+/// used instead when `dt < tau`.
+#[derive(Debug, Clone)]
+pub struct IntegratedOrnsteinUhlenbeckModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U2>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>, DimNameProd<D, U2>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>>,
+{
+    model: LinearTransitionModel<R, DimNameProd<D, U2>>,
+}
+
+impl<R, D> IntegratedOrnsteinUhlenbeckModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U2>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>, DimNameProd<D, U2>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>>,
+{
+    /// Create a model with the given time constant `tau` of the velocity and
+    /// the stationary variance `sigma_v^2` of the velocity.
+    pub fn new(dt: R, time_constant: R, velocity_variance: R) -> Self {
+        let two: R = na::convert(2.0);
+        let beta = R::one() / time_constant;
+        let bt = beta * dt;
+        let e1 = (-bt).exp();
+        let e2 = (-two * bt).exp();
+
+        let (f12, q) = if bt < na::convert(SERIES_THRESHOLD) {
+            let q = |i, j| decaying_noise_series(2, beta, dt, i, j);
+            let q12 = q(0, 1);
+            (dt * decay_series(1, bt), [[q(0, 0), q12], [q12, q(1, 1)]])
+        } else {
+            let q11 = (dt - two * (R::one() - e1) / beta + (R::one() - e2) / (two * beta))
+                / (beta * beta);
+            let q12 = (R::one() - e1) * (R::one() - e1) / (two * beta * beta);
+            let q22 = (R::one() - e2) / (two * beta);
+            ((R::one() - e1) / beta, [[q11, q12], [q12, q22]])
+        };
+        #[rustfmt::skip]
+        let f = [
+            [R::one(), f12],
+            [R::zero(), e1],
+        ];
+        let intensity = two * beta * velocity_variance;
+
+        Self {
+            model: per_dimension_model(2, |i, j| f[i][j], |i, j| q[i][j] * intensity),
+        }
+    }
+}
+
+impl<R, D> TransitionModelLinearNoControl<R, DimNameProd<D, U2>>
+    for IntegratedOrnsteinUhlenbeckModel<R, D>
+where
+    R: RealField,
+    D: DimName + DimNameMul<U2>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>, DimNameProd<D, U2>>,
+    DefaultAllocator: Allocator<R, DimNameProd<D, U2>>,
+{
+    fn transition_model(&self) -> &OMatrix<R, DimNameProd<D, U2>, DimNameProd<D, U2>> {
+        self.model.transition_model()
+    }
+    fn transition_model_transpose(&self) -> &OMatrix<R, DimNameProd<D, U2>, DimNameProd<D, U2>> {
+        self.model.transition_model_transpose()
+    }
+    fn transition_noise_covariance(&self) -> &OMatrix<R, DimNameProd<D, U2>, DimNameProd<D, U2>> {
+        self.model.transition_noise_covariance()
+    }
+}
+
+/// Build a kinematic model with `order` derivatives per spatial dimension.
+///
+/// The one-dimensional noise covariance is given by `noise(i, j)` for the
+/// derivatives `i` and `j`.
+fn kinematic_model<R, SS>(
+    order: usize,
+    dt: R,
+    noise: impl Fn(usize, usize) -> R,
+) -> LinearTransitionModel<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    // F_ij = dt^(j-i) / (j-i)!
+    let transition = |i: usize, j: usize| {
+        if j >= i {
+            dt.powi((j - i) as i32) / factorial(j - i)
+        } else {
+            R::zero()
+        }
+    };
+    per_dimension_model(order, transition, noise)
+}
+
+/// Build a model with `order` states per spatial dimension.
+///
+/// The one-dimensional transition model and noise covariance are given by
+/// `transition(i, j)` and `noise(i, j)`. The spatial dimension is
+/// `SS::dim() / order`.
+fn per_dimension_model<R, SS>(
+    order: usize,
+    transition: impl Fn(usize, usize) -> R,
+    noise: impl Fn(usize, usize) -> R,
+) -> LinearTransitionModel<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    debug_assert_eq!(SS::dim() % order, 0);
+    let d = SS::dim() / order;
+    let per_dimension = |entry: &dyn Fn(usize, usize) -> R| {
+        OMatrix::<R, SS, SS>::from_fn(|i, j| {
+            if i % d == j % d {
+                entry(i / d, j / d)
+            } else {
+                R::zero()
+            }
+        })
+    };
+    LinearTransitionModel::new(per_dimension(&transition), per_dimension(&noise))
+}
+
+/// Entry `(i, j)` of the noise covariance of `order` integrators driven by
+/// white noise of unit spectral density.
+fn continuous_noise<R: RealField>(order: usize, dt: R, i: usize, j: usize) -> R {
+    let power = 2 * order - 1 - i - j;
+    dt.powi(power as i32)
+        / (factorial::<R>(order - 1 - i) * factorial(order - 1 - j) * na::convert(power as f64))
+}
+
+/// Below this value of `dt / tau`, the transition model and noise covariance
+/// of the Singer and integrated Ornstein-Uhlenbeck models are computed from
+/// their Taylor series.
+const SERIES_THRESHOLD: f64 = 1.0;
+
+/// Number of terms of the Taylor series, enough for full precision in `f64`
+/// below `SERIES_THRESHOLD`.
+const SERIES_TERMS: usize = 20;
+
+/// `sum_n (-x)^n / (n + k)!`, e.g. `1 - e^(-x) = x decay_series(1, x)`.
+fn decay_series<R: RealField>(k: usize, x: R) -> R {
+    let mut sum = R::zero();
+    let mut power = R::one();
+    for n in 0..SERIES_TERMS {
+        sum += power / factorial(n + k);
+        power *= -x;
+    }
+    sum
+}
+
+/// Entry `(i, j)` of the noise covariance of `order - 1` integrators of a
+/// state decaying with rate `alpha`, which is driven by white noise of unit
+/// spectral density, by its Taylor series in `alpha dt`.
+fn decaying_noise_series<R: RealField>(order: usize, alpha: R, dt: R, i: usize, j: usize) -> R {
+    // With p_i = order - 1 - i, the impulse response of state i is
+    // sum_m (-alpha)^m s^(m + p_i) / (m + p_i)!, and the entry is the integral
+    // over [0, dt] of the product of two impulse responses.
+    let (pi, pj) = (order - 1 - i, order - 1 - j);
+    let x = -alpha * dt;
+    let mut sum = R::zero();
+    let mut power_m = R::one();
+    for m in 0..SERIES_TERMS {
+        let mut power = power_m;
+        for n in 0..SERIES_TERMS - m {
+            let degree = m + n + pi + pj + 1;
+            sum +=
+                power / (factorial::<R>(m + pi) * factorial(n + pj) * na::convert(degree as f64));
+            power *= x;
+        }
+        power_m *= x;
+    }
+    sum * dt.powi((pi + pj + 1) as i32)
+}
+
+fn factorial<R: RealField>(n: usize) -> R {
+    (1..=n).fold(R::one(), |acc, k| acc * na::convert(k as f64))
+}
+
+#[test]
+fn test_kinematic_models() {
+    use na::{U1, U4, U6};
+
+    let dt = 0.1;
+    let q = 2.0;
+
+    // The two-dimensional model of the examples.
+    let model = ConstantVelocityModel::<f64, U2>::continuous_white_noise(dt, q);
+    #[rustfmt::skip]
+    let expected_f = OMatrix::<f64, U4, U4>::new(
+        1.0, 0.0, dt, 0.0,
+        0.0, 1.0, 0.0, dt,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, 0.0, 0.0, 1.0);
+    let t33 = dt * dt * dt / 3.0;
+    let t22 = dt * dt / 2.0;
+    #[rustfmt::skip]
+    let expected_q = OMatrix::<f64, U4, U4>::new(
+        t33, 0.0, t22, 0.0,
+        0.0, t33, 0.0, t22,
+        t22, 0.0, dt, 0.0,
+        0.0, t22, 0.0, dt) * q;
+    assert!((model.transition_model() - expected_f).abs().max() < 1e-15);
+    assert!(
+        (model.transition_noise_covariance() - expected_q)
+            .abs()
+            .max()
+            < 1e-15
+    );
+    assert_eq!(model.transition_model_transpose(), &expected_f.transpose());
+
+    let model = ConstantPositionModel::<f64, U3>::continuous_white_noise(dt, q);
+    assert_eq!(
+        model.transition_model(),
+        &OMatrix::<f64, U3, U3>::identity()
+    );
+    assert!(
+        (model.transition_noise_covariance() - OMatrix::<f64, U3, U3>::identity() * q * dt)
+            .abs()
+            .max()
+            < 1e-15
+    );
+    let model = ConstantPositionModel::<f64, U1>::discrete_white_noise(dt, q);
+    assert!((model.transition_noise_covariance()[(0, 0)] - q * dt * dt).abs() < 1e-15);
+
+    // Q of the constant acceleration model, checked against the integral of
+    // F(t) G G' F(t)' with G = [0 0 1]' by the trapezoidal rule.
+    let model = ConstantAccelerationModel::<f64, U1>::continuous_white_noise(dt, q);
+    let f = *model.transition_model();
+    assert!((f[(0, 2)] - dt * dt / 2.0).abs() < 1e-15);
+    let steps = 1000;
+    let mut integral = OMatrix::<f64, U3, U3>::zeros();
+    for k in 0..=steps {
+        let t = dt * k as f64 / steps as f64;
+        let g = na::Vector3::new(t * t / 2.0, t, 1.0);
+        let weight = if k == 0 || k == steps { 0.5 } else { 1.0 };
+        integral += g * g.transpose() * (weight * q * dt / steps as f64);
+    }
+    assert!((model.transition_noise_covariance() - integral).abs().max() < 1e-9);
+
+    let model = ConstantAccelerationModel::<f64, U2>::discrete_white_noise(dt, q);
+    let g = na::Vector3::new(dt * dt / 2.0, dt, 1.0);
+    let expected = g * g.transpose() * q;
+    let actual: &OMatrix<f64, U6, U6> = model.transition_noise_covariance();
+    for i in 0..3 {
+        for j in 0..3 {
+            assert!((actual[(2 * i, 2 * j)] - expected[(i, j)]).abs() < 1e-15);
+            assert!((actual[(2 * i + 1, 2 * j + 1)] - expected[(i, j)]).abs() < 1e-15);
+            assert_eq!(actual[(2 * i, 2 * j + 1)], 0.0);
+        }
+    }
+}
+
+#[test]
+fn test_maneuver_models() {
+    use na::{Matrix2, Matrix3, U1, U4};
+
+    // The exponential of a matrix by its Taylor series.
+    fn expm<SS: DimName>(a: &OMatrix<f64, SS, SS>) -> OMatrix<f64, SS, SS>
+    where
+        DefaultAllocator: Allocator<f64, SS, SS>,
+    {
+        let mut term = OMatrix::<f64, SS, SS>::identity();
+        let mut result = term.clone();
+        for k in 1..40 {
+            term = &term * a / k as f64;
+            result += &term;
+        }
+        result
+    }
+    // Q = q int_0^dt e^(A s) G G' e^(A' s) ds by Simpson's rule.
+    fn integrate<SS: DimName>(
+        a: &OMatrix<f64, SS, SS>,
+        g: &OMatrix<f64, SS, SS>,
+        dt: f64,
+    ) -> OMatrix<f64, SS, SS>
+    where
+        DefaultAllocator: Allocator<f64, SS, SS>,
+    {
+        let steps = 200;
+        let h = dt / steps as f64;
+        let mut integral = OMatrix::<f64, SS, SS>::zeros();
+        for k in 0..=steps {
+            let weight = if k == 0 || k == steps {
+                1.0
+            } else if k % 2 == 1 {
+                4.0
+            } else {
+                2.0
+            };
+            let phi = expm(&(a * (k as f64 * h)));
+            integral += &phi * g * phi.transpose() * (weight * h / 3.0);
+        }
+        integral
+    }
+
+    let dt = 1.5;
+    let tau = 2.0;
+    let variance = 9.0;
+
+    // Singer: da = -a/tau dt + dw with spectral density 2 variance / tau
+    let model = SingerModel::<f64, U1>::new(dt, tau, variance);
+    let a = Matrix3::new(0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, -1.0 / tau);
+    let mut g = Matrix3::zeros();
+    g[(2, 2)] = 2.0 * variance / tau;
+    let f = expm(&(a * dt));
+    let q = integrate(&a, &g, dt);
+    assert!((model.transition_model() - f).abs().max() < 1e-12);
+    assert!((model.transition_noise_covariance() - q).abs().max() < 1e-9 * q.abs().max());
+    // the acceleration variance is stationary
+    let q = model.transition_noise_covariance();
+    let predicted = f[(2, 2)] * variance * f[(2, 2)] + q[(2, 2)];
+    assert!((predicted - variance).abs() < 1e-12);
+
+    // Integrated Ornstein-Uhlenbeck: dv = -v/tau dt + dw
+    let model = IntegratedOrnsteinUhlenbeckModel::<f64, U2>::new(dt, tau, variance);
+    let a = Matrix2::new(0.0, 1.0, 0.0, -1.0 / tau);
+    let mut g = Matrix2::zeros();
+    g[(1, 1)] = 2.0 * variance / tau;
+    let f = expm(&(a * dt));
+    let q = integrate(&a, &g, dt);
+    let actual_f: &OMatrix<f64, U4, U4> = model.transition_model();
+    let actual_q = model.transition_noise_covariance();
+    for i in 0..2 {
+        for j in 0..2 {
+            for d in 0..2 {
+                assert!((actual_f[(2 * i + d, 2 * j + d)] - f[(i, j)]).abs() < 1e-12);
+                assert!((actual_q[(2 * i + d, 2 * j + d)] - q[(i, j)]).abs() < 1e-9);
+            }
+            assert_eq!(actual_q[(2 * i, 2 * j + 1)], 0.0);
+        }
+    }
+}
+
+#[test]
+fn test_maneuver_models_precision() {
+    use na::{U1, U3};
+
+    // Entries q11, q12, q13, q22, q23 and q33 of Singer's closed form for Q,
+    // evaluated in extended precision.
+    fn check_singer(dt: f64, tau: f64, variance: f64, expected: [f64; 6]) {
+        let model = SingerModel::<f64, U1>::new(dt, tau, variance);
+        let q: &OMatrix<f64, U3, U3> = model.transition_noise_covariance();
+        let actual = [
+            q[(0, 0)],
+            q[(0, 1)],
+            q[(0, 2)],
+            q[(1, 1)],
+            q[(1, 2)],
+            q[(2, 2)],
+        ];
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-12 * e.abs(), "{} != {}", a, e);
+        }
+        assert_eq!(q, &q.transpose());
+        assert!(q.cholesky().is_some());
+    }
+
+    // closed form
+    #[rustfmt::skip]
+    check_singer(1.5, 1.0, 9.0, [
+        3.2528877366740137, 4.706255056646644, 2.527402060681619,
+        7.5846021500326986, 5.431740732639039, 8.551916384689225,
+    ]);
+    // Taylor series
+    #[rustfmt::skip]
+    check_singer(1.5, 2.0, 9.0, [
+        2.306906154507751, 3.5601756320104183, 2.459520386641732,
+        5.988097829362644, 5.011146983995207, 6.991828558664132,
+    ]);
+    #[rustfmt::skip]
+    check_singer(0.01, 60.0, 1.0, [
+        1.6665123548643568e-13, 4.1662037358521954e-11, 5.5546297145006e-9,
+        1.1109722330240485e-8, 1.6663889158931329e-6, 3.332777839501029e-4,
+    ]);
+    #[rustfmt::skip]
+    check_singer(0.001, 10.0, 1.0, [
+        9.999444464285159e-18, 2.4998333402775558e-14, 3.3330000183326116e-11,
+        6.666166689999167e-11, 9.999000058330834e-8, 1.9998000133326666e-4,
+    ]);
+    let model = SingerModel::<f64, U1>::new(0.01, 60.0, 1.0);
+    assert!((model.transition_model()[(0, 2)] - 4.999722233795911e-5).abs() < 1e-18);
+
+    // The two forms agree where they meet.
+    let below = SingerModel::<f64, U1>::new(1.0 - 1e-15, 1.0, 1.0);
+    let above = SingerModel::<f64, U1>::new(1.0, 1.0, 1.0);
+    let q = below.transition_noise_covariance();
+    let relative = (q - above.transition_noise_covariance()).component_div(q);
+    assert!(relative.abs().max() < 1e-13);
+    assert!(
+        (below.transition_model() - above.transition_model())
+            .abs()
+            .max()
+            < 1e-14
+    );
+
+    let model = IntegratedOrnsteinUhlenbeckModel::<f64, U1>::new(1e-4, 100.0, 1.0);
+    let q = model.transition_noise_covariance();
+    let expected = [
+        [6.666661666669001e-15, 9.999990000005834e-11],
+        [9.999990000005834e-11, 1.9999980000013335e-6],
+    ];
+    for i in 0..2 {
+        for j in 0..2 {
+            assert!((q[(i, j)] - expected[i][j]).abs() < 1e-12 * expected[i][j]);
+        }
+    }
+    assert!(q.cholesky().is_some());
+    assert!((model.transition_model()[(0, 1)] - 9.999995000001667e-5).abs() < 1e-18);
+}
@@ -0,0 +1,369 @@
+//! Observability and detectability analysis
+//!
+//! The state is observable if it can be determined from a sequence of
+//! noise-free observations. For a time-invariant model with `n` states, this
+//! is the case if the observability matrix
+//!
+//! ```text
+//! [H; H F; H F^2; ...; H F^(n-1)]
+//! ```
+//!
+//! has rank `n`. For a sequence of time-varying models, the blocks are
+//! `H_k F_(k-1) ... F_0`. The analysis uses the observability Gramian
+//! `W = O' O`, which has the same rank as the observability matrix `O` and
+//! whose eigenvalues are the squared singular values of `O`.
+//!
+//! The analysis is of the state at the time of the first observation.
+
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::repair::jacobi_eigen;
+use crate::{outer_product, ObservationModelLinear, TransitionModelLinearNoControl};
+
+/// Number of times the unobservable dynamics are squared to decide stability.
+const STABILITY_SQUARINGS: usize = 64;
+
+/// The result of an observability analysis
+#[derive(Debug, Clone)]
+pub struct ObservabilityAnalysis<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    gramian: OMatrix<R, SS, SS>,
+    singular_values: OVector<R, SS>,
+    directions: OMatrix<R, SS, SS>,
+}
+
+impl<R, SS> ObservabilityAnalysis<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Analyze the observability matrix given as its blocks.
+    pub fn from_blocks<OS>(blocks: &[OMatrix<R, OS, SS>]) -> Self
+    where
+        OS: DimName,
+        DefaultAllocator: Allocator<R, OS, SS>,
+        DefaultAllocator: Allocator<R, SS, OS>,
+    {
+        let mut gramian = OMatrix::<R, SS, SS>::zeros();
+        for block in blocks.iter() {
+            gramian += block.transpose() * block;
+        }
+        Self::from_gramian(gramian)
+    }
+
+    /// Analyze an observability Gramian.
+    pub fn from_gramian(gramian: OMatrix<R, SS, SS>) -> Self {
+        let (eigenvalues, eigenvectors) = jacobi_eigen(gramian.clone());
+        let mut order: Vec<usize> = (0..SS::dim()).collect();
+        order.sort_by(|&i, &j| {
+            eigenvalues[j]
+                .partial_cmp(&eigenvalues[i])
+                .unwrap_or(core::cmp::Ordering::Equal)
+        });
+        let singular_values = OVector::<R, SS>::from_fn(|i, _| {
+            let value = eigenvalues[order[i]];
+            if value > R::zero() {
+                value.sqrt()
+            } else {
+                R::zero()
+            }
+        });
+        let directions = OMatrix::<R, SS, SS>::from_fn(|i, j| eigenvectors[(i, order[j])]);
+        Self {
+            gramian,
+            singular_values,
+            directions,
+        }
+    }
+
+    /// Get the observability Gramian `W = O' O`.
+    #[inline]
+    pub fn gramian(&self) -> &OMatrix<R, SS, SS> {
+        &self.gramian
+    }
+
+    /// Get the singular values of the observability matrix in descending
+    /// order.
+    #[inline]
+    pub fn singular_values(&self) -> &OVector<R, SS> {
+        &self.singular_values
+    }
+
+    /// The default relative tolerance on the singular values.
+    ///
+    /// Because the singular values are computed from the Gramian, singular
+    /// values smaller than about the square root of the machine epsilon times
+    /// the largest one cannot be distinguished from zero.
+    pub fn default_tolerance() -> R {
+        let n: R = na::convert(SS::dim() as f64);
+        let ten: R = na::convert(10.0);
+        ten * n * R::default_epsilon().sqrt()
+    }
+
+    /// Get the rank of the observability matrix.
+    ///
+    /// This uses [`default_tolerance`](#method.default_tolerance).
+    pub fn rank(&self) -> usize {
+        self.rank_with_tolerance(Self::default_tolerance())
+    }
+
+    /// Get the rank of the observability matrix, counting singular values
+    /// larger than `tolerance` times the largest singular value.
+    pub fn rank_with_tolerance(&self, tolerance: R) -> usize {
+        let threshold = self.singular_values[0] * tolerance;
+        self.singular_values
+            .iter()
+            .filter(|s| **s > threshold && **s > R::zero())
+            .count()
+    }
+
+    /// Return whether the state is observable.
+    pub fn is_observable(&self) -> bool {
+        self.rank() == SS::dim()
+    }
+
+    /// Get the condition number of the observability matrix.
+    ///
+    /// This is the ratio of the largest to the smallest singular value. Large
+    /// values indicate that some directions of the state are only weakly
+    /// observable. It is infinite if the smallest singular value is zero.
+    pub fn condition_number(&self) -> R {
+        self.singular_values[0] / self.singular_values[SS::dim() - 1]
+    }
+
+    /// Get an orthonormal basis of the unobservable subspace.
+    ///
+    /// These are the directions of the state which cannot be determined from
+    /// the observations. The basis is empty if the state is observable.
+    pub fn unobservable_subspace(&self) -> Vec<OVector<R, SS>> {
+        (self.rank()..SS::dim())
+            .map(|j| self.directions.column(j).into_owned())
+            .collect()
+    }
+}
+
+/// Compute the blocks `H F^k` of the observability matrix for `k` from zero
+/// to the state size minus one.
+pub fn observability_matrix<R, SS, OS>(
+    transition_model: &dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &dyn ObservationModelLinear<R, SS, OS>,
+) -> Vec<OMatrix<R, OS, SS>>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    let f = transition_model.transition_model();
+    let mut block = observation_model.observation_matrix().clone();
+    let mut blocks = Vec::with_capacity(SS::dim());
+    for _ in 0..SS::dim() {
+        let next = &block * f;
+        blocks.push(block);
+        block = next;
+    }
+    blocks
+}
+
+/// Compute the blocks `H_k F_(k-1) ... F_0` of the observability matrix of a
+/// sequence of time-varying models.
+///
+/// `transition_models[k]` is the transition from the time of
+/// `observation_models[k]` to the time of `observation_models[k+1]`.
+///
+/// Panics if there is not exactly one transition model fewer than
+/// observation models.
+pub fn observability_matrix_time_varying<R, SS, OS>(
+    transition_models: &[&dyn TransitionModelLinearNoControl<R, SS>],
+    observation_models: &[&dyn ObservationModelLinear<R, SS, OS>],
+) -> Vec<OMatrix<R, OS, SS>>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    assert_eq!(transition_models.len() + 1, observation_models.len());
+    let mut state_transition = OMatrix::<R, SS, SS>::identity();
+    let mut blocks = Vec::with_capacity(observation_models.len());
+    for (k, observation_model) in observation_models.iter().enumerate() {
+        if k > 0 {
+            state_transition = transition_models[k - 1].transition_model() * state_transition;
+        }
+        blocks.push(observation_model.observation_matrix() * &state_transition);
+    }
+    blocks
+}
+
+/// Analyze the observability of a time-invariant model.
+pub fn analyze_observability<R, SS, OS>(
+    transition_model: &dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &dyn ObservationModelLinear<R, SS, OS>,
+) -> ObservabilityAnalysis<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    ObservabilityAnalysis::from_blocks(&observability_matrix(transition_model, observation_model))
+}
+
+/// Analyze the observability of a sequence of time-varying models.
+///
+/// See [`observability_matrix_time_varying`](fn.observability_matrix_time_varying.html)
+/// for the arguments.
+pub fn analyze_observability_time_varying<R, SS, OS>(
+    transition_models: &[&dyn TransitionModelLinearNoControl<R, SS>],
+    observation_models: &[&dyn ObservationModelLinear<R, SS, OS>],
+) -> ObservabilityAnalysis<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    ObservabilityAnalysis::from_blocks(&observability_matrix_time_varying(
+        transition_models,
+        observation_models,
+    ))
+}
+
+/// Test whether a time-invariant model is detectable.
+///
+/// A model is detectable if all unobservable modes are stable, so that the
+/// error of a Kalman filter remains bounded. This holds if the dynamics
+/// restricted to the unobservable subspace have spectral radius less than
+/// one, which is decided by repeatedly squaring them.
+pub fn is_detectable<R, SS, OS>(
+    transition_model: &dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &dyn ObservationModelLinear<R, SS, OS>,
+) -> bool
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    let unobservable =
+        analyze_observability(transition_model, observation_model).unobservable_subspace();
+    if unobservable.is_empty() {
+        return true;
+    }
+    // Project the dynamics onto the unobservable subspace, which is invariant
+    // under F.
+    let mut projection = OMatrix::<R, SS, SS>::zeros();
+    for direction in unobservable.iter() {
+        projection += outer_product(direction, direction);
+    }
+    let mut dynamics = &projection * transition_model.transition_model() * &projection;
+    for _ in 0..STABILITY_SQUARINGS {
+        dynamics = &dynamics * &dynamics;
+    }
+    // this is false if the norm overflowed to infinity or NaN
+    dynamics.norm() < R::one()
+}
+
+#[test]
+fn test_observability() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{U1, U2};
+
+    // Constant velocity model
+    let transition_model = LinearTransitionModel::new(
+        OMatrix::<f64, U2, U2>::new(1.0, 1.0, 0.0, 1.0),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    let noise = OMatrix::<f64, U1, U1>::new(1.0);
+    let position = LinearObservationModel::new(OMatrix::<f64, U1, U2>::new(1.0, 0.0), noise);
+    let velocity = LinearObservationModel::new(OMatrix::<f64, U1, U2>::new(0.0, 1.0), noise);
+
+    // Observing position, the observability matrix is [1 0; 1 1], whose
+    // condition number is the golden ratio squared.
+    let blocks = observability_matrix(&transition_model, &position);
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[1], OMatrix::<f64, U1, U2>::new(1.0, 1.0));
+    let analysis = analyze_observability(&transition_model, &position);
+    assert!(analysis.is_observable());
+    assert_eq!(analysis.rank(), 2);
+    assert!(analysis.unobservable_subspace().is_empty());
+    let golden_ratio = (1.0 + 5.0f64.sqrt()) / 2.0;
+    assert!((analysis.condition_number() - golden_ratio.powi(2)).abs() < 1e-9);
+    assert!(is_detectable(&transition_model, &position));
+
+    // Observing velocity, the position is unobservable and not detectable.
+    let analysis = analyze_observability(&transition_model, &velocity);
+    assert!(!analysis.is_observable());
+    assert_eq!(analysis.rank(), 1);
+    let unobservable = analysis.unobservable_subspace();
+    assert_eq!(unobservable.len(), 1);
+    assert!((unobservable[0][0].abs() - 1.0).abs() < 1e-12);
+    assert!(unobservable[0][1].abs() < 1e-12);
+    assert!(!is_detectable(&transition_model, &velocity));
+
+    // If the position decays, it is unobservable but detectable.
+    let decaying = LinearTransitionModel::new(
+        OMatrix::<f64, U2, U2>::new(0.5, 0.0, 0.0, 1.0),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    assert!(!analyze_observability(&decaying, &velocity).is_observable());
+    assert!(is_detectable(&decaying, &velocity));
+
+    // Time-varying: two observations of position determine the velocity only
+    // if time passes in between.
+    let stationary = LinearTransitionModel::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    let analysis = analyze_observability_time_varying(&[&stationary], &[&position, &position]);
+    assert_eq!(analysis.rank(), 1);
+    let analysis = analyze_observability_time_varying(
+        &[&stationary, &transition_model],
+        &[&position, &position, &position],
+    );
+    assert_eq!(analysis.rank(), 2);
+    assert_eq!(
+        analysis.gramian(),
+        &OMatrix::<f64, U2, U2>::new(3.0, 1.0, 1.0, 1.0)
+    );
+}
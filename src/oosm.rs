@@ -0,0 +1,461 @@
+//! Out-of-sequence measurement handling
+//!
+//! Measurements delivered over a network may arrive after measurements of a
+//! later time have already been processed. The
+//! [`OosmKalmanFilter`](struct.OosmKalmanFilter.html) here keeps a bounded
+//! history of estimates so that such late measurements can still be used.
+
+use std::collections::VecDeque;
+
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    is_missing, is_nan, CoverianceUpdateMethod, Error, ErrorKind, KalmanFilterNoControl,
+    ObservationModelLinear, StateAndCovariance, TransitionModelLinearNoControl,
+};
+
+/// Specifies how an out-of-sequence measurement is incorporated
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OosmStrategy {
+    /// If the measurement is from the previous time step, retrodict the
+    /// current estimate to that time and update the current estimate directly.
+    /// This is exact and costs a single update, regardless of the history
+    /// length. It uses algorithm A1 of Y. Bar-Shalom, "Update with
+    /// out-of-sequence measurements in tracking: exact solution", IEEE
+    /// Transactions on Aerospace and Electronic Systems, 2002.
+    ///
+    /// Measurements with a longer lag, or which arrive when A1 is not exact
+    /// (because the current time step has several measurements or has already
+    /// been corrected by an out-of-sequence measurement, or the transition
+    /// model is not invertible), are handled by `Replay`. So are measurements
+    /// which arrive while the history still contains an earlier estimate not
+    /// corrected by a retrodicted measurement.
+    Retrodiction,
+    /// Rewind to the time of the measurement and replay all later
+    /// measurements. This is exact but its cost grows with the lag.
+    Replay,
+}
+
+/// Options for the [`OosmKalmanFilter`](struct.OosmKalmanFilter.html)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct OosmOptions {
+    /// The strategy for out-of-sequence measurements.
+    pub strategy: OosmStrategy,
+    /// The number of time steps, including the current one, for which
+    /// estimates are kept. Measurements older than this are discarded.
+    pub max_history: usize,
+    /// The covariance update method used by all updates.
+    pub covariance_update_method: CoverianceUpdateMethod,
+}
+
+struct HistoryEntry<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    time: u64,
+    prior: StateAndCovariance<R, SS>,
+    posterior: StateAndCovariance<R, SS>,
+    observations: Vec<OVector<R, OS>>,
+    /// Whether the posterior was corrected by retrodiction, such that it is no
+    /// longer the plain update of the prior.
+    retrodicted: bool,
+    /// Whether the posterior does not include the last of the observations,
+    /// because that was retrodicted to the following time step. The prior is
+    /// still valid, but the prior of the following entry is not.
+    stale: bool,
+}
+
+/// A Kalman filter which accepts out-of-sequence measurements
+///
+/// Time is counted in steps of the transition model, starting from zero for
+/// the initial estimate. Each call to
+/// [`step`](struct.OosmKalmanFilter.html#method.step) advances the time by one
+/// step. A measurement of an earlier time step is incorporated with
+/// [`update_out_of_sequence`](struct.OosmKalmanFilter.html#method.update_out_of_sequence).
+pub struct OosmKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    options: OosmOptions,
+    history: VecDeque<HistoryEntry<R, SS, OS>>,
+}
+
+impl<'a, R, SS, OS> OosmKalmanFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+    DefaultAllocator: Allocator<(usize, usize), SS>,
+{
+    /// Initialize a new `OosmKalmanFilter` struct at time zero.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        initial_estimate: StateAndCovariance<R, SS>,
+        options: OosmOptions,
+    ) -> Self {
+        assert!(options.max_history >= 1);
+        let mut history = VecDeque::with_capacity(options.max_history);
+        history.push_back(HistoryEntry {
+            time: 0,
+            prior: initial_estimate.clone(),
+            posterior: initial_estimate,
+            observations: Vec::new(),
+            retrodicted: false,
+            stale: false,
+        });
+        Self {
+            transition_model,
+            observation_model,
+            options,
+            history,
+        }
+    }
+
+    /// Get the current time step.
+    pub fn time(&self) -> u64 {
+        self.history.back().unwrap().time
+    }
+
+    /// Get the current estimate.
+    pub fn estimate(&self) -> &StateAndCovariance<R, SS> {
+        &self.history.back().unwrap().posterior
+    }
+
+    /// Advance one time step and update with the observation.
+    ///
+    /// If any component of the observation is NaN (not a number), it is
+    /// treated as missing.
+    pub fn step(
+        &mut self,
+        observation: &OVector<R, OS>,
+    ) -> Result<StateAndCovariance<R, SS>, Error> {
+        let kf = KalmanFilterNoControl::new(self.transition_model, self.observation_model);
+        let previous = self.history.back().unwrap();
+        let prior = self.transition_model.predict(&previous.posterior);
+        let posterior = kf.step_with_options(
+            &previous.posterior,
+            observation,
+            self.options.covariance_update_method,
+        )?;
+        let time = previous.time + 1;
+        if self.history.len() == self.options.max_history {
+            self.history.pop_front();
+        }
+        self.history.push_back(HistoryEntry {
+            time,
+            prior,
+            posterior: posterior.clone(),
+            observations: vec![observation.clone()],
+            retrodicted: false,
+            stale: false,
+        });
+        Ok(posterior)
+    }
+
+    /// Incorporate a measurement of an earlier (or the current) time step.
+    ///
+    /// Returns `false`, leaving the filter unchanged, if the measurement is
+    /// older than the history or from the future.
+    pub fn update_out_of_sequence(
+        &mut self,
+        time: u64,
+        observation: &OVector<R, OS>,
+    ) -> Result<bool, Error> {
+        let oldest = self.history.front().unwrap().time;
+        if time < oldest || time > self.time() {
+            return Ok(false);
+        }
+        if is_missing(observation)? {
+            return Ok(true);
+        }
+        let idx = (time - oldest) as usize;
+        let last = self.history.len() - 1;
+
+        if idx == last {
+            // not out of sequence
+            let entry = &mut self.history[last];
+            entry.posterior = self.observation_model.update(
+                &entry.posterior,
+                observation,
+                self.options.covariance_update_method,
+            )?;
+            entry.observations.push(observation.clone());
+            return Ok(true);
+        }
+
+        if self.options.strategy == OosmStrategy::Retrodiction
+            && idx + 1 == last
+            && self.retrodict(observation)?
+        {
+            self.history[idx].observations.push(observation.clone());
+            self.history[idx].stale = true;
+            self.history[last].retrodicted = true;
+            return Ok(true);
+        }
+
+        self.history[idx].observations.push(observation.clone());
+        self.replay_from(idx)?;
+        Ok(true)
+    }
+
+    /// Update the current estimate with a measurement of the previous time
+    /// step using algorithm A1. Returns `false` if A1 is not applicable.
+    fn retrodict(&mut self, observation: &OVector<R, OS>) -> Result<bool, Error> {
+        let current = self.history.back().unwrap();
+        // A stale entry must be recomputed by a replay. Allowing only one
+        // keeps the prior of each stale entry valid.
+        if current.retrodicted
+            || current.observations.len() > 1
+            || self.history.iter().any(|e| e.stale)
+        {
+            return Ok(false);
+        }
+        let f_inv = match self
+            .transition_model
+            .transition_model()
+            .clone()
+            .try_inverse()
+        {
+            Some(v) => v,
+            None => return Ok(false),
+        };
+        let q = self.transition_model.transition_noise_covariance();
+        let h = self.observation_model.observation_matrix();
+        let ht = self.observation_model.observation_matrix_transpose();
+        let p = current.posterior.covariance();
+
+        // Q H' S^-1 applied to the innovation and to H Q, and the prior
+        // covariance P(k|k-1) H' S^-1 H Q, for the current time step. If the
+        // current time step had no measurement, these terms are zero.
+        let (state_correction, qhshq, phshq) = match current
+            .observations
+            .first()
+            .filter(|z| !z.iter().any(|x| is_nan(*x)))
+        {
+            Some(z) => {
+                let innovation = self.observation_model.innovation(&current.prior, z)?;
+                let qhs: OMatrix<R, SS, OS> = q * ht * innovation.covariance_inverse();
+                let phs: OMatrix<R, SS, OS> =
+                    current.prior.covariance() * ht * innovation.covariance_inverse();
+                (&qhs * innovation.residual(), &qhs * h * q, phs * h * q)
+            }
+            None => (
+                OVector::<R, SS>::zeros(),
+                OMatrix::<R, SS, SS>::zeros(),
+                OMatrix::<R, SS, SS>::zeros(),
+            ),
+        };
+        let p_vv = q - qhshq;
+        let p_xv = q - phshq;
+
+        // retrodicted estimate of the previous time step
+        let state_d = &f_inv * (current.posterior.state() - state_correction);
+        let covariance_d = &f_inv * (p + &p_vv - &p_xv - p_xv.transpose()) * f_inv.transpose();
+
+        // covariance between the current state and the late measurement
+        let p_xz: OMatrix<R, SS, OS> = (p - &p_xv) * f_inv.transpose() * ht;
+        let s_d = h * covariance_d * ht + self.observation_model.observation_noise_covariance();
+        let s_chol = na::linalg::Cholesky::new(s_d)
+            .ok_or(ErrorKind::InnovationCovarianceNotPositiveDefinite)?;
+        let gain: OMatrix<R, SS, OS> = &p_xz * s_chol.inverse();
+        let residual = observation - self.observation_model.evaluate(&state_d);
+        let state = current.posterior.state() + &gain * residual;
+        let covariance = p - &gain * p_xz.transpose();
+        let half: R = na::convert(0.5);
+        self.history.back_mut().unwrap().posterior =
+            StateAndCovariance::new(state, (&covariance + covariance.transpose()) * half);
+        Ok(true)
+    }
+
+    /// Update entry `idx` with its last observation, which was just added, and
+    /// then replay all later entries.
+    ///
+    /// If an earlier entry is stale, the replay starts there instead.
+    fn replay_from(&mut self, idx: usize) -> Result<(), Error> {
+        let kf = KalmanFilterNoControl::new(self.transition_model, self.observation_model);
+        let method = self.options.covariance_update_method;
+        let first = match self.history.iter().position(|e| e.stale) {
+            Some(stale) => stale.min(idx),
+            None => idx,
+        };
+        for i in first..self.history.len() {
+            let (observations, start) = if i == first {
+                let entry = &self.history[i];
+                if entry.stale {
+                    // The prior of a stale entry is valid.
+                    (&entry.observations[..], entry.prior.clone())
+                } else {
+                    // The posterior includes all but the new observation. The
+                    // prior may not, if the entry was retrodicted to.
+                    let n = entry.observations.len();
+                    (&entry.observations[n - 1..], entry.posterior.clone())
+                }
+            } else {
+                let previous = self.history[i - 1].posterior.clone();
+                let entry = &mut self.history[i];
+                entry.prior = self.transition_model.predict(&previous);
+                match entry.observations.first() {
+                    Some(first) => {
+                        let posterior = kf.step_with_options(&previous, first, method)?;
+                        (&entry.observations[1..], posterior)
+                    }
+                    None => (&entry.observations[..], entry.prior.clone()),
+                }
+            };
+            let mut posterior = start;
+            for observation in observations.iter() {
+                if !observation.iter().any(|x| is_nan(*x)) {
+                    posterior = self
+                        .observation_model
+                        .update(&posterior, observation, method)?;
+                }
+            }
+            let entry = &mut self.history[i];
+            entry.posterior = posterior;
+            entry.retrodicted = false;
+            entry.stale = false;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_oosm() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{U1, U2};
+
+    // constant velocity, observing position
+    let transition_model = LinearTransitionModel::<f64, U2>::new(
+        OMatrix::<f64, U2, U2>::new(1.0, 0.5, 0.0, 1.0),
+        OMatrix::<f64, U2, U2>::new(0.01, 0.02, 0.02, 0.05),
+    );
+    let observation_model = LinearObservationModel::<f64, U2, U1>::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OMatrix::<f64, U1, U1>::new(0.1),
+    );
+    let initial = StateAndCovariance::new(
+        OVector::<f64, U2>::new(0.0, 1.0),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    let observations: Vec<_> = [0.6, 1.1, 1.4, 2.1, 2.4, 3.2]
+        .iter()
+        .map(|z| OVector::<f64, U1>::new(*z))
+        .collect();
+    let missing = OVector::<f64, U1>::new(f64::NAN);
+
+    let kf = KalmanFilterNoControl::new(&transition_model, &observation_model);
+    let in_order = kf.filter(&initial, &observations).unwrap();
+
+    for strategy in [OosmStrategy::Retrodiction, OosmStrategy::Replay].iter() {
+        let options = OosmOptions {
+            strategy: *strategy,
+            max_history: 4,
+            covariance_update_method: CoverianceUpdateMethod::OptimalKalmanForcedSymmetric,
+        };
+        let mut oosm = OosmKalmanFilter::new(
+            &transition_model,
+            &observation_model,
+            initial.clone(),
+            options,
+        );
+        // Measurements 2 and 4 are delivered late, with lags of one and two.
+        for (i, observation) in observations.iter().enumerate() {
+            let time = i as u64 + 1;
+            let delayed = time == 2 || time == 4;
+            oosm.step(if delayed { &missing } else { observation })
+                .unwrap();
+            if time == 3 {
+                assert!(oosm.update_out_of_sequence(2, &observations[1]).unwrap());
+            }
+            if time == 6 {
+                assert!(oosm.update_out_of_sequence(4, &observations[3]).unwrap());
+            }
+            if time == 3 || time == 6 {
+                let expected = &in_order[i];
+                assert!((oosm.estimate().state() - expected.state()).norm() < 1e-9);
+                assert!((oosm.estimate().covariance() - expected.covariance()).norm() < 1e-9);
+            }
+        }
+        assert_eq!(oosm.time(), 6);
+        // too old for the history
+        assert!(!oosm.update_out_of_sequence(2, &observations[1]).unwrap());
+    }
+}
+
+#[test]
+fn test_oosm_after_retrodiction() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{U1, U2};
+
+    let transition_model = LinearTransitionModel::<f64, U2>::new(
+        OMatrix::<f64, U2, U2>::new(1.0, 0.5, 0.0, 1.0),
+        OMatrix::<f64, U2, U2>::new(0.01, 0.02, 0.02, 0.05),
+    );
+    let observation_model = LinearObservationModel::<f64, U2, U1>::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OMatrix::<f64, U1, U1>::new(0.1),
+    );
+    let initial = StateAndCovariance::new(
+        OVector::<f64, U2>::new(0.0, 1.0),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    let observations: Vec<_> = [0.6, 1.1, 1.4, 2.1, 2.4]
+        .iter()
+        .map(|z| OVector::<f64, U1>::new(*z))
+        .collect();
+    let missing = OVector::<f64, U1>::new(f64::NAN);
+
+    let kf = KalmanFilterNoControl::new(&transition_model, &observation_model);
+    let expected = kf.filter(&initial, &observations).unwrap();
+    let expected = expected.last().unwrap();
+
+    for max_history in [3, 10].iter() {
+        for strategy in [OosmStrategy::Retrodiction, OosmStrategy::Replay].iter() {
+            let options = OosmOptions {
+                strategy: *strategy,
+                max_history: *max_history,
+                covariance_update_method: CoverianceUpdateMethod::OptimalKalmanForcedSymmetric,
+            };
+            let mut oosm = OosmKalmanFilter::new(
+                &transition_model,
+                &observation_model,
+                initial.clone(),
+                options,
+            );
+            // Measurement 2 is retrodicted with A1. Measurement 3 then arrives
+            // with a lag of two, so that the estimate of time 2 is replayed.
+            oosm.step(&observations[0]).unwrap();
+            oosm.step(&missing).unwrap();
+            oosm.step(&missing).unwrap();
+            assert!(oosm.update_out_of_sequence(2, &observations[1]).unwrap());
+            oosm.step(&observations[3]).unwrap();
+            oosm.step(&observations[4]).unwrap();
+            assert!(oosm.update_out_of_sequence(3, &observations[2]).unwrap());
+            assert!((oosm.estimate().state() - expected.state()).norm() < 1e-9);
+            assert!((oosm.estimate().covariance() - expected.covariance()).norm() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! Maximum-likelihood tuning of model parameters by numerical optimization
+//!
+//! The models used by a Kalman filter often depend on a small number of
+//! scalar parameters, such as the noise scale of a motion model or the
+//! variance of an observation model. The functions here find the parameters
+//! which maximize the log-likelihood of recorded observations using the
+//! Nelder-Mead simplex method, which requires no gradients.
+//!
+//! Parameters which must be positive, such as variances, are best optimized
+//! in log space (i.e. the closure mapping parameters to models computes
+//! `p.exp()`), which keeps the search unconstrained.
+
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    Error, KalmanFilterNoControl, ObservationModelLinear, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// Options for the Nelder-Mead simplex method
+#[derive(Debug, Clone)]
+pub struct NelderMeadOptions<R: RealField> {
+    /// Maximum number of iterations.
+    pub max_iterations: usize,
+    /// Convergence is declared when the function values at the vertices of the
+    /// simplex differ by less than this amount...
+    pub function_tolerance: R,
+    /// ...and the vertices are within this distance (in each coordinate) of the
+    /// best vertex.
+    pub parameter_tolerance: R,
+    /// Size of the initial simplex along each coordinate axis.
+    pub initial_step: R,
+}
+
+impl<R: RealField> Default for NelderMeadOptions<R> {
+    fn default() -> Self {
+        Self {
+            max_iterations: 1000,
+            function_tolerance: na::convert(1e-8),
+            parameter_tolerance: na::convert(1e-6),
+            initial_step: na::convert(0.1),
+        }
+    }
+}
+
+/// The result of a numerical minimization
+#[derive(Debug, Clone)]
+pub struct MinimizeResult<R> {
+    /// The best parameters found.
+    pub parameters: Vec<R>,
+    /// The function value at `parameters`.
+    pub value: R,
+    /// The number of iterations performed.
+    pub iterations: usize,
+    /// The number of function evaluations performed.
+    pub function_evaluations: usize,
+    /// Whether the convergence criteria were met.
+    pub converged: bool,
+}
+
+/// The result of [`maximize_likelihood`](fn.maximize_likelihood.html)
+#[derive(Debug, Clone)]
+pub struct MaximumLikelihoodResult<R> {
+    /// The best parameters found.
+    pub parameters: Vec<R>,
+    /// The log-likelihood of the observations at `parameters`.
+    pub log_likelihood: R,
+    /// The number of iterations performed.
+    pub iterations: usize,
+    /// The number of likelihood evaluations performed.
+    pub function_evaluations: usize,
+    /// Whether the convergence criteria were met.
+    pub converged: bool,
+}
+
+/// Minimize a function using the Nelder-Mead simplex method
+///
+/// Function values which are NaN are treated as positive infinity, so a
+/// function may return NaN to reject parameters.
+pub fn nelder_mead<R, F>(
+    mut f: F,
+    initial_parameters: &[R],
+    options: &NelderMeadOptions<R>,
+) -> MinimizeResult<R>
+where
+    R: RealField,
+    F: FnMut(&[R]) -> R,
+{
+    let n = initial_parameters.len();
+    let mut function_evaluations = 0;
+    let mut eval = |x: &[R]| {
+        function_evaluations += 1;
+        let value = f(x);
+        if crate::is_nan(value) {
+            R::max_value()
+        } else {
+            value
+        }
+    };
+
+    // Standard coefficients for reflection, expansion, contraction and shrinking.
+    let alpha = R::one();
+    let gamma: R = na::convert(2.0);
+    let rho: R = na::convert(0.5);
+    let sigma: R = na::convert(0.5);
+
+    let mut simplex: Vec<(Vec<R>, R)> = Vec::with_capacity(n + 1);
+    let x0 = initial_parameters.to_vec();
+    let f0 = eval(&x0);
+    simplex.push((x0, f0));
+    for i in 0..n {
+        let mut x = initial_parameters.to_vec();
+        x[i] += options.initial_step;
+        let fx = eval(&x);
+        simplex.push((x, fx));
+    }
+
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < options.max_iterations {
+        simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        let best = &simplex[0];
+        let worst = &simplex[n];
+        let function_spread = (worst.1 - best.1).abs();
+        let parameter_spread = simplex[1..].iter().fold(R::zero(), |acc, (x, _)| {
+            x.iter()
+                .zip(best.0.iter())
+                .fold(acc, |acc, (a, b)| acc.max((*a - *b).abs()))
+        });
+        if function_spread <= options.function_tolerance
+            && parameter_spread <= options.parameter_tolerance
+        {
+            converged = true;
+            break;
+        }
+        iterations += 1;
+
+        // Centroid of all but the worst vertex.
+        let mut centroid = vec![R::zero(); n];
+        for (x, _) in simplex[..n].iter() {
+            for (c, xi) in centroid.iter_mut().zip(x.iter()) {
+                *c += *xi;
+            }
+        }
+        let n_r: R = na::convert(n as f64);
+        for c in centroid.iter_mut() {
+            *c /= n_r;
+        }
+        let towards = |coef: R, x: &[R]| -> Vec<R> {
+            centroid
+                .iter()
+                .zip(x.iter())
+                .map(|(c, xi)| *c + coef * (*xi - *c))
+                .collect()
+        };
+
+        let reflected = towards(-alpha, &simplex[n].0);
+        let f_reflected = eval(&reflected);
+        if f_reflected < simplex[0].1 {
+            let expanded = towards(-gamma, &simplex[n].0);
+            let f_expanded = eval(&expanded);
+            simplex[n] = if f_expanded < f_reflected {
+                (expanded, f_expanded)
+            } else {
+                (reflected, f_reflected)
+            };
+            continue;
+        }
+        if f_reflected < simplex[n - 1].1 {
+            simplex[n] = (reflected, f_reflected);
+            continue;
+        }
+        let (contracted, f_contracted) = if f_reflected < simplex[n].1 {
+            // Outside contraction.
+            let x = towards(-rho, &simplex[n].0);
+            let fx = eval(&x);
+            (x, fx)
+        } else {
+            // Inside contraction.
+            let x = towards(rho, &simplex[n].0);
+            let fx = eval(&x);
+            (x, fx)
+        };
+        if f_contracted < simplex[n].1.min(f_reflected) {
+            simplex[n] = (contracted, f_contracted);
+            continue;
+        }
+
+        // Shrink towards the best vertex.
+        let best = simplex[0].0.clone();
+        for vertex in simplex[1..].iter_mut() {
+            let x: Vec<R> = best
+                .iter()
+                .zip(vertex.0.iter())
+                .map(|(b, xi)| *b + sigma * (*xi - *b))
+                .collect();
+            let fx = eval(&x);
+            *vertex = (x, fx);
+        }
+    }
+
+    simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    let (parameters, value) = simplex.swap_remove(0);
+    MinimizeResult {
+        parameters,
+        value,
+        iterations,
+        function_evaluations,
+        converged,
+    }
+}
+
+/// Find the model parameters which maximize the likelihood of observations
+///
+/// `model_fn` maps a parameter vector to a transition model and an
+/// observation model. For each candidate parameter vector, the Kalman filter
+/// is run over each sequence of observations (starting from
+/// `initial_estimate`) and the log-likelihoods are summed. The Nelder-Mead
+/// method is used to find the maximum, starting from `initial_parameters`.
+///
+/// Parameters for which the filter fails (e.g. because a covariance matrix is
+/// not positive definite) are treated as having zero likelihood. An error is
+/// returned only if the filter fails with `initial_parameters`.
+pub fn maximize_likelihood<R, SS, OS, T, O, F>(
+    model_fn: F,
+    initial_estimate: &StateAndCovariance<R, SS>,
+    sequences: &[&[OVector<R, OS>]],
+    initial_parameters: &[R],
+    options: &NelderMeadOptions<R>,
+) -> Result<MaximumLikelihoodResult<R>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    T: TransitionModelLinearNoControl<R, SS>,
+    O: ObservationModelLinear<R, SS, OS>,
+    F: Fn(&[R]) -> (T, O),
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    let log_likelihood = |parameters: &[R]| -> Result<R, Error> {
+        let (transition_model, observation_model) = model_fn(parameters);
+        let kf = KalmanFilterNoControl::new(&transition_model, &observation_model);
+        let mut total = R::zero();
+        for observations in sequences.iter() {
+            total += kf.log_likelihood(initial_estimate, observations)?;
+        }
+        Ok(total)
+    };
+
+    // Fail early if the starting point is not usable.
+    log_likelihood(initial_parameters)?;
+
+    let result = nelder_mead(
+        |parameters| match log_likelihood(parameters) {
+            Ok(ll) => -ll,
+            Err(_) => R::max_value(),
+        },
+        initial_parameters,
+        options,
+    );
+    Ok(MaximumLikelihoodResult {
+        parameters: result.parameters,
+        log_likelihood: -result.value,
+        iterations: result.iterations,
+        function_evaluations: result.function_evaluations,
+        converged: result.converged,
+    })
+}
+
+#[test]
+fn test_nelder_mead_rosenbrock() {
+    let result = nelder_mead(
+        |x: &[f64]| (1.0 - x[0]).powi(2) + 100.0 * (x[1] - x[0] * x[0]).powi(2),
+        &[-1.2, 1.0],
+        &NelderMeadOptions {
+            max_iterations: 5000,
+            ..Default::default()
+        },
+    );
+    assert!(result.converged);
+    assert!((result.parameters[0] - 1.0).abs() < 1e-3);
+    assert!((result.parameters[1] - 1.0).abs() < 1e-3);
+}
+
+#[test]
+fn test_maximize_likelihood_agrees_with_em() {
+    use crate::em::{expectation_maximization, EmOptions};
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{OMatrix, U1};
+    use rand_core::SeedableRng;
+
+    let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(987654321);
+    let mut normal = || crate::particle::standard_normal::<f64>(&mut rng);
+    let mut x = 0.0;
+    let mut observations = Vec::new();
+    for _ in 0..300 {
+        x += 0.5f64.sqrt() * normal();
+        observations.push(OVector::<f64, U1>::new(x + 2.0f64.sqrt() * normal()));
+    }
+    let initial_estimate = StateAndCovariance::new(
+        OVector::<f64, U1>::new(0.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    let models = |p: &[f64]| {
+        (
+            LinearTransitionModel::<f64, U1>::new(
+                OMatrix::<f64, U1, U1>::new(1.0),
+                OMatrix::<f64, U1, U1>::new(p[0].exp()),
+            ),
+            LinearObservationModel::<f64, U1, U1>::new(
+                OMatrix::<f64, U1, U1>::new(1.0),
+                OMatrix::<f64, U1, U1>::new(p[1].exp()),
+            ),
+        )
+    };
+    let result = maximize_likelihood(
+        models,
+        &initial_estimate,
+        &[&observations],
+        &[0.0, 0.0],
+        &Default::default(),
+    )
+    .unwrap();
+    assert!(result.converged);
+
+    // With the initial estimate held fixed, EM maximizes the same likelihood.
+    let (tm, om) = models(&[0.0, 0.0]);
+    let em = expectation_maximization(
+        &tm,
+        &om,
+        &initial_estimate,
+        &[&observations],
+        &EmOptions {
+            max_iterations: 1000,
+            tolerance: 1e-10,
+            initial_covariance: crate::em::CovarianceConstraint::Fixed,
+            estimate_initial_state: false,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!((result.log_likelihood - em.log_likelihood).abs() < 1e-3);
+    let q = em.transition_model.transition_noise_covariance()[(0, 0)];
+    assert!((result.parameters[0].exp() - q).abs() / q < 0.05);
+}
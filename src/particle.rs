@@ -0,0 +1,421 @@
+//! Bootstrap particle filter
+//!
+//! For strongly nonlinear or multimodal problems, no Gaussian filter fits. The
+//! [`ParticleFilter`](struct.ParticleFilter.html) here represents the
+//! posterior by a weighted set of samples (particles). Each step, particles are
+//! propagated by sampling from the transition model, reweighted by the
+//! likelihood of the observation and, when the weights have become too
+//! uneven, resampled.
+//!
+//! The random number generator is supplied by the caller as any
+//! [`RngCore`](https://docs.rs/rand_core/0.6/rand_core/trait.RngCore.html), so
+//! results are reproducible with a seeded generator.
+
+use na::allocator::Allocator;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+use rand_core::RngCore;
+
+use crate::{is_nan, outer_product, Error, ErrorKind, StateAndCovariance};
+
+/// A stochastic model of process dynamics from which samples can be drawn
+pub trait TransitionSampler<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Draw a sample of the next state given the current state.
+    fn sample(&self, state: &OVector<R, SS>, rng: &mut dyn RngCore) -> OVector<R, SS>;
+}
+
+impl<R, SS, F> TransitionSampler<R, SS> for F
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS>,
+    F: Fn(&OVector<R, SS>, &mut dyn RngCore) -> OVector<R, SS>,
+{
+    fn sample(&self, state: &OVector<R, SS>, rng: &mut dyn RngCore) -> OVector<R, SS> {
+        self(state, rng)
+    }
+}
+
+/// A model of the probability of an observation given the state
+///
+/// The observation type `O` is arbitrary.
+pub trait ObservationLikelihood<R, SS, O>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Compute the log-likelihood of the observation given the state.
+    ///
+    /// The likelihood only needs to be known up to a constant factor.
+    fn log_likelihood(&self, state: &OVector<R, SS>, observation: &O) -> R;
+}
+
+impl<R, SS, O, F> ObservationLikelihood<R, SS, O> for F
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS>,
+    F: Fn(&OVector<R, SS>, &O) -> R,
+{
+    fn log_likelihood(&self, state: &OVector<R, SS>, observation: &O) -> R {
+        self(state, observation)
+    }
+}
+
+/// Specifies the scheme used to resample particles
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ResamplingMethod {
+    /// Draw each particle independently in proportion to its weight.
+    Multinomial,
+    /// Draw one particle from each of `N` equal strata of the cumulative
+    /// weights, with an independent offset in each stratum.
+    Stratified,
+    /// Like `Stratified`, but with the same offset in each stratum. This has
+    /// the lowest variance and cost, and is the usual choice.
+    Systematic,
+}
+
+/// Draw a uniform random number in `[0, 1)`.
+fn uniform<R: RealField>(rng: &mut dyn RngCore) -> R {
+    // use the upper 53 bits for a double precision mantissa
+    let x = (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    na::convert(x)
+}
+
+/// Draw a standard normal random number using the Box-Muller transform.
+pub(crate) fn standard_normal<R: RealField>(rng: &mut dyn RngCore) -> R {
+    let u1: R = R::one() - uniform::<R>(rng);
+    let u2: R = uniform(rng);
+    (-(u1.ln() + u1.ln())).sqrt() * (R::two_pi() * u2).cos()
+}
+
+/// Select particle indices in proportion to their weights
+///
+/// Returns `n` indices into `weights`. The weights must be non-negative and
+/// should sum to one. Particles of zero weight are never selected.
+pub fn resample<R: RealField>(
+    weights: &[R],
+    n: usize,
+    method: ResamplingMethod,
+    rng: &mut dyn RngCore,
+) -> Vec<usize> {
+    let n_r: R = na::convert(n as f64);
+    // Each scheme draws sorted positions in [0, 1), which are then located in
+    // the cumulative weights.
+    let positions: Vec<R> = match method {
+        ResamplingMethod::Multinomial => {
+            let mut u: Vec<R> = (0..n).map(|_| uniform(rng)).collect();
+            u.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            u
+        }
+        ResamplingMethod::Stratified => (0..n)
+            .map(|i| (na::convert::<_, R>(i as f64) + uniform(rng)) / n_r)
+            .collect(),
+        ResamplingMethod::Systematic => {
+            let offset: R = uniform(rng);
+            (0..n)
+                .map(|i| (na::convert::<_, R>(i as f64) + offset) / n_r)
+                .collect()
+        }
+    };
+
+    // Rounding errors may make the weights sum to slightly less or more than
+    // one. The positions are scaled to the actual sum and, should the
+    // cumulative weights still fall short, the last particle of nonzero weight
+    // is selected.
+    let total = weights.iter().fold(R::zero(), |acc, w| acc + *w);
+    let last = weights
+        .iter()
+        .rposition(|w| *w > R::zero())
+        .unwrap_or(weights.len().saturating_sub(1));
+    let mut indices = Vec::with_capacity(n);
+    let mut cumulative = R::zero();
+    let mut j = 0;
+    for position in positions {
+        let position = position * total;
+        while j < last && cumulative + weights[j] <= position {
+            cumulative += weights[j];
+            j += 1;
+        }
+        indices.push(j);
+    }
+    indices
+}
+
+/// Compute the effective sample size of normalized weights
+///
+/// This is `1 / sum(w^2)`, which ranges from one, when a single particle has
+/// all the weight, to the number of particles, when all weights are equal.
+pub fn effective_sample_size<R: RealField>(weights: &[R]) -> R {
+    let sum_sq = weights.iter().fold(R::zero(), |acc, w| acc + *w * *w);
+    R::one() / sum_sq
+}
+
+/// Options for the [`ParticleFilter`](struct.ParticleFilter.html)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ParticleFilterOptions<R: RealField> {
+    /// The resampling scheme.
+    pub resampling_method: ResamplingMethod,
+    /// Particles are resampled after an update when the effective sample size
+    /// falls below this fraction of the number of particles. Use zero to never
+    /// resample, or one to always resample.
+    pub resampling_threshold: R,
+}
+
+impl<R: RealField> Default for ParticleFilterOptions<R> {
+    fn default() -> Self {
+        Self {
+            resampling_method: ResamplingMethod::Systematic,
+            resampling_threshold: na::convert(0.5),
+        }
+    }
+}
+
+/// A bootstrap (sampling importance resampling) particle filter
+pub struct ParticleFilter<'a, R, SS, O>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    transition: &'a dyn TransitionSampler<R, SS>,
+    likelihood: &'a dyn ObservationLikelihood<R, SS, O>,
+    options: ParticleFilterOptions<R>,
+    particles: Vec<OVector<R, SS>>,
+    weights: Vec<R>,
+}
+
+impl<'a, R, SS, O> ParticleFilter<'a, R, SS, O>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Initialize a new `ParticleFilter` struct with equally weighted
+    /// particles.
+    pub fn new(
+        transition: &'a dyn TransitionSampler<R, SS>,
+        likelihood: &'a dyn ObservationLikelihood<R, SS, O>,
+        particles: Vec<OVector<R, SS>>,
+        options: ParticleFilterOptions<R>,
+    ) -> Self {
+        assert!(!particles.is_empty());
+        let w = R::one() / na::convert(particles.len() as f64);
+        let weights = vec![w; particles.len()];
+        Self {
+            transition,
+            likelihood,
+            options,
+            particles,
+            weights,
+        }
+    }
+
+    /// Get the particles.
+    #[inline]
+    pub fn particles(&self) -> &[OVector<R, SS>] {
+        &self.particles
+    }
+
+    /// Get the normalized weights of the particles.
+    #[inline]
+    pub fn weights(&self) -> &[R] {
+        &self.weights
+    }
+
+    /// Get the effective sample size of the current weights.
+    pub fn effective_sample_size(&self) -> R {
+        effective_sample_size(&self.weights)
+    }
+
+    /// Predict, update with the observation, and resample if needed.
+    ///
+    /// If `observation` is `None`, only the prediction is performed. Returns
+    /// whether the particles were resampled.
+    pub fn step(&mut self, observation: Option<&O>, rng: &mut dyn RngCore) -> Result<bool, Error> {
+        self.predict(rng);
+        match observation {
+            Some(observation) => {
+                self.update(observation)?;
+                let n: R = na::convert(self.particles.len() as f64);
+                if self.effective_sample_size() < self.options.resampling_threshold * n {
+                    self.resample(rng);
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Propagate each particle by sampling from the transition model.
+    pub fn predict(&mut self, rng: &mut dyn RngCore) {
+        for particle in self.particles.iter_mut() {
+            *particle = self.transition.sample(particle, rng);
+        }
+    }
+
+    /// Reweight the particles by the likelihood of the observation.
+    ///
+    /// Returns an error if no particle has a non-zero, finite weight or if any
+    /// log-likelihood is NaN.
+    pub fn update(&mut self, observation: &O) -> Result<(), Error> {
+        let log_weights: Vec<R> = self
+            .particles
+            .iter()
+            .zip(self.weights.iter())
+            .map(|(p, w)| w.ln() + self.likelihood.log_likelihood(p, observation))
+            .collect();
+        // subtract the maximum before exponentiating to avoid underflow
+        let max =
+            log_weights
+                .iter()
+                .skip(1)
+                .fold(log_weights[0], |acc, lw| if *lw > acc { *lw } else { acc });
+        if !max.is_finite() || log_weights.iter().any(|lw| is_nan(*lw)) {
+            return Err(ErrorKind::DegenerateParticleWeights.into());
+        }
+        let mut sum = R::zero();
+        for (w, lw) in self.weights.iter_mut().zip(log_weights) {
+            *w = (lw - max).exp();
+            sum += *w;
+        }
+        for w in self.weights.iter_mut() {
+            *w /= sum;
+        }
+        Ok(())
+    }
+
+    /// Resample the particles and reset the weights to be equal.
+    pub fn resample(&mut self, rng: &mut dyn RngCore) {
+        let n = self.particles.len();
+        let indices = resample(&self.weights, n, self.options.resampling_method, rng);
+        self.particles = indices
+            .into_iter()
+            .map(|i| self.particles[i].clone())
+            .collect();
+        let w = R::one() / na::convert(n as f64);
+        self.weights = vec![w; n];
+    }
+
+    /// Summarize the particles by their weighted mean and covariance.
+    pub fn estimate(&self) -> StateAndCovariance<R, SS> {
+        let mut mean = OVector::<R, SS>::zeros();
+        for (p, w) in self.particles.iter().zip(self.weights.iter()) {
+            mean += p * *w;
+        }
+        let mut covariance = OMatrix::<R, SS, SS>::zeros();
+        for (p, w) in self.particles.iter().zip(self.weights.iter()) {
+            let d = p - &mean;
+            covariance += outer_product(&d, &d) * *w;
+        }
+        StateAndCovariance::new(mean, covariance)
+    }
+}
+
+#[test]
+fn test_resample() {
+    use rand_core::SeedableRng;
+    let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(0);
+    let weights = [0.1, 0.0, 0.6, 0.3];
+    for method in [
+        ResamplingMethod::Multinomial,
+        ResamplingMethod::Stratified,
+        ResamplingMethod::Systematic,
+    ]
+    .iter()
+    {
+        let indices = resample::<f64>(&weights, 10000, *method, &mut rng);
+        let mut counts = [0usize; 4];
+        for i in indices {
+            counts[i] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        for (count, w) in counts.iter().zip(weights.iter()) {
+            assert!((*count as f64 / 10000.0 - w).abs() < 0.02);
+        }
+    }
+    assert!((effective_sample_size(&weights) - 1.0 / 0.46).abs() < 1e-12);
+
+    // Weights which fall short of one never select a particle of zero weight.
+    let short = [0.2, 0.7 - 1e-9, 0.0];
+    for method in [
+        ResamplingMethod::Multinomial,
+        ResamplingMethod::Stratified,
+        ResamplingMethod::Systematic,
+    ]
+    .iter()
+    {
+        let indices = resample::<f64>(&short, 10000, *method, &mut rng);
+        assert!(indices.iter().all(|i| *i < 2));
+        let fraction = indices.iter().filter(|i| **i == 0).count() as f64 / 10000.0;
+        assert!((fraction - 0.2 / 0.9).abs() < 0.02);
+    }
+}
+
+#[test]
+fn test_particle_filter_matches_kalman_filter() {
+    use crate::{KalmanFilterNoControl, LinearObservationModel, LinearTransitionModel};
+    use na::U1;
+    use rand_core::SeedableRng;
+
+    // random walk observed in noise, for which the Kalman filter is exact
+    let q: f64 = 0.1;
+    let r = 0.5;
+    let transition = move |x: &OVector<f64, U1>, rng: &mut dyn RngCore| {
+        OVector::<f64, U1>::new(x[0] + q.sqrt() * standard_normal::<f64>(rng))
+    };
+    let likelihood = move |x: &OVector<f64, U1>, z: &f64| -0.5 * (z - x[0]).powi(2) / r;
+
+    let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(1);
+    let particles: Vec<_> = (0..5000)
+        .map(|_| OVector::<f64, U1>::new(standard_normal(&mut rng)))
+        .collect();
+    let mut pf = ParticleFilter::new(
+        &transition,
+        &likelihood,
+        particles,
+        ParticleFilterOptions::default(),
+    );
+
+    let transition_model = LinearTransitionModel::<f64, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(q),
+    );
+    let observation_model = LinearObservationModel::<f64, U1, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(r),
+    );
+    let kf = KalmanFilterNoControl::new(&transition_model, &observation_model);
+    let mut kf_estimate = StateAndCovariance::new(
+        OVector::<f64, U1>::new(0.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+
+    for z in [0.5, 0.8, 0.4, 1.2, 1.0, 1.5].iter() {
+        pf.step(Some(z), &mut rng).unwrap();
+        kf_estimate = kf.step(&kf_estimate, &OVector::<f64, U1>::new(*z)).unwrap();
+        let pf_estimate = pf.estimate();
+        assert!((pf_estimate.state()[0] - kf_estimate.state()[0]).abs() < 0.05);
+        assert!((pf_estimate.covariance()[0] - kf_estimate.covariance()[0]).abs() < 0.03);
+    }
+    pf.step(None, &mut rng).unwrap();
+
+    // No particle is consistent with an impossible observation.
+    let impossible = |_: &OVector<f64, U1>, _: &f64| f64::NEG_INFINITY;
+    let mut pf = ParticleFilter::new(
+        &transition,
+        &impossible,
+        vec![OVector::<f64, U1>::new(0.0)],
+        ParticleFilterOptions::default(),
+    );
+    assert!(pf.step(Some(&0.0), &mut rng).is_err());
+}
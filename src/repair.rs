@@ -0,0 +1,338 @@
+use na::allocator::Allocator;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use num_traits::identities::One;
+
+/// Maximum number of sweeps of the Jacobi eigenvalue algorithm.
+const MAX_JACOBI_SWEEPS: usize = 50;
+
+/// Maximum number of times the diagonal loading is doubled.
+const MAX_LOADING_DOUBLINGS: usize = 64;
+
+/// Specifies how a covariance matrix is repaired
+///
+/// Numerical errors can make a covariance matrix slightly asymmetric or
+/// indefinite. Each method first symmetrizes the matrix as `(P + P')/2`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum CovarianceRepair<R> {
+    /// Only symmetrize the matrix.
+    Symmetrize,
+    /// Raise all eigenvalues below the given floor to the floor.
+    ClipEigenvalues(R),
+    /// Replace the matrix with the nearest positive semi-definite matrix in
+    /// the Frobenius norm, by setting negative eigenvalues to zero. See N. J.
+    /// Higham, "Computing a nearest symmetric positive semidefinite matrix",
+    /// Linear Algebra and its Applications, 1988.
+    NearestPositiveSemiDefinite,
+    /// Add the given positive multiple of the identity matrix, doubling it
+    /// until the Cholesky decomposition succeeds. Nothing is added to a
+    /// positive definite matrix. If the matrix is still not positive definite
+    /// after 64 doublings, the diagnostic reports it.
+    DiagonalLoading(R),
+}
+
+/// Diagnostic information about a covariance repair
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct CovarianceRepairDiagnostic<R> {
+    method: CovarianceRepair<R>,
+    change: R,
+    positive_definite: bool,
+}
+
+impl<R: RealField> CovarianceRepairDiagnostic<R> {
+    /// Get the repair method used.
+    #[inline]
+    pub fn method(&self) -> CovarianceRepair<R> {
+        self.method
+    }
+    /// Get the size of the change to the covariance matrix as a Frobenius
+    /// norm.
+    #[inline]
+    pub fn change(&self) -> R {
+        self.change
+    }
+    /// Return whether the covariance matrix was changed.
+    #[inline]
+    pub fn is_repaired(&self) -> bool {
+        self.change > R::zero()
+    }
+    /// Return whether the repaired covariance matrix is positive definite,
+    /// such that its Cholesky decomposition succeeds.
+    ///
+    /// Only `DiagonalLoading` aims for a positive definite matrix, so this
+    /// says whether it succeeded. The other methods may leave the matrix
+    /// positive semi-definite.
+    #[inline]
+    pub fn is_positive_definite(&self) -> bool {
+        self.positive_definite
+    }
+}
+
+/// Repair a covariance matrix with the given method.
+///
+/// Returns the repaired matrix and a diagnostic describing the change.
+///
+/// Panics if the initial loading of `CovarianceRepair::DiagonalLoading` is not
+/// positive.
+pub fn repair_covariance<R, SS>(
+    covariance: &OMatrix<R, SS, SS>,
+    method: CovarianceRepair<R>,
+) -> (OMatrix<R, SS, SS>, CovarianceRepairDiagnostic<R>)
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    let half: R = na::convert(0.5);
+    let symmetric = (covariance + covariance.transpose()) * half;
+    let repaired = match method {
+        CovarianceRepair::Symmetrize => symmetric,
+        CovarianceRepair::ClipEigenvalues(floor) => clip_eigenvalues(symmetric, floor),
+        CovarianceRepair::NearestPositiveSemiDefinite => clip_eigenvalues(symmetric, R::zero()),
+        CovarianceRepair::DiagonalLoading(initial) => {
+            assert!(
+                initial > R::zero(),
+                "the initial diagonal loading must be positive"
+            );
+            let mut loading = initial;
+            let mut loaded = symmetric.clone();
+            for _ in 0..MAX_LOADING_DOUBLINGS {
+                if na::linalg::Cholesky::new(loaded.clone()).is_some() {
+                    break;
+                }
+                loaded = &symmetric + OMatrix::<R, SS, SS>::one() * loading;
+                loading *= na::convert(2.0);
+            }
+            loaded
+        }
+    };
+    let change = (&repaired - covariance).norm();
+    let positive_definite = na::linalg::Cholesky::new(repaired.clone()).is_some();
+    (
+        repaired,
+        CovarianceRepairDiagnostic {
+            method,
+            change,
+            positive_definite,
+        },
+    )
+}
+
+/// Raise the eigenvalues of a symmetric matrix to at least `floor`.
+fn clip_eigenvalues<R, SS>(symmetric: OMatrix<R, SS, SS>, floor: R) -> OMatrix<R, SS, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    let (eigenvalues, eigenvectors) = jacobi_eigen(symmetric.clone());
+    if eigenvalues.iter().all(|x| *x >= floor) {
+        return symmetric;
+    }
+    let clipped = eigenvalues.map(|x| if x < floor { floor } else { x });
+    let scaled = OMatrix::<R, SS, SS>::from_fn(|i, j| eigenvectors[(i, j)] * clipped[j]);
+    let result = scaled * eigenvectors.transpose();
+    let half: R = na::convert(0.5);
+    (&result + result.transpose()) * half
+}
+
+/// Estimate the condition number of a symmetric matrix.
+///
+/// This is the ratio of the largest to the smallest absolute eigenvalue. It is
+/// used to describe a matrix which could not be decomposed.
+pub(crate) fn condition_estimate<R, SS>(matrix: &OMatrix<R, SS, SS>) -> f64
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    let half: R = na::convert(0.5);
+    let (eigenvalues, _) = jacobi_eigen((matrix + matrix.transpose()) * half);
+    let largest = eigenvalues
+        .iter()
+        .fold(R::zero(), |acc, x| acc.max(x.abs()));
+    let smallest = eigenvalues
+        .iter()
+        .fold(R::max_value(), |acc, x| acc.min(x.abs()));
+    na::try_convert(largest / smallest).unwrap_or(f64::NAN)
+}
+
+/// Compute the eigenvalues and eigenvectors of a symmetric matrix.
+///
+/// This uses the cyclic Jacobi method, which unlike
+/// `nalgebra::linalg::SymmetricEigen` needs no additional bounds on the
+/// dimension, so that it can be used wherever a covariance matrix is. It is
+/// used for all symmetric eigenvalue problems in this crate.
+pub(crate) fn jacobi_eigen<R, SS>(mut a: OMatrix<R, SS, SS>) -> (OVector<R, SS>, OMatrix<R, SS, SS>)
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    let n = SS::dim();
+    let mut v = OMatrix::<R, SS, SS>::one();
+    let tolerance = R::default_epsilon() * a.norm();
+    let two: R = na::convert(2.0);
+    for _ in 0..MAX_JACOBI_SWEEPS {
+        let mut off_diagonal = R::zero();
+        for p in 0..n {
+            for q in (p + 1)..n {
+                off_diagonal += a[(p, q)] * a[(p, q)];
+            }
+        }
+        if off_diagonal.sqrt() <= tolerance {
+            break;
+        }
+        for p in 0..n {
+            for q in (p + 1)..n {
+                let apq = a[(p, q)];
+                if apq == R::zero() {
+                    continue;
+                }
+                // rotate to zero a[(p, q)]
+                let theta = (a[(q, q)] - a[(p, p)]) / (two * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + R::one()).sqrt());
+                let c = R::one() / (t * t + R::one()).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let akp = a[(k, p)];
+                    let akq = a[(k, q)];
+                    a[(k, p)] = c * akp - s * akq;
+                    a[(k, q)] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let apk = a[(p, k)];
+                    let aqk = a[(q, k)];
+                    a[(p, k)] = c * apk - s * aqk;
+                    a[(q, k)] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let vkp = v[(k, p)];
+                    let vkq = v[(k, q)];
+                    v[(k, p)] = c * vkp - s * vkq;
+                    v[(k, q)] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    (a.diagonal(), v)
+}
+
+#[test]
+fn test_repair_covariance() {
+    use crate::{
+        CoverianceUpdateMethod, KalmanFilterNoControl, LinearObservationModel,
+        LinearTransitionModel, StateAndCovariance,
+    };
+    use na::{U1, U2, U3};
+
+    // Eigen decomposition
+    let m = OMatrix::<f64, U3, U3>::new(4.0, 1.0, 0.5, 1.0, 3.0, 0.2, 0.5, 0.2, 1.0);
+    let (values, vectors) = jacobi_eigen(m);
+    let reconstructed =
+        OMatrix::<f64, U3, U3>::from_fn(|i, j| vectors[(i, j)] * values[j]) * vectors.transpose();
+    assert!((reconstructed - m).abs().max() < 1e-12);
+    assert!(
+        (vectors.transpose() * vectors - OMatrix::<f64, U3, U3>::identity())
+            .abs()
+            .max()
+            < 1e-12
+    );
+
+    // Slightly asymmetric and, once symmetrized, indefinite with eigenvalues
+    // 2.00011 and -0.00011
+    let bad = OMatrix::<f64, U2, U2>::new(1.0, 1.0001, 1.00012, 1.0);
+    let (symmetric, diagnostic) = repair_covariance(&bad, CovarianceRepair::Symmetrize);
+    assert_eq!(symmetric, symmetric.transpose());
+    assert!(diagnostic.is_repaired());
+    let bad = symmetric;
+
+    let (clipped, _) = repair_covariance(&bad, CovarianceRepair::ClipEigenvalues(1e-3));
+    let (values, _) = jacobi_eigen(clipped);
+    assert!((values.min() - 1e-3).abs() < 1e-12);
+
+    // the nearest PSD matrix is the projection onto the eigenvector [1 1]
+    let (nearest, diagnostic) =
+        repair_covariance(&bad, CovarianceRepair::NearestPositiveSemiDefinite);
+    let expected = OMatrix::<f64, U2, U2>::from_element(0.5 * (2.0 + 0.00011));
+    assert!((nearest - expected).abs().max() < 1e-12);
+    assert_eq!(
+        diagnostic.method(),
+        CovarianceRepair::NearestPositiveSemiDefinite
+    );
+    assert!((diagnostic.change() - 0.00011).abs() < 1e-9);
+
+    let (loaded, diagnostic) = repair_covariance(&bad, CovarianceRepair::DiagonalLoading(1e-6));
+    assert!(na::linalg::Cholesky::new(loaded).is_some());
+    assert!(diagnostic.is_positive_definite());
+    // 64 doublings of a tiny loading are not enough
+    let (_, diagnostic) = repair_covariance(&bad, CovarianceRepair::DiagonalLoading(1e-300));
+    assert!(!diagnostic.is_positive_definite());
+    let (unchanged, diagnostic) = repair_covariance(&expected, CovarianceRepair::Symmetrize);
+    assert_eq!(unchanged, expected);
+    assert!(!diagnostic.is_repaired());
+
+    // Automatic repair after the update of an indefinite prior
+    let transition_model = LinearTransitionModel::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::zeros(),
+    );
+    let observation_model = LinearObservationModel::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    let previous = StateAndCovariance::new(OVector::<f64, U2>::zeros(), bad);
+    let observation = OVector::<f64, U1>::new(1.0);
+    let method = CoverianceUpdateMethod::OptimalKalmanForcedSymmetric;
+
+    let kf = KalmanFilterNoControl::new(&transition_model, &observation_model);
+    let (estimate, diagnostic) = kf
+        .step_with_diagnostics(&previous, &observation, method)
+        .unwrap();
+    assert!(diagnostic.is_none());
+    assert!(na::linalg::Cholesky::new(*estimate.covariance()).is_none());
+
+    let kf = kf.with_covariance_repair(CovarianceRepair::NearestPositiveSemiDefinite);
+    let (estimate, diagnostic) = kf
+        .step_with_diagnostics(&previous, &observation, method)
+        .unwrap();
+    assert!(diagnostic.unwrap().is_repaired());
+    let (values, _) = jacobi_eigen(*estimate.covariance());
+    assert!(values.min() > -1e-12);
+    assert_eq!(
+        kf.step(&previous, &observation).unwrap().covariance(),
+        estimate.covariance()
+    );
+}
+
+#[test]
+#[should_panic(expected = "the initial diagonal loading must be positive")]
+fn test_invalid_diagonal_loading() {
+    use crate::{KalmanFilterNoControl, LinearObservationModel, LinearTransitionModel};
+    use na::{U1, U2};
+
+    let transition_model = LinearTransitionModel::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    let observation_model = LinearObservationModel::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    // Rejected when configuring the filter, not at the first update.
+    KalmanFilterNoControl::new(&transition_model, &observation_model)
+        .with_covariance_repair(CovarianceRepair::DiagonalLoading(0.0));
+}
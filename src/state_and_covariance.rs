@@ -3,8 +3,23 @@ use na::{DefaultAllocator, DimName, RealField};
 use na::{OMatrix, OVector};
 use nalgebra as na;
 
+use crate::repair::jacobi_eigen;
+use crate::{outer_product, Error, ErrorKind};
+
 /// State and covariance pair for a given estimate
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "OVector<R, SS>: serde::Serialize, OMatrix<R, SS, SS>: serde::Serialize",
+        deserialize = "OVector<R, SS>: serde::Deserialize<'de>, \
+                       OMatrix<R, SS, SS>: serde::Deserialize<'de>"
+    ))
+)]
 pub struct StateAndCovariance<R, SS>
 where
     R: RealField,
@@ -39,9 +54,64 @@ where
         // I have decided that the computational cost cost is not worth the
         // marginal benefits such testing would bring. If your covariance
         // matrices might not be symmetric and positive semi-definite, test them
-        // prior to this.
+        // prior to this or use `new_checked`.
         Self { state, covariance }
     }
+    /// Create a new `StateAndCovariance` after checking it.
+    ///
+    /// Returns an error if the checks in
+    /// [`validate`](struct.StateAndCovariance.html#method.validate) fail.
+    pub fn new_checked(
+        state: OVector<R, SS>,
+        covariance: OMatrix<R, SS, SS>,
+    ) -> Result<Self, Error> {
+        let result = Self::new(state, covariance);
+        result.validate()?;
+        Ok(result)
+    }
+    /// Check that the state and covariance are valid.
+    ///
+    /// This calls
+    /// [`validate_with_tolerance`](struct.StateAndCovariance.html#method.validate_with_tolerance)
+    /// with a tolerance of `1e-5`.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.validate_with_tolerance(na::convert(1e-5))
+    }
+    /// Check that the state and covariance are valid.
+    ///
+    /// The state and covariance `P` must be finite, the covariance must be
+    /// symmetric, such that the Frobenius norm of `P - P'` is at most
+    /// `tolerance` times that of `P`, and it must be positive semi-definite.
+    /// Positive definite matrices are recognized by the Cholesky
+    /// decomposition. Otherwise, the eigenvalues are computed and may be
+    /// negative only by rounding error. The error returned says which check
+    /// failed.
+    pub fn validate_with_tolerance(&self, tolerance: R) -> Result<(), Error> {
+        if !self.state.iter().all(|x| x.is_finite()) {
+            return Err(ErrorKind::StateNotFinite.into());
+        }
+        let p = &self.covariance;
+        if !p.iter().all(|x| x.is_finite()) {
+            return Err(ErrorKind::CovarianceNotFinite.into());
+        }
+        if (p - p.transpose()).norm() > tolerance * p.norm() {
+            return Err(ErrorKind::CovarianceNotSymmetric.into());
+        }
+        if na::linalg::Cholesky::new(p.clone()).is_some() {
+            return Ok(());
+        }
+        let half: R = na::convert(0.5);
+        let (eigenvalues, _) = jacobi_eigen((p + p.transpose()) * half);
+        let largest = eigenvalues
+            .iter()
+            .fold(R::zero(), |acc, x| acc.max(x.abs()));
+        let tolerance = largest * R::default_epsilon().sqrt();
+        if eigenvalues.iter().all(|x| *x >= -tolerance) {
+            Ok(())
+        } else {
+            Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into())
+        }
+    }
     #[inline]
     pub fn state(&self) -> &OVector<R, SS> {
         &self.state
@@ -50,4 +120,86 @@ where
     pub fn covariance(&self) -> &OMatrix<R, SS, SS> {
         &self.covariance
     }
+    /// Approximate a Gaussian mixture by a single Gaussian with the same moments.
+    ///
+    /// Each component in `components` is weighted by the corresponding entry
+    /// of `weights`. The weights are normalized to sum to one. The returned
+    /// covariance includes the spread of the component means.
+    ///
+    /// Panics if the slices differ in length or are empty.
+    pub fn mixture(weights: &[R], components: &[Self]) -> Self {
+        assert_eq!(weights.len(), components.len());
+        assert!(!components.is_empty());
+        let total = weights.iter().fold(R::zero(), |acc, w| acc + *w);
+        let mut state = OVector::<R, SS>::zeros();
+        for (w, c) in weights.iter().zip(components.iter()) {
+            state += c.state() * (*w / total);
+        }
+        let mut covariance = OMatrix::<R, SS, SS>::zeros();
+        for (w, c) in weights.iter().zip(components.iter()) {
+            let d = c.state() - &state;
+            covariance += (c.covariance() + outer_product(&d, &d)) * (*w / total);
+        }
+        Self { state, covariance }
+    }
+}
+
+#[test]
+fn test_validate() {
+    use na::{U2, U3};
+
+    let state = OVector::<f64, U2>::new(1.0, 2.0);
+    // positive definite
+    let valid = OMatrix::<f64, U2, U2>::new(2.0, 0.5, 0.5, 1.0);
+    assert!(StateAndCovariance::new_checked(state, valid).is_ok());
+    // positive semi-definite but singular, which fails the Cholesky
+    // decomposition
+    let singular = OMatrix::<f64, U2, U2>::new(1.0, 1.0, 1.0, 1.0);
+    assert!(StateAndCovariance::new_checked(state, singular).is_ok());
+    let zero = OMatrix::<f64, U3, U3>::zeros();
+    assert!(StateAndCovariance::new(OVector::<f64, U3>::zeros(), zero)
+        .validate()
+        .is_ok());
+
+    // asymmetry which is small compared to the matrix
+    let large = OMatrix::<f64, U2, U2>::new(1e6, 0.0, 1e-12, 1e6);
+    assert!(StateAndCovariance::new_checked(state, large).is_ok());
+    let slightly_asymmetric = OMatrix::<f64, U2, U2>::new(1.0, 0.5, 0.5 + 1e-4, 1.0);
+    assert!(StateAndCovariance::new(state, slightly_asymmetric)
+        .validate_with_tolerance(1e-3)
+        .is_ok());
+
+    let kind = |result: Result<StateAndCovariance<f64, U2>, Error>| *result.unwrap_err().kind();
+    assert_eq!(
+        kind(StateAndCovariance::new_checked(
+            OVector::<f64, U2>::new(f64::NAN, 0.0),
+            valid
+        )),
+        ErrorKind::StateNotFinite
+    );
+    assert_eq!(
+        kind(StateAndCovariance::new_checked(
+            state,
+            OMatrix::<f64, U2, U2>::new(f64::INFINITY, 0.0, 0.0, 1.0)
+        )),
+        ErrorKind::CovarianceNotFinite
+    );
+    assert_eq!(
+        kind(StateAndCovariance::new_checked(
+            state,
+            OMatrix::<f64, U2, U2>::new(1.0, 0.5, 0.4, 1.0)
+        )),
+        ErrorKind::CovarianceNotSymmetric
+    );
+    assert_eq!(
+        kind(StateAndCovariance::new_checked(state, slightly_asymmetric)),
+        ErrorKind::CovarianceNotSymmetric
+    );
+    assert_eq!(
+        kind(StateAndCovariance::new_checked(
+            state,
+            OMatrix::<f64, U2, U2>::new(1.0, 2.0, 2.0, 1.0)
+        )),
+        ErrorKind::CovarianceNotPositiveSemiDefinite
+    );
 }
@@ -0,0 +1,324 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use num_traits::identities::One;
+
+use crate::{is_missing, Error, ErrorKind, ObservationModelLinear, TransitionModelLinearNoControl};
+
+/// Maximum number of doubling iterations when solving the Riccati equation.
+const MAX_DOUBLING_ITERATIONS: usize = 100;
+
+/// The steady-state solution of a time-invariant Kalman filter
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "OMatrix<R, SS, SS>: serde::Serialize, OMatrix<R, SS, OS>: serde::Serialize",
+        deserialize = "OMatrix<R, SS, SS>: serde::Deserialize<'de>, \
+                       OMatrix<R, SS, OS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct SteadyStateSolution<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+{
+    prior_covariance: OMatrix<R, SS, SS>,
+    posterior_covariance: OMatrix<R, SS, SS>,
+    gain: OMatrix<R, SS, OS>,
+}
+
+impl<R, SS, OS> SteadyStateSolution<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+{
+    /// Get the steady-state prior covariance, the solution of the Riccati
+    /// equation.
+    #[inline]
+    pub fn prior_covariance(&self) -> &OMatrix<R, SS, SS> {
+        &self.prior_covariance
+    }
+    /// Get the steady-state posterior covariance.
+    #[inline]
+    pub fn posterior_covariance(&self) -> &OMatrix<R, SS, SS> {
+        &self.posterior_covariance
+    }
+    /// Get the steady-state Kalman gain `K`.
+    #[inline]
+    pub fn gain(&self) -> &OMatrix<R, SS, OS> {
+        &self.gain
+    }
+}
+
+/// Solve the discrete algebraic Riccati equation (DARE) of a Kalman filter
+///
+/// Finds the steady-state prior covariance `P` satisfying
+///
+/// ```text
+/// P = F P F' - F P H' (H P H' + R)^-1 H P F' + Q
+/// ```
+///
+/// and the corresponding posterior covariance and Kalman gain. The solution
+/// exists if `(F, H)` is detectable and `(F, Q)` is stabilizable, and `R`
+/// must be positive definite.
+///
+/// The equation is solved with the structure-preserving doubling algorithm,
+/// which converges quadratically. See E. K.-W. Chu, H.-Y. Fan, W.-W. Lin and
+/// C.-S. Wang, "Structure-preserving algorithms for periodic discrete-time
+/// algebraic Riccati equations", International Journal of Control, 2004.
+pub fn solve_dare<R, SS, OS>(
+    transition_model: &dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &dyn ObservationModelLinear<R, SS, OS>,
+) -> Result<SteadyStateSolution<R, SS, OS>, Error>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+    DefaultAllocator: Allocator<(usize, usize), SS>,
+{
+    let h_obs = observation_model.observation_matrix();
+    let ht_obs = observation_model.observation_matrix_transpose();
+    let r_inv =
+        match na::linalg::Cholesky::new(observation_model.observation_noise_covariance().clone()) {
+            Some(v) => v.inverse(),
+            None => {
+                return Err(ErrorKind::CovarianceNotPositiveSemiDefinite.into());
+            }
+        };
+
+    // Iterate X = A' X (I + G X)^-1 A + H with A = F', G = H' R^-1 H and
+    // H = Q, for which X is the prior covariance.
+    let identity = OMatrix::<R, SS, SS>::one();
+    let mut a = transition_model.transition_model_transpose().clone();
+    let mut g = ht_obs * r_inv * h_obs;
+    let mut h = transition_model.transition_noise_covariance().clone();
+    let tolerance = R::default_epsilon().sqrt();
+    let mut converged = false;
+    for _ in 0..MAX_DOUBLING_ITERATIONS {
+        let w_inv = match (&identity + &g * &h).try_inverse() {
+            Some(v) => v,
+            None => {
+                return Err(ErrorKind::DidNotConverge.into());
+            }
+        };
+        let w_inv_a = &w_inv * &a;
+        let h_next = &h + a.transpose() * &h * &w_inv_a;
+        let g_next = &g + &a * &w_inv * &g * a.transpose();
+        a = &a * w_inv_a;
+        g = g_next;
+        let change = (&h_next - &h).norm();
+        h = h_next;
+        // Because of the quadratic convergence, the error of the result is
+        // much smaller than the change in the last iteration.
+        if change <= tolerance * h.norm() {
+            converged = true;
+            break;
+        }
+    }
+    if !converged {
+        return Err(ErrorKind::DidNotConverge.into());
+    }
+
+    let half: R = na::convert(0.5);
+    let prior_covariance = (&h + h.transpose()) * half;
+    let s = h_obs * &prior_covariance * ht_obs + observation_model.observation_noise_covariance();
+    let s_inv = match na::linalg::Cholesky::new(s) {
+        Some(v) => v.inverse(),
+        None => {
+            return Err(ErrorKind::InnovationCovarianceNotPositiveDefinite.into());
+        }
+    };
+    let gain = &prior_covariance * ht_obs * s_inv;
+    let posterior_covariance = (&identity - &gain * h_obs) * &prior_covariance;
+    let posterior_covariance = (&posterior_covariance + posterior_covariance.transpose()) * half;
+    Ok(SteadyStateSolution {
+        prior_covariance,
+        posterior_covariance,
+        gain,
+    })
+}
+
+/// A Kalman filter with a constant, steady-state gain
+///
+/// For a time-invariant system, the covariance and gain of the Kalman filter
+/// converge to the solution of the Riccati equation (see
+/// [`solve_dare`](fn.solve_dare.html)). This filter uses the steady-state gain
+/// from the start, so that each step takes two matrix-vector products and no
+/// matrix inversions. After the initial transient, its estimates are the same
+/// as those of [`KalmanFilterNoControl`](struct.KalmanFilterNoControl.html).
+///
+/// Only state vectors are propagated. The observation model must be linear.
+pub struct SteadyStateKalmanFilter<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+{
+    transition_model: OMatrix<R, SS, SS>,
+    // (I - K H) F
+    update_model: OMatrix<R, SS, SS>,
+    solution: SteadyStateSolution<R, SS, OS>,
+}
+
+impl<R, SS, OS> SteadyStateKalmanFilter<R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+    DefaultAllocator: Allocator<(usize, usize), SS>,
+{
+    /// Initialize a new `SteadyStateKalmanFilter` by solving the Riccati
+    /// equation.
+    pub fn new(
+        transition_model: &dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &dyn ObservationModelLinear<R, SS, OS>,
+    ) -> Result<Self, Error> {
+        let solution = solve_dare(transition_model, observation_model)?;
+        Ok(Self::from_solution(
+            transition_model,
+            observation_model,
+            solution,
+        ))
+    }
+
+    /// Initialize a new `SteadyStateKalmanFilter` from an existing solution.
+    pub fn from_solution(
+        transition_model: &dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &dyn ObservationModelLinear<R, SS, OS>,
+        solution: SteadyStateSolution<R, SS, OS>,
+    ) -> Self {
+        let f = transition_model.transition_model().clone();
+        let kh = solution.gain() * observation_model.observation_matrix();
+        let update_model = (OMatrix::<R, SS, SS>::one() - kh) * &f;
+        Self {
+            transition_model: f,
+            update_model,
+            solution,
+        }
+    }
+
+    /// Get the steady-state solution.
+    #[inline]
+    pub fn solution(&self) -> &SteadyStateSolution<R, SS, OS> {
+        &self.solution
+    }
+
+    /// Perform prediction and update steps with the steady-state gain.
+    ///
+    /// Computes `x = (I - K H) F x_prev + K z`. If any component of the
+    /// observation is NaN (not a number), the predicted state `F x_prev` is
+    /// returned.
+    pub fn step(
+        &self,
+        previous_state: &OVector<R, SS>,
+        observation: &OVector<R, OS>,
+    ) -> Result<OVector<R, SS>, Error> {
+        if is_missing(observation)? {
+            Ok(&self.transition_model * previous_state)
+        } else {
+            Ok(&self.update_model * previous_state + self.solution.gain() * observation)
+        }
+    }
+}
+
+#[test]
+fn test_steady_state() {
+    use crate::{KalmanFilterNoControl, LinearObservationModel, LinearTransitionModel};
+    use crate::{StateAndCovariance, TransitionModelLinearNoControl};
+    use na::{U1, U2};
+
+    // Constant velocity model observing position.
+    let dt = 0.1;
+    let transition_model = LinearTransitionModel::new(
+        OMatrix::<f64, U2, U2>::new(1.0, dt, 0.0, 1.0),
+        OMatrix::<f64, U2, U2>::new(dt.powi(3) / 3.0, dt.powi(2) / 2.0, dt.powi(2) / 2.0, dt),
+    );
+    let observation_model = LinearObservationModel::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OMatrix::<f64, U1, U1>::new(0.01),
+    );
+    let solution = solve_dare(&transition_model, &observation_model).unwrap();
+
+    // The solution satisfies the Riccati equation.
+    let p = solution.prior_covariance();
+    let predicted = transition_model.predict(&StateAndCovariance::new(
+        OVector::<f64, U2>::zeros(),
+        *solution.posterior_covariance(),
+    ));
+    assert!((predicted.covariance() - p).abs().max() < 1e-12);
+
+    // The covariance of the time-varying filter converges to the solution.
+    let kf = KalmanFilterNoControl::new(&transition_model, &observation_model);
+    let observation = OVector::<f64, U1>::new(0.0);
+    let mut estimate = StateAndCovariance::new(
+        OVector::<f64, U2>::zeros(),
+        OMatrix::<f64, U2, U2>::identity(),
+    );
+    for _ in 0..1000 {
+        estimate = kf.step(&estimate, &observation).unwrap();
+    }
+    assert!(
+        (estimate.covariance() - solution.posterior_covariance())
+            .abs()
+            .max()
+            < 1e-10
+    );
+
+    // Started at the steady state, the estimates are the same.
+    let ssf = SteadyStateKalmanFilter::new(&transition_model, &observation_model).unwrap();
+    let mut estimate = StateAndCovariance::new(
+        OVector::<f64, U2>::new(1.0, -1.0),
+        *solution.posterior_covariance(),
+    );
+    let mut state = *estimate.state();
+    for z in [0.9, 0.85, 0.7, 0.66].iter() {
+        let observation = OVector::<f64, U1>::new(*z);
+        estimate = kf.step(&estimate, &observation).unwrap();
+        state = ssf.step(&state, &observation).unwrap();
+        assert!((estimate.state() - state).abs().max() < 1e-10);
+    }
+    let predicted = ssf
+        .step(&state, &OVector::<f64, U1>::new(f64::NAN))
+        .unwrap();
+    assert!(
+        (predicted - transition_model.transition_model() * state)
+            .abs()
+            .max()
+            < 1e-15
+    );
+    assert_eq!(
+        ssf.step(&state, &OVector::<f64, U1>::new(f64::INFINITY))
+            .unwrap_err()
+            .kind(),
+        &ErrorKind::NonFiniteInput
+    );
+}
@@ -0,0 +1,126 @@
+use na::RealField;
+use nalgebra as na;
+
+/// Solve a linear assignment problem with the Hungarian algorithm
+///
+/// `cost[i][j]` is the cost of assigning row `i` to column `j`. All rows must
+/// have the same length. Every row is assigned to a distinct column (or, if
+/// there are more rows than columns, every column to a distinct row) such that
+/// the total cost is minimized. The result has one entry per row with the
+/// assigned column, if any.
+///
+/// This is the O(n^2 m) shortest augmenting path formulation of the algorithm
+/// using row and column potentials.
+pub fn solve_assignment<R: RealField>(cost: &[Vec<R>]) -> Vec<Option<usize>> {
+    let n_rows = cost.len();
+    if n_rows == 0 {
+        return Vec::new();
+    }
+    let n_cols = cost[0].len();
+    for row in cost.iter() {
+        assert_eq!(row.len(), n_cols);
+    }
+    if n_cols == 0 {
+        return vec![None; n_rows];
+    }
+
+    if n_rows > n_cols {
+        // Solve the transposed problem, which has fewer rows than columns.
+        let transposed: Vec<Vec<R>> = (0..n_cols)
+            .map(|j| (0..n_rows).map(|i| cost[i][j]).collect())
+            .collect();
+        let col_assignment = solve_assignment(&transposed);
+        let mut result = vec![None; n_rows];
+        for (j, i) in col_assignment.into_iter().enumerate() {
+            if let Some(i) = i {
+                result[i] = Some(j);
+            }
+        }
+        return result;
+    }
+
+    let n = n_rows;
+    let m = n_cols;
+    let infinity = R::max_value();
+    // Arrays are 1-indexed; index 0 is a sentinel.
+    let mut u = vec![R::zero(); n + 1];
+    let mut v = vec![R::zero(); m + 1];
+    // row assigned to each column
+    let mut p = vec![0usize; m + 1];
+    let mut way = vec![0usize; m + 1];
+
+    for i in 1..=n {
+        p[0] = i;
+        let mut j0 = 0;
+        let mut minv = vec![infinity; m + 1];
+        let mut used = vec![false; m + 1];
+        loop {
+            used[j0] = true;
+            let i0 = p[j0];
+            let mut delta = infinity;
+            let mut j1 = 0;
+            for j in 1..=m {
+                if !used[j] {
+                    let cur = cost[i0 - 1][j - 1] - u[i0] - v[j];
+                    if cur < minv[j] {
+                        minv[j] = cur;
+                        way[j] = j0;
+                    }
+                    if minv[j] < delta {
+                        delta = minv[j];
+                        j1 = j;
+                    }
+                }
+            }
+            for j in 0..=m {
+                if used[j] {
+                    u[p[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    minv[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if p[j0] == 0 {
+                break;
+            }
+        }
+        loop {
+            let j1 = way[j0];
+            p[j0] = p[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut result = vec![None; n];
+    for j in 1..=m {
+        if p[j] != 0 {
+            result[p[j] - 1] = Some(j - 1);
+        }
+    }
+    result
+}
+
+#[test]
+fn test_solve_assignment() {
+    let cost = vec![
+        vec![4.0, 1.0, 3.0],
+        vec![2.0, 0.0, 5.0],
+        vec![3.0, 2.0, 2.0],
+    ];
+    assert_eq!(
+        solve_assignment::<f64>(&cost),
+        vec![Some(1), Some(0), Some(2)]
+    );
+
+    // More rows than columns.
+    let cost = vec![vec![1.0], vec![0.5], vec![2.0]];
+    assert_eq!(solve_assignment::<f64>(&cost), vec![None, Some(0), None]);
+
+    // More columns than rows.
+    let cost = vec![vec![5.0, 1.0, 3.0]];
+    assert_eq!(solve_assignment::<f64>(&cost), vec![Some(1)]);
+}
@@ -0,0 +1,430 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OMatrix, OVector, RealField};
+use nalgebra as na;
+
+use super::check_detections;
+use crate::{
+    outer_product, Error, Innovation, ObservationModelLinear, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// Parameters of the Joint Probabilistic Data Association filter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JpdaParameters<R: RealField> {
+    /// Probability that a target is detected in a given frame, in `(0, 1]`.
+    pub detection_probability: R,
+    /// Spatial density of clutter (false) detections, in detections per unit
+    /// volume of observation space. Must be positive.
+    pub clutter_density: R,
+    /// A detection can only originate from a track if its squared Mahalanobis
+    /// distance from the track's predicted observation is at most this value.
+    /// Must be positive.
+    pub gate_threshold: R,
+}
+
+/// The result of a JPDA update for one track
+#[derive(Debug, Clone)]
+pub struct JpdaUpdate<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// The posterior state estimate.
+    pub estimate: StateAndCovariance<R, SS>,
+    /// Probability that each detection originated from this track.
+    pub association_probabilities: Vec<R>,
+    /// Probability that none of the detections originated from this track.
+    pub miss_probability: R,
+}
+
+/// A Joint Probabilistic Data Association (JPDA) filter
+///
+/// Rather than assigning each detection to at most one track, JPDA updates
+/// each track with all detections in its gate, weighted by the probability
+/// that the detection originated from the track. These probabilities are
+/// computed by enumerating all feasible joint association events, in which
+/// each detection originates from at most one track (or from clutter) and
+/// each track produces at most one detection. See Y. Bar-Shalom, F. Daum and
+/// J. Huang, "The probabilistic data association filter", IEEE Control
+/// Systems Magazine, 2009.
+///
+/// The number of joint events grows combinatorially with the number of tracks
+/// sharing detections, so this is suitable for small clusters of tracks.
+pub struct JpdaFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    parameters: JpdaParameters<R>,
+}
+
+impl<'a, R, SS, OS> JpdaFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `JpdaFilter` struct.
+    ///
+    /// Panics if the detection probability is not in `(0, 1]` or the clutter
+    /// density or gate threshold is not positive.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        parameters: JpdaParameters<R>,
+    ) -> Self {
+        assert!(
+            parameters.detection_probability > R::zero()
+                && parameters.detection_probability <= R::one(),
+            "the detection probability must be in (0, 1]"
+        );
+        assert!(
+            parameters.clutter_density > R::zero(),
+            "the clutter density must be positive"
+        );
+        assert!(
+            parameters.gate_threshold > R::zero(),
+            "the gate threshold must be positive"
+        );
+        Self {
+            transition_model,
+            observation_model,
+            parameters,
+        }
+    }
+
+    /// Predict each track forward one time step and update with the detections.
+    ///
+    /// Returns one update per entry of `previous_estimates`, in the same order.
+    pub fn step(
+        &self,
+        previous_estimates: &[StateAndCovariance<R, SS>],
+        detections: &[OVector<R, OS>],
+    ) -> Result<Vec<JpdaUpdate<R, SS>>, Error> {
+        let priors: Vec<_> = previous_estimates
+            .iter()
+            .map(|e| self.transition_model.predict(e))
+            .collect();
+        self.update(&priors, detections)
+    }
+
+    /// Update the prior estimate of each track with the detections.
+    ///
+    /// Returns one update per entry of `priors`, in the same order. If no joint
+    /// association event has a positive and finite probability, e.g. because
+    /// the likelihood ratios underflow or overflow, every track keeps its prior
+    /// with a miss probability of one.
+    pub fn update(
+        &self,
+        priors: &[StateAndCovariance<R, SS>],
+        detections: &[OVector<R, OS>],
+    ) -> Result<Vec<JpdaUpdate<R, SS>>, Error> {
+        check_detections(detections)?;
+        let p_d = self.parameters.detection_probability;
+
+        // Innovation of every gated track-detection pair and its likelihood
+        // ratio against clutter.
+        let mut innovations: Vec<Vec<Option<Innovation<R, OS>>>> = Vec::with_capacity(priors.len());
+        let mut ratios: Vec<Vec<Option<R>>> = Vec::with_capacity(priors.len());
+        for prior in priors.iter() {
+            let mut innovation_row = Vec::with_capacity(detections.len());
+            let mut ratio_row = Vec::with_capacity(detections.len());
+            if let Some(first) = detections.first() {
+                let base = self.observation_model.innovation(prior, first)?;
+                let predicted = self.observation_model.evaluate(prior.state());
+                for detection in detections.iter() {
+                    let innovation = base.with_residual(detection - &predicted);
+                    if innovation.mahalanobis_squared() <= self.parameters.gate_threshold {
+                        ratio_row.push(Some(
+                            p_d * innovation.log_likelihood().exp()
+                                / self.parameters.clutter_density,
+                        ));
+                        innovation_row.push(Some(innovation));
+                    } else {
+                        ratio_row.push(None);
+                        innovation_row.push(None);
+                    }
+                }
+            }
+            innovations.push(innovation_row);
+            ratios.push(ratio_row);
+        }
+
+        // Sum the probabilities of all feasible joint events.
+        let mut beta = vec![vec![R::zero(); detections.len() + 1]; priors.len()];
+        let mut used = vec![false; detections.len()];
+        let mut event = vec![None; priors.len()];
+        let total = enumerate_events(
+            &ratios,
+            R::one() - p_d,
+            0,
+            R::one(),
+            &mut used,
+            &mut event,
+            &mut beta,
+        );
+
+        if !(total > R::zero() && total.is_finite()) {
+            return Ok(priors
+                .iter()
+                .map(|prior| JpdaUpdate {
+                    estimate: prior.clone(),
+                    association_probabilities: vec![R::zero(); detections.len()],
+                    miss_probability: R::one(),
+                })
+                .collect());
+        }
+
+        let mut result = Vec::with_capacity(priors.len());
+        for ((prior, row), beta_row) in priors.iter().zip(innovations.iter()).zip(beta.iter()) {
+            let miss_probability = beta_row[0] / total;
+            let association_probabilities: Vec<R> =
+                beta_row[1..].iter().map(|b| *b / total).collect();
+
+            let gated: Vec<(&Innovation<R, OS>, R)> = row
+                .iter()
+                .zip(association_probabilities.iter())
+                .filter_map(|(x, b)| x.as_ref().map(|innovation| (innovation, *b)))
+                .collect();
+            let estimate = match gated.first() {
+                None => prior.clone(),
+                Some((first, _)) => {
+                    let k_gain: OMatrix<R, SS, OS> = prior.covariance()
+                        * self.observation_model.observation_matrix_transpose()
+                        * first.covariance_inverse();
+                    let mut combined = OVector::<R, OS>::zeros();
+                    let mut spread = OMatrix::<R, OS, OS>::zeros();
+                    for (innovation, b) in gated.iter() {
+                        combined += innovation.residual() * *b;
+                        spread += outer_product(innovation.residual(), innovation.residual()) * *b;
+                    }
+                    spread -= outer_product(&combined, &combined);
+
+                    let state = prior.state() + &k_gain * combined;
+                    // covariance if the correct detection were known
+                    let updated_covariance =
+                        prior.covariance() - &k_gain * first.covariance() * k_gain.transpose();
+                    let covariance = prior.covariance() * miss_probability
+                        + updated_covariance * (R::one() - miss_probability)
+                        + &k_gain * spread * k_gain.transpose();
+                    let half: R = na::convert(0.5);
+                    StateAndCovariance::new(state, (&covariance + covariance.transpose()) * half)
+                }
+            };
+            result.push(JpdaUpdate {
+                estimate,
+                association_probabilities,
+                miss_probability,
+            });
+        }
+        Ok(result)
+    }
+}
+
+/// Recursively enumerate joint association events, accumulating the
+/// unnormalized probability of each track-detection pairing in `beta`.
+///
+/// `beta[t][0]` accumulates events in which track `t` is not detected and
+/// `beta[t][j+1]` events in which detection `j` originates from track `t`.
+/// Returns the total unnormalized probability of all events.
+///
+/// A track without gated detections is not detected in any event, so its miss
+/// weight is a common factor of all events, which cancels on normalization. It
+/// is left out, so that it does not zero all events when the detection
+/// probability is one.
+fn enumerate_events<R: RealField>(
+    ratios: &[Vec<Option<R>>],
+    miss_weight: R,
+    track: usize,
+    weight: R,
+    used: &mut [bool],
+    event: &mut [Option<usize>],
+    beta: &mut [Vec<R>],
+) -> R {
+    if track == ratios.len() {
+        for (t, assigned) in event.iter().enumerate() {
+            match assigned {
+                Some(j) => beta[t][j + 1] += weight,
+                None => beta[t][0] += weight,
+            }
+        }
+        return weight;
+    }
+    event[track] = None;
+    let miss = if ratios[track].iter().all(Option::is_none) {
+        R::one()
+    } else {
+        miss_weight
+    };
+    let mut total = enumerate_events(
+        ratios,
+        miss_weight,
+        track + 1,
+        weight * miss,
+        used,
+        event,
+        beta,
+    );
+    for (j, ratio) in ratios[track].iter().enumerate() {
+        if let Some(ratio) = ratio {
+            if !used[j] {
+                used[j] = true;
+                event[track] = Some(j);
+                total += enumerate_events(
+                    ratios,
+                    miss_weight,
+                    track + 1,
+                    weight * *ratio,
+                    used,
+                    event,
+                    beta,
+                );
+                used[j] = false;
+            }
+        }
+    }
+    event[track] = None;
+    total
+}
+
+#[test]
+fn test_jpda() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::U1;
+
+    let transition_model = LinearTransitionModel::<f64, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(0.0),
+    );
+    let observation_model = LinearObservationModel::<f64, U1, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    let parameters = JpdaParameters {
+        detection_probability: 0.9,
+        clutter_density: 0.01,
+        gate_threshold: 16.0,
+    };
+    let jpda = JpdaFilter::new(&transition_model, &observation_model, parameters);
+    let prior = StateAndCovariance::new(
+        OVector::<f64, U1>::new(0.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+
+    // With a single track, JPDA reduces to PDA.
+    let detections = vec![OVector::<f64, U1>::new(0.5), OVector::<f64, U1>::new(1.5)];
+    let updates = jpda
+        .update(core::slice::from_ref(&prior), &detections)
+        .unwrap();
+    let s: f64 = 2.0;
+    let l =
+        |v: f64| 0.9 * (-0.5 * v * v / s).exp() / (2.0 * std::f64::consts::PI * s).sqrt() / 0.01;
+    let total = 0.1 + l(0.5) + l(1.5);
+    assert!((updates[0].miss_probability - 0.1 / total).abs() < 1e-12);
+    assert!((updates[0].association_probabilities[0] - l(0.5) / total).abs() < 1e-12);
+    assert!((updates[0].association_probabilities[1] - l(1.5) / total).abs() < 1e-12);
+
+    // Two tracks between two detections: the probabilities of each track sum
+    // to one and each detection is shared symmetrically.
+    let other = StateAndCovariance::new(
+        OVector::<f64, U1>::new(2.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    let detections = vec![OVector::<f64, U1>::new(0.9), OVector::<f64, U1>::new(1.1)];
+    let updates = jpda.step(&[prior, other], &detections).unwrap();
+    for update in updates.iter() {
+        let sum: f64 =
+            update.miss_probability + update.association_probabilities.iter().sum::<f64>();
+        assert!((sum - 1.0).abs() < 1e-12);
+    }
+    assert!(
+        (updates[0].association_probabilities[0] - updates[1].association_probabilities[1]).abs()
+            < 1e-12
+    );
+    assert!(updates[0].association_probabilities[0] > updates[0].association_probabilities[1]);
+    // The association uncertainty inflates the covariance beyond that of an
+    // update with a single known detection.
+    assert!(updates[0].estimate.covariance()[(0, 0)] > 0.5);
+}
+
+#[test]
+fn test_jpda_certain_detection() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::U1;
+
+    let transition_model = LinearTransitionModel::<f64, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(0.0),
+    );
+    let observation_model = LinearObservationModel::<f64, U1, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    let parameters = JpdaParameters {
+        detection_probability: 1.0,
+        clutter_density: 0.01,
+        gate_threshold: 9.0,
+    };
+    let jpda = JpdaFilter::new(&transition_model, &observation_model, parameters);
+    let near = StateAndCovariance::new(
+        OVector::<f64, U1>::new(0.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    let far = StateAndCovariance::new(
+        OVector::<f64, U1>::new(100.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+
+    // The far track has an empty gate, which must not make the near track's
+    // probabilities NaN.
+    let detections = vec![OVector::<f64, U1>::new(0.5)];
+    let updates = jpda
+        .update(&[near.clone(), far.clone()], &detections)
+        .unwrap();
+    assert_eq!(updates[0].miss_probability, 0.0);
+    assert_eq!(updates[0].association_probabilities, vec![1.0]);
+    assert!((updates[0].estimate.state()[0] - 0.25).abs() < 1e-12);
+    assert_eq!(updates[1].miss_probability, 1.0);
+    assert_eq!(updates[1].association_probabilities, vec![0.0]);
+    assert_eq!(updates[1].estimate.state(), far.state());
+
+    // Without any detection, every track keeps its prior.
+    let updates = jpda.update(&[near.clone(), far], &[]).unwrap();
+    assert_eq!(updates[0].miss_probability, 1.0);
+    assert_eq!(updates[0].estimate.state(), near.state());
+}
+
+#[test]
+#[should_panic(expected = "the clutter density must be positive")]
+fn test_jpda_invalid_parameters() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::U1;
+
+    let transition_model = LinearTransitionModel::<f64, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(0.0),
+    );
+    let observation_model = LinearObservationModel::<f64, U1, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    let parameters = JpdaParameters {
+        detection_probability: 0.9,
+        clutter_density: 0.0,
+        gate_threshold: 9.0,
+    };
+    JpdaFilter::new(&transition_model, &observation_model, parameters);
+}
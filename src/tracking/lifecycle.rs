@@ -0,0 +1,530 @@
+use std::collections::VecDeque;
+
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OVector, RealField};
+use nalgebra as na;
+
+use super::{gated_distances, gnn_associate, FrameAssociation, TrackId};
+use crate::{
+    CoverianceUpdateMethod, Error, ObservationModelLinear, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// Whether a track has been confirmed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum TrackStatus {
+    /// The track has not yet been detected often enough to be confirmed.
+    Tentative,
+    /// The track has met the M-of-N confirmation criterion.
+    Confirmed,
+}
+
+/// Parameters of the [`LifecycleTracker`](struct.LifecycleTracker.html)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct LifecycleOptions<R: RealField> {
+    /// Gate on the squared Mahalanobis distance between a track's predicted
+    /// observation and a detection.
+    pub gate_threshold: R,
+    /// Number of detections (M) within the confirmation window required to
+    /// confirm a tentative track.
+    pub confirmation_hits: usize,
+    /// Length (N) of the confirmation window, in frames, including the frame in
+    /// which the track was initiated.
+    pub confirmation_window: usize,
+    /// A confirmed track is deleted when it has coasted (gone without a
+    /// detection) for more than this many consecutive frames.
+    pub max_coasting_frames: usize,
+    /// While coasting, the covariance of a track is scaled down whenever its
+    /// trace would exceed this value.
+    pub max_covariance_trace: R,
+    /// Maximum number of frames kept in the history of each track. The oldest
+    /// entries are dropped first. `None` keeps the whole history, which grows
+    /// without bound for long-lived tracks.
+    pub max_history: Option<usize>,
+}
+
+/// The estimate of a track in one frame
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct TrackHistoryEntry<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// The frame number, counting from one.
+    pub frame: u64,
+    /// The posterior estimate in this frame.
+    pub estimate: StateAndCovariance<R, SS>,
+    /// Index of the detection used in this frame, or `None` when coasting.
+    pub detection: Option<usize>,
+}
+
+/// A track together with its lifecycle state and history
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct ManagedTrack<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    id: TrackId,
+    status: TrackStatus,
+    estimate: StateAndCovariance<R, SS>,
+    history: VecDeque<TrackHistoryEntry<R, SS>>,
+    recent_hits: VecDeque<bool>,
+    consecutive_misses: usize,
+}
+
+impl<R, SS> ManagedTrack<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Get the identifier of this track.
+    #[inline]
+    pub fn id(&self) -> TrackId {
+        self.id
+    }
+    /// Get the confirmation status of this track.
+    #[inline]
+    pub fn status(&self) -> TrackStatus {
+        self.status
+    }
+    /// Get the current state estimate of this track.
+    #[inline]
+    pub fn estimate(&self) -> &StateAndCovariance<R, SS> {
+        &self.estimate
+    }
+    /// Get the estimate of this track in every frame since its initiation,
+    /// oldest first, limited to the most recent `max_history` frames.
+    #[inline]
+    pub fn history(&self) -> &VecDeque<TrackHistoryEntry<R, SS>> {
+        &self.history
+    }
+    /// Append an entry to the history, dropping the oldest entries beyond
+    /// `max_history`.
+    fn record(&mut self, entry: TrackHistoryEntry<R, SS>, max_history: Option<usize>) {
+        self.history.push_back(entry);
+        if let Some(max_history) = max_history {
+            while self.history.len() > max_history {
+                self.history.pop_front();
+            }
+        }
+    }
+    /// Get the number of consecutive frames without a detection.
+    #[inline]
+    pub fn consecutive_misses(&self) -> usize {
+        self.consecutive_misses
+    }
+}
+
+/// A change in the set of tracks
+#[derive(Debug, Clone)]
+pub enum TrackEvent<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// A tentative track was initiated from an unassociated detection.
+    Birth(TrackId),
+    /// A tentative track was confirmed.
+    Confirmation(TrackId),
+    /// A track was deleted. The track, including its history, is returned.
+    Death(ManagedTrack<R, SS>),
+}
+
+/// The result of processing one frame with a
+/// [`LifecycleTracker`](struct.LifecycleTracker.html)
+#[derive(Debug, Clone)]
+pub struct LifecycleStep<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// The association of detections with the tracks existing before this
+    /// frame. Detections which initiated new tracks are listed as unmatched.
+    pub association: FrameAssociation,
+    /// Births, confirmations and deaths, in the order in which they occurred.
+    pub events: Vec<TrackEvent<R, SS>>,
+}
+
+/// A function computing the initial estimate of a track from a detection
+pub type TrackInitiator<R, SS, OS> = dyn Fn(&OVector<R, OS>) -> StateAndCovariance<R, SS>;
+
+/// A snapshot of the tracks of a [`LifecycleTracker`](struct.LifecycleTracker.html)
+///
+/// This holds everything but the models, initiator and options, so that a
+/// tracker can be checkpointed and later restored with
+/// [`LifecycleTracker::restore`](struct.LifecycleTracker.html#method.restore).
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct LifecycleTrackerState<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    tracks: Vec<ManagedTrack<R, SS>>,
+    next_id: TrackId,
+    frame: u64,
+}
+
+/// A multi-target tracker which initiates, confirms and deletes tracks
+///
+/// Each frame, detections are associated with existing tracks by global
+/// nearest neighbor association. Every detection not associated with a track
+/// initiates a new tentative track, using the `initiator` function to compute
+/// its initial estimate. A tentative track is confirmed once it has been
+/// detected in M of its first N frames, and deleted as soon as this is no
+/// longer possible. A confirmed track coasts on its predicted estimate while it
+/// is not detected, and is deleted once it has coasted for too long.
+pub struct LifecycleTracker<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    initiator: &'a TrackInitiator<R, SS, OS>,
+    options: LifecycleOptions<R>,
+    tracks: Vec<ManagedTrack<R, SS>>,
+    next_id: TrackId,
+    frame: u64,
+}
+
+impl<'a, R, SS, OS> LifecycleTracker<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `LifecycleTracker` struct with no tracks.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        initiator: &'a TrackInitiator<R, SS, OS>,
+        options: LifecycleOptions<R>,
+    ) -> Self {
+        assert!(options.confirmation_hits <= options.confirmation_window);
+        Self {
+            transition_model,
+            observation_model,
+            initiator,
+            options,
+            tracks: Vec::new(),
+            next_id: 0,
+            frame: 0,
+        }
+    }
+
+    /// Get the current tracks, both tentative and confirmed.
+    #[inline]
+    pub fn tracks(&self) -> &[ManagedTrack<R, SS>] {
+        &self.tracks
+    }
+
+    /// Get the current confirmed tracks.
+    pub fn confirmed_tracks(&self) -> impl Iterator<Item = &ManagedTrack<R, SS>> {
+        self.tracks
+            .iter()
+            .filter(|t| t.status == TrackStatus::Confirmed)
+    }
+
+    /// Get the number of frames processed so far.
+    #[inline]
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Get a snapshot of the tracks, e.g. to checkpoint the tracker.
+    pub fn state(&self) -> LifecycleTrackerState<R, SS> {
+        LifecycleTrackerState {
+            tracks: self.tracks.clone(),
+            next_id: self.next_id,
+            frame: self.frame,
+        }
+    }
+
+    /// Replace the tracks with those of a snapshot taken by
+    /// [`state`](#method.state).
+    pub fn restore(&mut self, state: LifecycleTrackerState<R, SS>) {
+        self.tracks = state.tracks;
+        self.next_id = state.next_id;
+        self.frame = state.frame;
+    }
+
+    /// Process the detections of one frame.
+    pub fn step(&mut self, detections: &[OVector<R, OS>]) -> Result<LifecycleStep<R, SS>, Error> {
+        self.frame += 1;
+        let estimates: Vec<_> = self.tracks.iter().map(|t| &t.estimate).collect();
+        let (priors, distances) = gated_distances(
+            self.transition_model,
+            self.observation_model,
+            &estimates,
+            detections,
+            self.options.gate_threshold,
+        )?;
+        let assignment = gnn_associate(&distances, self.options.gate_threshold);
+
+        let mut association = FrameAssociation {
+            assignments: Vec::new(),
+            unmatched_tracks: Vec::new(),
+            unmatched_detections: Vec::new(),
+        };
+        let mut events = Vec::new();
+        let mut detection_used = vec![false; detections.len()];
+        let mut survivors = Vec::with_capacity(self.tracks.len());
+        let tracks = core::mem::take(&mut self.tracks);
+        for ((mut track, prior), assigned) in tracks.into_iter().zip(priors).zip(assignment) {
+            match assigned {
+                Some(j) => {
+                    track.estimate = self.observation_model.update(
+                        &prior,
+                        &detections[j],
+                        CoverianceUpdateMethod::JosephForm,
+                    )?;
+                    track.consecutive_misses = 0;
+                    detection_used[j] = true;
+                    association.assignments.push((track.id, j));
+                }
+                None => {
+                    track.estimate = self.cap_covariance(prior);
+                    track.consecutive_misses += 1;
+                    association.unmatched_tracks.push(track.id);
+                }
+            }
+            let entry = TrackHistoryEntry {
+                frame: self.frame,
+                estimate: track.estimate.clone(),
+                detection: assigned,
+            };
+            track.record(entry, self.options.max_history);
+
+            let alive = match track.status {
+                TrackStatus::Tentative => {
+                    if track.recent_hits.len() < self.options.confirmation_window {
+                        track.recent_hits.push_back(assigned.is_some());
+                    }
+                    let hits = track.recent_hits.iter().filter(|hit| **hit).count();
+                    let remaining = self.options.confirmation_window - track.recent_hits.len();
+                    if hits >= self.options.confirmation_hits {
+                        track.status = TrackStatus::Confirmed;
+                        events.push(TrackEvent::Confirmation(track.id));
+                        true
+                    } else {
+                        hits + remaining >= self.options.confirmation_hits
+                    }
+                }
+                TrackStatus::Confirmed => {
+                    track.consecutive_misses <= self.options.max_coasting_frames
+                }
+            };
+            if alive {
+                survivors.push(track);
+            } else {
+                events.push(TrackEvent::Death(track));
+            }
+        }
+        self.tracks = survivors;
+
+        for (j, detection) in detections.iter().enumerate() {
+            if detection_used[j] {
+                continue;
+            }
+            association.unmatched_detections.push(j);
+            let id = self.next_id;
+            self.next_id += 1;
+            let estimate = (self.initiator)(detection);
+            let mut recent_hits = VecDeque::with_capacity(self.options.confirmation_window);
+            recent_hits.push_back(true);
+            let mut track = ManagedTrack {
+                id,
+                status: TrackStatus::Tentative,
+                estimate: estimate.clone(),
+                history: VecDeque::new(),
+                recent_hits,
+                consecutive_misses: 0,
+            };
+            let entry = TrackHistoryEntry {
+                frame: self.frame,
+                estimate,
+                detection: Some(j),
+            };
+            track.record(entry, self.options.max_history);
+            events.push(TrackEvent::Birth(id));
+            if self.options.confirmation_hits <= 1 {
+                track.status = TrackStatus::Confirmed;
+                events.push(TrackEvent::Confirmation(id));
+            }
+            self.tracks.push(track);
+        }
+
+        Ok(LifecycleStep {
+            association,
+            events,
+        })
+    }
+
+    /// Scale the covariance down if its trace exceeds the maximum.
+    fn cap_covariance(&self, estimate: StateAndCovariance<R, SS>) -> StateAndCovariance<R, SS> {
+        let trace = estimate.covariance().trace();
+        if trace > self.options.max_covariance_trace {
+            let scale = self.options.max_covariance_trace / trace;
+            StateAndCovariance::new(estimate.state().clone(), estimate.covariance() * scale)
+        } else {
+            estimate
+        }
+    }
+}
+
+#[test]
+fn test_lifecycle_tracker() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{OMatrix, U1, U2};
+
+    // constant velocity in one dimension, observing position
+    let transition_model = LinearTransitionModel::<f64, U2>::new(
+        OMatrix::<f64, U2, U2>::new(1.0, 1.0, 0.0, 1.0),
+        OMatrix::<f64, U2, U2>::identity() * 0.01,
+    );
+    let observation_model = LinearObservationModel::<f64, U2, U1>::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OMatrix::<f64, U1, U1>::new(0.01),
+    );
+    let initiator = |z: &OVector<f64, U1>| {
+        StateAndCovariance::new(
+            OVector::<f64, U2>::new(z[0], 1.0),
+            OMatrix::<f64, U2, U2>::new(0.01, 0.0, 0.0, 0.1),
+        )
+    };
+    let options = LifecycleOptions {
+        gate_threshold: 9.0,
+        confirmation_hits: 2,
+        confirmation_window: 3,
+        max_coasting_frames: 2,
+        max_covariance_trace: 5.0,
+        max_history: None,
+    };
+    let mut tracker =
+        LifecycleTracker::new(&transition_model, &observation_model, &initiator, options);
+
+    // Frame 1: a target and a clutter detection far away.
+    let step = tracker.step(&[OVector::<f64, U1>::new(0.0), OVector::<f64, U1>::new(100.0)]);
+    let step = step.unwrap();
+    assert!(matches!(
+        step.events[..],
+        [TrackEvent::Birth(0), TrackEvent::Birth(1)]
+    ));
+    assert_eq!(tracker.tracks().len(), 2);
+
+    // Frames 2 and 3: only the target is detected. The target's track is
+    // confirmed and the clutter track is deleted once it can no longer be.
+    let step = tracker.step(&[OVector::<f64, U1>::new(1.0)]).unwrap();
+    assert!(matches!(step.events[..], [TrackEvent::Confirmation(0)]));
+    let step = tracker.step(&[OVector::<f64, U1>::new(2.0)]).unwrap();
+    match &step.events[..] {
+        [TrackEvent::Death(track)] => {
+            assert_eq!(track.id(), 1);
+            assert_eq!(track.status(), TrackStatus::Tentative);
+            assert_eq!(track.history().len(), 3);
+        }
+        _ => panic!("expected the clutter track to die"),
+    }
+    assert_eq!(tracker.confirmed_tracks().count(), 1);
+
+    // The target disappears. Its track coasts for two frames, with bounded
+    // covariance, and is then deleted.
+    for _ in 0..2 {
+        let step = tracker.step(&[]).unwrap();
+        assert!(step.events.is_empty());
+        assert!(tracker.tracks()[0].estimate().covariance().trace() <= 5.0 + 1e-9);
+    }
+    let step = tracker.step(&[]).unwrap();
+    match &step.events[..] {
+        [TrackEvent::Death(track)] => {
+            assert_eq!(track.id(), 0);
+            assert_eq!(track.history().len(), 6);
+            assert_eq!(track.history()[2].detection, Some(0));
+            assert_eq!(track.history()[5].detection, None);
+        }
+        _ => panic!("expected the target track to die"),
+    }
+    assert!(tracker.tracks().is_empty());
+    assert_eq!(tracker.frame(), 6);
+
+    // With a bounded history, only the most recent frames are kept.
+    let options = LifecycleOptions {
+        max_history: Some(2),
+        ..options
+    };
+    let mut tracker =
+        LifecycleTracker::new(&transition_model, &observation_model, &initiator, options);
+    for x in 0..5 {
+        tracker.step(&[OVector::<f64, U1>::new(x as f64)]).unwrap();
+    }
+    let history = tracker.tracks()[0].history();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].frame, 4);
+    assert_eq!(history[1].frame, 5);
+}
@@ -0,0 +1,596 @@
+use std::cmp::Ordering;
+
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OVector, RealField};
+use nalgebra as na;
+
+use super::{check_detections, TrackId};
+use crate::{
+    Error, ErrorKind, KalmanFilterNoControl, ObservationModelLinear, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// Parameters of the [`MhtTracker`](struct.MhtTracker.html)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct MhtParameters<R: RealField> {
+    /// Probability that a target is detected in a given frame, in `(0, 1)`.
+    pub detection_probability: R,
+    /// Spatial density of clutter (false) detections, in detections per unit
+    /// volume of observation space. Must be positive.
+    pub clutter_density: R,
+    /// Gate on the squared Mahalanobis distance between a track's predicted
+    /// observation and a detection.
+    pub gate_threshold: R,
+    /// Depth of N-scan pruning. After each frame, association decisions made
+    /// more than this many frames ago are fixed to those of the global best
+    /// hypothesis.
+    pub n_scan: usize,
+    /// Maximum number of hypotheses kept per track.
+    pub max_hypotheses: usize,
+}
+
+/// A leaf of the hypothesis tree of one track
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "R: serde::Serialize, StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "R: serde::Deserialize<'de>, StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct TrackHypothesis<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    estimate: StateAndCovariance<R, SS>,
+    score: R,
+    associations: Vec<Option<usize>>,
+}
+
+impl<R, SS> TrackHypothesis<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Get the state estimate under this hypothesis.
+    #[inline]
+    pub fn estimate(&self) -> &StateAndCovariance<R, SS> {
+        &self.estimate
+    }
+    /// Get the cumulative log-likelihood ratio of this hypothesis.
+    #[inline]
+    pub fn score(&self) -> R {
+        self.score
+    }
+    /// Get the detection index associated with the track in each of the most
+    /// recent frames, oldest first, ending with the current frame. `None`
+    /// denotes a missed detection.
+    ///
+    /// Only frames which have not yet been fixed by N-scan pruning are
+    /// included.
+    #[inline]
+    pub fn associations(&self) -> &[Option<usize>] {
+        &self.associations
+    }
+    /// Association in the frame `frames_ago` frames before the current one.
+    fn association(&self, frames_ago: usize) -> Option<Option<usize>> {
+        let n = self.associations.len();
+        if frames_ago < n {
+            Some(self.associations[n - 1 - frames_ago])
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "R: serde::Serialize, StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "R: serde::Deserialize<'de>, StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+struct MhtTrack<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    id: TrackId,
+    hypotheses: Vec<TrackHypothesis<R, SS>>,
+}
+
+/// The best joint association hypothesis over all tracks
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "R: serde::Serialize, StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "R: serde::Deserialize<'de>, StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct GlobalHypothesis<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Total score, the sum of the scores of the track hypotheses.
+    pub score: R,
+    /// The chosen hypothesis of each track.
+    pub tracks: Vec<(TrackId, TrackHypothesis<R, SS>)>,
+}
+
+/// A snapshot of the hypothesis trees of an [`MhtTracker`](struct.MhtTracker.html)
+///
+/// This holds everything but the models and parameters, so that a tracker can
+/// be checkpointed and later restored with
+/// [`MhtTracker::restore`](struct.MhtTracker.html#method.restore).
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "R: serde::Serialize, StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "R: serde::Deserialize<'de>, StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct MhtTrackerState<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    tracks: Vec<MhtTrack<R, SS>>,
+    next_id: TrackId,
+}
+
+/// A track-oriented Multiple Hypothesis Tracker (MHT)
+///
+/// Each track keeps a tree of hypotheses about which detection (if any)
+/// originated from it in each frame. Only the leaves of the tree are stored,
+/// each with the state estimate obtained by Kalman filtering along its branch
+/// and a score, the cumulative log-likelihood ratio of its associations
+/// against the detections being clutter.
+///
+/// Each frame, every leaf is extended by a missed-detection branch and a branch
+/// for each detection in its gate. The global best hypothesis is the set of
+/// leaves, one per track, with the highest total score such that no detection
+/// is used by two tracks. The trees are then pruned by N-scan pruning, which
+/// keeps only the leaves agreeing with the global best hypothesis on the
+/// association `n_scan` frames ago, and by keeping only the highest scoring
+/// leaves of each track.
+///
+/// Tracks are created and removed explicitly.
+pub struct MhtTracker<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    parameters: MhtParameters<R>,
+    tracks: Vec<MhtTrack<R, SS>>,
+    next_id: TrackId,
+}
+
+impl<'a, R, SS, OS> MhtTracker<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `MhtTracker` struct with no tracks.
+    ///
+    /// Panics if the detection probability is not in `(0, 1)`, the clutter
+    /// density is not positive or `max_hypotheses` is zero. Otherwise, the
+    /// scores of missed detections or of detections would not be finite.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        parameters: MhtParameters<R>,
+    ) -> Self {
+        assert!(
+            parameters.detection_probability > R::zero()
+                && parameters.detection_probability < R::one(),
+            "the detection probability must be in (0, 1)"
+        );
+        assert!(
+            parameters.clutter_density > R::zero(),
+            "the clutter density must be positive"
+        );
+        assert!(parameters.max_hypotheses >= 1);
+        Self {
+            transition_model,
+            observation_model,
+            parameters,
+            tracks: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Start a new track and return its identifier.
+    pub fn add_track(&mut self, estimate: StateAndCovariance<R, SS>) -> TrackId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tracks.push(MhtTrack {
+            id,
+            hypotheses: vec![TrackHypothesis {
+                estimate,
+                score: R::zero(),
+                associations: Vec::new(),
+            }],
+        });
+        id
+    }
+
+    /// Remove a track, returning whether it existed.
+    pub fn remove_track(&mut self, id: TrackId) -> bool {
+        let n = self.tracks.len();
+        self.tracks.retain(|t| t.id != id);
+        self.tracks.len() != n
+    }
+
+    /// Get a snapshot of the hypothesis trees, e.g. to checkpoint the tracker.
+    pub fn state(&self) -> MhtTrackerState<R, SS> {
+        MhtTrackerState {
+            tracks: self.tracks.clone(),
+            next_id: self.next_id,
+        }
+    }
+
+    /// Replace the hypothesis trees with those of a snapshot taken by
+    /// [`state`](#method.state).
+    pub fn restore(&mut self, state: MhtTrackerState<R, SS>) {
+        self.tracks = state.tracks;
+        self.next_id = state.next_id;
+    }
+
+    /// Get the current hypotheses of a track, highest scoring first.
+    pub fn hypotheses(&self, id: TrackId) -> Option<&[TrackHypothesis<R, SS>]> {
+        self.tracks
+            .iter()
+            .find(|t| t.id == id)
+            .map(|t| &t.hypotheses[..])
+    }
+
+    /// Process the detections of one frame and return the global best
+    /// hypothesis.
+    pub fn step(
+        &mut self,
+        detections: &[OVector<R, OS>],
+    ) -> Result<GlobalHypothesis<R, SS>, Error> {
+        check_detections(detections)?;
+        let kf = KalmanFilterNoControl::new(self.transition_model, self.observation_model);
+        let p_d = self.parameters.detection_probability;
+        let miss_score = (R::one() - p_d).ln();
+        let detection_score = p_d.ln() - self.parameters.clutter_density.ln();
+
+        // Extend every leaf.
+        for track in self.tracks.iter_mut() {
+            let mut children = Vec::with_capacity(track.hypotheses.len() * (detections.len() + 1));
+            for leaf in track.hypotheses.iter() {
+                let prior = self.transition_model.predict(&leaf.estimate);
+                if let Some(first) = detections.first() {
+                    let base = self.observation_model.innovation(&prior, first)?;
+                    let predicted = self.observation_model.evaluate(prior.state());
+                    for (j, detection) in detections.iter().enumerate() {
+                        let innovation = base.with_residual(detection - &predicted);
+                        if innovation.mahalanobis_squared() <= self.parameters.gate_threshold {
+                            let mut associations = leaf.associations.clone();
+                            associations.push(Some(j));
+                            children.push(TrackHypothesis {
+                                estimate: kf.step(&leaf.estimate, detection)?,
+                                score: leaf.score + detection_score + innovation.log_likelihood(),
+                                associations,
+                            });
+                        }
+                    }
+                }
+                let mut associations = leaf.associations.clone();
+                associations.push(None);
+                children.push(TrackHypothesis {
+                    estimate: prior,
+                    score: leaf.score + miss_score,
+                    associations,
+                });
+            }
+            children.sort_by(|a, b| by_decreasing_score(a, b));
+            track.hypotheses = children;
+        }
+
+        // Find the global best hypothesis. This exists because the previous
+        // best hypothesis, extended by missed detections, is feasible, unless
+        // the scores are not finite.
+        let window = self.parameters.n_scan + 1;
+        let candidates: Vec<&[TrackHypothesis<R, SS>]> =
+            self.tracks.iter().map(|t| &t.hypotheses[..]).collect();
+        let best = best_global_hypothesis(&candidates, window)
+            .ok_or_else(|| Error::from(ErrorKind::NoFeasibleHypothesis))?;
+
+        let mut score = R::zero();
+        let mut chosen = Vec::with_capacity(self.tracks.len());
+        for (track, best_idx) in self.tracks.iter().zip(best.iter()) {
+            let leaf = &track.hypotheses[*best_idx];
+            score += leaf.score;
+            chosen.push((track.id, leaf.clone()));
+        }
+
+        // Prune each track, always keeping the leaf of the global best
+        // hypothesis.
+        let n_scan = self.parameters.n_scan;
+        let max_hypotheses = self.parameters.max_hypotheses;
+        for (track, best_idx) in self.tracks.iter_mut().zip(best) {
+            let best_leaf = track.hypotheses.swap_remove(best_idx);
+            let fixed = best_leaf.association(n_scan);
+            let mut kept: Vec<_> = track
+                .hypotheses
+                .drain(..)
+                .filter(|h| fixed.is_none() || h.association(n_scan) == fixed)
+                .collect();
+            kept.sort_by(|a, b| by_decreasing_score(a, b));
+            kept.truncate(max_hypotheses - 1);
+            kept.push(best_leaf);
+            kept.sort_by(|a, b| by_decreasing_score(a, b));
+            if fixed.is_some() {
+                // The oldest association is now identical in all leaves.
+                for h in kept.iter_mut() {
+                    h.associations.remove(0);
+                }
+            }
+            track.hypotheses = kept;
+        }
+
+        Ok(GlobalHypothesis {
+            score,
+            tracks: chosen,
+        })
+    }
+}
+
+/// Order track hypotheses by decreasing score, with scores which are NaN
+/// considered equal to all others.
+fn by_decreasing_score<R, SS>(a: &TrackHypothesis<R, SS>, b: &TrackHypothesis<R, SS>) -> Ordering
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal)
+}
+
+/// Whether two track hypotheses use the same detection in any of the most
+/// recent `window` frames.
+fn conflict<R, SS>(a: &TrackHypothesis<R, SS>, b: &TrackHypothesis<R, SS>, window: usize) -> bool
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    (0..window).any(|k| match (a.association(k), b.association(k)) {
+        (Some(Some(i)), Some(Some(j))) => i == j,
+        _ => false,
+    })
+}
+
+/// Find the compatible choice of one hypothesis per track with the highest
+/// total score by branch and bound.
+///
+/// The hypotheses of each track must be sorted by decreasing score. Returns
+/// the index of the chosen hypothesis of each track, or `None` if no
+/// compatible choice exists.
+fn best_global_hypothesis<R, SS>(
+    tracks: &[&[TrackHypothesis<R, SS>]],
+    window: usize,
+) -> Option<Vec<usize>>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    // upper bound on the score achievable by the tracks from each index on
+    let mut remaining_bound = vec![R::zero(); tracks.len() + 1];
+    for i in (0..tracks.len()).rev() {
+        remaining_bound[i] = remaining_bound[i + 1] + tracks[i].first()?.score;
+    }
+    let mut search = BranchAndBound {
+        tracks,
+        window,
+        remaining_bound,
+        current: Vec::with_capacity(tracks.len()),
+        best: None,
+    };
+    search.recurse(R::zero());
+    search.best.map(|(_, choice)| choice)
+}
+
+struct BranchAndBound<'b, R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    tracks: &'b [&'b [TrackHypothesis<R, SS>]],
+    window: usize,
+    remaining_bound: Vec<R>,
+    current: Vec<usize>,
+    best: Option<(R, Vec<usize>)>,
+}
+
+impl<'b, R, SS> BranchAndBound<'b, R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    fn recurse(&mut self, score: R) {
+        let depth = self.current.len();
+        if depth == self.tracks.len() {
+            let improves = match &self.best {
+                Some((best, _)) => score > *best,
+                None => true,
+            };
+            if improves {
+                self.best = Some((score, self.current.clone()));
+            }
+            return;
+        }
+        for (idx, candidate) in self.tracks[depth].iter().enumerate() {
+            let bound = score + candidate.score + self.remaining_bound[depth + 1];
+            if let Some((best, _)) = &self.best {
+                if bound <= *best {
+                    // Hypotheses are sorted, so no later one can do better.
+                    break;
+                }
+            }
+            let compatible = self
+                .current
+                .iter()
+                .enumerate()
+                .all(|(t, i)| !conflict(&self.tracks[t][*i], candidate, self.window));
+            if compatible {
+                self.current.push(idx);
+                self.recurse(score + candidate.score);
+                self.current.pop();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_mht_crossing_targets() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{OMatrix, U1, U2};
+
+    // constant velocity in one dimension, observing position
+    let transition_model = LinearTransitionModel::<f64, U2>::new(
+        OMatrix::<f64, U2, U2>::new(1.0, 1.0, 0.0, 1.0),
+        OMatrix::<f64, U2, U2>::new(0.25, 0.5, 0.5, 1.0) * 0.01,
+    );
+    let observation_model = LinearObservationModel::<f64, U2, U1>::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OMatrix::<f64, U1, U1>::new(0.1),
+    );
+    let parameters = MhtParameters {
+        detection_probability: 0.9,
+        clutter_density: 0.01,
+        gate_threshold: 16.0,
+        n_scan: 3,
+        max_hypotheses: 10,
+    };
+    let mut mht = MhtTracker::new(&transition_model, &observation_model, parameters);
+    let covariance = OMatrix::<f64, U2, U2>::new(0.1, 0.0, 0.0, 0.1);
+    let a = mht.add_track(StateAndCovariance::new(
+        OVector::<f64, U2>::new(-5.0, 1.0),
+        covariance,
+    ));
+    let b = mht.add_track(StateAndCovariance::new(
+        OVector::<f64, U2>::new(5.0, -1.0),
+        covariance,
+    ));
+
+    // The targets cross at t=5. Detections are sorted by position, so their
+    // order swaps when the targets cross, and the target moving left is not
+    // detected in frame 7.
+    let mut best = None;
+    for t in 1..=10 {
+        let xa = -5.0 + t as f64;
+        let xb = 5.0 - t as f64;
+        let mut detections = if t == 7 { vec![xa] } else { vec![xa, xb] };
+        detections.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        let detections: Vec<_> = detections
+            .into_iter()
+            .map(OVector::<f64, U1>::new)
+            .collect();
+        let global = mht.step(&detections).unwrap();
+        for (id, _) in global.tracks.iter() {
+            assert!(mht.hypotheses(*id).unwrap().len() <= 10);
+        }
+        best = Some(global);
+    }
+    let best = best.unwrap();
+    assert_eq!(best.tracks[0].0, a);
+    assert_eq!(best.tracks[1].0, b);
+    let state_a = best.tracks[0].1.estimate().state();
+    let state_b = best.tracks[1].1.estimate().state();
+    assert!((state_a[0] - 5.0).abs() < 0.1);
+    assert!((state_a[1] - 1.0).abs() < 0.1);
+    assert!((state_b[0] + 5.0).abs() < 0.1);
+    assert!((state_b[1] + 1.0).abs() < 0.1);
+    // In the final frame, detection 1 is at +5 and detection 0 at -5.
+    assert_eq!(best.tracks[0].1.associations().last(), Some(&Some(1)));
+    assert_eq!(best.tracks[1].1.associations().last(), Some(&Some(0)));
+    // Only the associations not yet fixed by N-scan pruning are kept.
+    assert_eq!(best.tracks[0].1.associations().len(), 4);
+    assert!(mht.remove_track(a));
+    assert!(mht.hypotheses(a).is_none());
+}
+
+#[test]
+#[should_panic(expected = "the clutter density must be positive")]
+fn test_mht_invalid_parameters() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{OMatrix, U1};
+
+    // Without clutter, the score of a detection would be infinite.
+    let transition_model = LinearTransitionModel::<f64, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    let observation_model = LinearObservationModel::<f64, U1, U1>::new(
+        OMatrix::<f64, U1, U1>::new(1.0),
+        OMatrix::<f64, U1, U1>::new(1.0),
+    );
+    let parameters = MhtParameters {
+        detection_probability: 0.9,
+        clutter_density: 0.0,
+        gate_threshold: 16.0,
+        n_scan: 3,
+        max_hypotheses: 10,
+    };
+    MhtTracker::new(&transition_model, &observation_model, parameters);
+}
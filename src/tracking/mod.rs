@@ -0,0 +1,477 @@
+//! Multi-target tracking
+//!
+//! When several targets are tracked at once from unlabelled detections, each
+//! detection must be associated with a track before the track's Kalman filter
+//! can be updated. The [`GnnTracker`](struct.GnnTracker.html) here performs
+//! global nearest neighbor (GNN) association: each frame, every detection is
+//! gated against every track's predicted observation, and the assignment of
+//! detections to tracks which minimizes the total squared Mahalanobis distance
+//! is found with the Hungarian algorithm.
+//!
+//! In dense clutter, the [`JpdaFilter`](struct.JpdaFilter.html) avoids hard
+//! assignment and instead updates each track with all gated detections,
+//! weighted by their association probabilities.
+//!
+//! The [`LifecycleTracker`](struct.LifecycleTracker.html) additionally manages
+//! the lifecycle of tracks: it initiates tentative tracks from unassociated
+//! detections, confirms them by M-of-N logic, coasts them through missed
+//! detections and deletes them.
+//!
+//! For the hardest scenes, such as closely crossing targets, the
+//! [`MhtTracker`](struct.MhtTracker.html) defers association decisions by
+//! keeping multiple association hypotheses per track.
+//!
+//! When the number of targets is unknown and individual identities are not
+//! needed, the [`GmPhdFilter`](struct.GmPhdFilter.html) estimates the number
+//! and states of targets without explicit data association.
+
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OVector, RealField};
+use nalgebra as na;
+
+use crate::{
+    Error, ErrorKind, KalmanFilterNoControl, ObservationModelLinear, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+mod assignment;
+pub use assignment::solve_assignment;
+
+mod jpda;
+pub use jpda::{JpdaFilter, JpdaParameters, JpdaUpdate};
+
+mod lifecycle;
+pub use lifecycle::{
+    LifecycleOptions, LifecycleStep, LifecycleTracker, LifecycleTrackerState, ManagedTrack,
+    TrackEvent, TrackHistoryEntry, TrackInitiator, TrackStatus,
+};
+
+mod mht;
+pub use mht::{GlobalHypothesis, MhtParameters, MhtTracker, MhtTrackerState, TrackHypothesis};
+
+mod phd;
+pub use phd::{GaussianComponent, GmPhdFilter, GmPhdParameters};
+
+/// Identifier of a track, unique within a tracker
+pub type TrackId = u64;
+
+/// A single tracked target
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct Track<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    id: TrackId,
+    estimate: StateAndCovariance<R, SS>,
+}
+
+impl<R, SS> Track<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Get the identifier of this track.
+    #[inline]
+    pub fn id(&self) -> TrackId {
+        self.id
+    }
+    /// Get the current state estimate of this track.
+    #[inline]
+    pub fn estimate(&self) -> &StateAndCovariance<R, SS> {
+        &self.estimate
+    }
+}
+
+/// The association of detections to tracks in one frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameAssociation {
+    /// Pairs of track identifier and index of the detection assigned to it.
+    pub assignments: Vec<(TrackId, usize)>,
+    /// Tracks to which no detection was assigned.
+    pub unmatched_tracks: Vec<TrackId>,
+    /// Indices of detections which were not assigned to any track.
+    pub unmatched_detections: Vec<usize>,
+}
+
+/// Check that no detection has NaN or infinite components.
+///
+/// Unlike an observation, a detection cannot be missing, so a NaN component is
+/// an error as well. The error carries the index of the detection.
+fn check_detections<R, OS>(detections: &[OVector<R, OS>]) -> Result<(), Error>
+where
+    R: RealField,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, OS>,
+{
+    match detections
+        .iter()
+        .position(|d| !d.iter().all(|x| x.is_finite()))
+    {
+        Some(j) => Err(Error::from(ErrorKind::NonFiniteInput).with_index(j)),
+        None => Ok(()),
+    }
+}
+
+/// Squared Mahalanobis distance between each track's predicted observation and
+/// each detection, or `None` if the detection is outside the track's gate.
+///
+/// Also returns the prior (predicted) estimate of each track.
+#[allow(clippy::type_complexity)]
+fn gated_distances<R, SS, OS>(
+    transition_model: &dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &dyn ObservationModelLinear<R, SS, OS>,
+    estimates: &[&StateAndCovariance<R, SS>],
+    detections: &[OVector<R, OS>],
+    gate_threshold: R,
+) -> Result<(Vec<StateAndCovariance<R, SS>>, Vec<Vec<Option<R>>>), Error>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    check_detections(detections)?;
+    let mut priors = Vec::with_capacity(estimates.len());
+    let mut distances = Vec::with_capacity(estimates.len());
+    for estimate in estimates.iter() {
+        let prior = transition_model.predict(estimate);
+        let mut row = Vec::with_capacity(detections.len());
+        if let Some(first) = detections.first() {
+            let base = observation_model.innovation(&prior, first)?;
+            let predicted = observation_model.evaluate(prior.state());
+            for detection in detections.iter() {
+                let d2 = base
+                    .with_residual(detection - &predicted)
+                    .mahalanobis_squared();
+                row.push(if d2 <= gate_threshold { Some(d2) } else { None });
+            }
+        }
+        priors.push(prior);
+        distances.push(row);
+    }
+    Ok((priors, distances))
+}
+
+/// Assign detections to tracks minimizing the total cost of gated pairs.
+///
+/// Returns, for each track, the index of the assigned detection, if any. Pairs
+/// outside the gate cost `gate_threshold`, which is the cost of leaving a track
+/// unassigned, and are never returned.
+fn gnn_associate<R: RealField>(
+    distances: &[Vec<Option<R>>],
+    gate_threshold: R,
+) -> Vec<Option<usize>> {
+    let cost: Vec<Vec<R>> = distances
+        .iter()
+        .map(|row| row.iter().map(|d| d.unwrap_or(gate_threshold)).collect())
+        .collect();
+    solve_assignment(&cost)
+        .into_iter()
+        .enumerate()
+        .map(|(i, j)| j.filter(|j| distances[i][*j].is_some()))
+        .collect()
+}
+
+/// A snapshot of the tracks of a [`GnnTracker`](struct.GnnTracker.html)
+///
+/// This holds everything but the models, so that a tracker can be checkpointed
+/// and later restored with [`GnnTracker::restore`](struct.GnnTracker.html#method.restore).
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct GnnTrackerState<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    tracks: Vec<Track<R, SS>>,
+    next_id: TrackId,
+}
+
+/// A multi-target tracker using global nearest neighbor association
+///
+/// All tracks share the same transition model and observation model. Tracks
+/// are created and removed explicitly.
+pub struct GnnTracker<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    gate_threshold: R,
+    tracks: Vec<Track<R, SS>>,
+    next_id: TrackId,
+}
+
+impl<'a, R, SS, OS> GnnTracker<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `GnnTracker` struct with no tracks.
+    ///
+    /// A detection can only be assigned to a track if its squared Mahalanobis
+    /// distance from the track's predicted observation is at most
+    /// `gate_threshold`. This is typically a quantile of the chi-squared
+    /// distribution with `OS` degrees of freedom (e.g. 9.21 for 99% with two
+    /// degrees of freedom).
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        gate_threshold: R,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            gate_threshold,
+            tracks: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Get the current tracks.
+    #[inline]
+    pub fn tracks(&self) -> &[Track<R, SS>] {
+        &self.tracks
+    }
+
+    /// Start a new track and return its identifier.
+    pub fn add_track(&mut self, estimate: StateAndCovariance<R, SS>) -> TrackId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tracks.push(Track { id, estimate });
+        id
+    }
+
+    /// Remove a track, returning it if it existed.
+    pub fn remove_track(&mut self, id: TrackId) -> Option<Track<R, SS>> {
+        let idx = self.tracks.iter().position(|t| t.id == id)?;
+        Some(self.tracks.remove(idx))
+    }
+
+    /// Get a snapshot of the tracks, e.g. to checkpoint the tracker.
+    pub fn state(&self) -> GnnTrackerState<R, SS> {
+        GnnTrackerState {
+            tracks: self.tracks.clone(),
+            next_id: self.next_id,
+        }
+    }
+
+    /// Replace the tracks with those of a snapshot taken by
+    /// [`state`](#method.state).
+    pub fn restore(&mut self, state: GnnTrackerState<R, SS>) {
+        self.tracks = state.tracks;
+        self.next_id = state.next_id;
+    }
+
+    /// Process the detections of one frame
+    ///
+    /// Every track is predicted forward one time step. Detections are then
+    /// associated with tracks and each matched track is updated with its
+    /// detection. Unmatched tracks keep their predicted (prior) estimate.
+    pub fn step(&mut self, detections: &[OVector<R, OS>]) -> Result<FrameAssociation, Error> {
+        let estimates: Vec<_> = self.tracks.iter().map(|t| &t.estimate).collect();
+        let (priors, distances) = gated_distances(
+            self.transition_model,
+            self.observation_model,
+            &estimates,
+            detections,
+            self.gate_threshold,
+        )?;
+        let assignment = gnn_associate(&distances, self.gate_threshold);
+
+        let kf = KalmanFilterNoControl::new(self.transition_model, self.observation_model);
+        let mut result = FrameAssociation {
+            assignments: Vec::new(),
+            unmatched_tracks: Vec::new(),
+            unmatched_detections: Vec::new(),
+        };
+        let mut detection_used = vec![false; detections.len()];
+        for ((track, prior), assigned) in self.tracks.iter_mut().zip(priors).zip(assignment) {
+            match assigned {
+                Some(j) => {
+                    track.estimate = kf.step(&track.estimate, &detections[j])?;
+                    detection_used[j] = true;
+                    result.assignments.push((track.id, j));
+                }
+                None => {
+                    track.estimate = prior;
+                    result.unmatched_tracks.push(track.id);
+                }
+            }
+        }
+        result.unmatched_detections = detection_used
+            .iter()
+            .enumerate()
+            .filter(|(_, used)| !**used)
+            .map(|(j, _)| j)
+            .collect();
+        Ok(result)
+    }
+}
+
+#[test]
+fn test_gnn_tracker() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{OMatrix, U2};
+
+    let transition_model = LinearTransitionModel::<f64, U2>::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::identity() * 0.01,
+    );
+    let observation_model = LinearObservationModel::<f64, U2, U2>::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::identity() * 0.01,
+    );
+    let mut tracker = GnnTracker::new(&transition_model, &observation_model, 9.21);
+    let covariance = OMatrix::<f64, U2, U2>::identity() * 0.1;
+    let a = tracker.add_track(StateAndCovariance::new(
+        OVector::<f64, U2>::new(0.0, 0.0),
+        covariance,
+    ));
+    let b = tracker.add_track(StateAndCovariance::new(
+        OVector::<f64, U2>::new(1.0, 0.0),
+        covariance,
+    ));
+
+    // Detections in a different order than the tracks, plus one far away.
+    let detections = vec![
+        OVector::<f64, U2>::new(1.05, 0.0),
+        OVector::<f64, U2>::new(50.0, 50.0),
+        OVector::<f64, U2>::new(-0.05, 0.0),
+    ];
+    let association = tracker.step(&detections).unwrap();
+    assert_eq!(association.assignments, vec![(a, 2), (b, 0)]);
+    assert!(association.unmatched_tracks.is_empty());
+    assert_eq!(association.unmatched_detections, vec![1]);
+
+    // A frame with only one detection leaves the other track unmatched.
+    let association = tracker.step(&[OVector::<f64, U2>::new(1.0, 0.0)]).unwrap();
+    assert_eq!(association.assignments, vec![(b, 0)]);
+    assert_eq!(association.unmatched_tracks, vec![a]);
+    assert!(tracker.remove_track(a).is_some());
+    assert_eq!(tracker.tracks().len(), 1);
+
+    // A detection with infinite components is rejected.
+    let detections = [
+        OVector::<f64, U2>::new(1.0, 0.0),
+        OVector::<f64, U2>::new(f64::INFINITY, 0.0),
+    ];
+    let err = tracker.step(&detections).unwrap_err();
+    assert_eq!(err.kind(), &crate::ErrorKind::NonFiniteInput);
+    assert_eq!(err.index(), Some(1));
+}
+
+#[cfg(feature = "serde-serialize")]
+#[test]
+fn test_tracker_checkpoint() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{OMatrix, U1, U2};
+
+    // constant velocity in one dimension, observing position
+    let transition_model = LinearTransitionModel::<f64, U2>::new(
+        OMatrix::<f64, U2, U2>::new(1.0, 1.0, 0.0, 1.0),
+        OMatrix::<f64, U2, U2>::new(0.25, 0.5, 0.5, 1.0) * 0.01,
+    );
+    let observation_model = LinearObservationModel::<f64, U2, U1>::new(
+        OMatrix::<f64, U1, U2>::new(1.0, 0.0),
+        OMatrix::<f64, U1, U1>::new(0.1),
+    );
+    let covariance = OMatrix::<f64, U2, U2>::new(0.1, 0.0, 0.0, 0.1);
+    let frames = [[0.1, 9.9], [1.0, 9.1], [2.1, 7.9], [2.9, 7.0]];
+    let detections = |frame: &[f64; 2]| -> Vec<OVector<f64, U1>> {
+        frame.iter().map(|z| OVector::<f64, U1>::new(*z)).collect()
+    };
+
+    let mut gnn = GnnTracker::new(&transition_model, &observation_model, 16.0);
+    let parameters = MhtParameters {
+        detection_probability: 0.9,
+        clutter_density: 0.01,
+        gate_threshold: 16.0,
+        n_scan: 2,
+        max_hypotheses: 10,
+    };
+    let mut mht = MhtTracker::new(&transition_model, &observation_model, parameters);
+    for (x, v) in [(0.0, 1.0), (10.0, -1.0)].iter() {
+        let estimate = StateAndCovariance::new(OVector::<f64, U2>::new(*x, *v), covariance);
+        gnn.add_track(estimate.clone());
+        mht.add_track(estimate);
+    }
+    for frame in frames[..2].iter() {
+        gnn.step(&detections(frame)).unwrap();
+        mht.step(&detections(frame)).unwrap();
+    }
+
+    // Checkpoint both trackers and restore them into new ones.
+    let json = serde_json::to_string(&gnn.state()).unwrap();
+    let mut restored_gnn = GnnTracker::new(&transition_model, &observation_model, 16.0);
+    restored_gnn.restore(serde_json::from_str(&json).unwrap());
+    let json = serde_json::to_string(&mht.state()).unwrap();
+    let mut restored_mht = MhtTracker::new(&transition_model, &observation_model, parameters);
+    restored_mht.restore(serde_json::from_str(&json).unwrap());
+
+    for frame in frames[2..].iter() {
+        let expected = gnn.step(&detections(frame)).unwrap();
+        assert_eq!(restored_gnn.step(&detections(frame)).unwrap(), expected);
+        let expected = mht.step(&detections(frame)).unwrap();
+        let actual = restored_mht.step(&detections(frame)).unwrap();
+        assert_eq!(actual.score, expected.score);
+        assert_eq!(actual.tracks.len(), expected.tracks.len());
+    }
+    for (actual, expected) in restored_gnn.tracks().iter().zip(gnn.tracks()) {
+        assert_eq!(actual.id(), expected.id());
+        assert_eq!(actual.estimate().state(), expected.estimate().state());
+    }
+    // New tracks continue the numbering of the checkpointed tracker.
+    let estimate = StateAndCovariance::new(OVector::<f64, U2>::new(0.0, 0.0), covariance);
+    assert_eq!(
+        restored_gnn.add_track(estimate.clone()),
+        gnn.add_track(estimate)
+    );
+}
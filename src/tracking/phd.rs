@@ -0,0 +1,336 @@
+use na::allocator::Allocator;
+use na::dimension::DimMin;
+use na::{DefaultAllocator, DimName, OVector, RealField};
+use nalgebra as na;
+
+use super::check_detections;
+use crate::{
+    CoverianceUpdateMethod, Error, ErrorKind, ObservationModelLinear, StateAndCovariance,
+    TransitionModelLinearNoControl,
+};
+
+/// A weighted Gaussian, one component of a Gaussian mixture
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(bound(
+        serialize = "R: serde::Serialize, StateAndCovariance<R, SS>: serde::Serialize",
+        deserialize = "R: serde::Deserialize<'de>, StateAndCovariance<R, SS>: serde::Deserialize<'de>"
+    ))
+)]
+pub struct GaussianComponent<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// The weight of this component.
+    pub weight: R,
+    /// The mean and covariance of this component.
+    pub estimate: StateAndCovariance<R, SS>,
+}
+
+impl<R, SS> GaussianComponent<R, SS>
+where
+    R: RealField,
+    SS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    /// Create a new `GaussianComponent`.
+    pub fn new(weight: R, estimate: StateAndCovariance<R, SS>) -> Self {
+        Self { weight, estimate }
+    }
+}
+
+/// Parameters of the [`GmPhdFilter`](struct.GmPhdFilter.html)
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct GmPhdParameters<R: RealField> {
+    /// Probability that a target survives from one frame to the next.
+    pub survival_probability: R,
+    /// Probability that a target is detected in a given frame.
+    pub detection_probability: R,
+    /// Intensity of clutter (false) detections, in detections per unit volume
+    /// of observation space.
+    pub clutter_intensity: R,
+    /// Components with a weight below this value are discarded.
+    pub prune_threshold: R,
+    /// Components whose means are within this squared Mahalanobis distance of
+    /// the highest weighted component are merged into it.
+    pub merge_threshold: R,
+    /// Maximum number of components kept after pruning and merging.
+    pub max_components: usize,
+    /// Components with at least this weight are extracted as targets.
+    pub extraction_threshold: R,
+}
+
+/// A Gaussian-mixture Probability Hypothesis Density (GM-PHD) filter
+///
+/// The PHD filter propagates the intensity (first moment) of the random finite
+/// set of targets rather than individual tracks, and so estimates the number
+/// of targets along with their states without explicit data association. The
+/// integral of the intensity is the expected number of targets. Here, the
+/// intensity is represented by a mixture of weighted Gaussians. See B.-N. Vo
+/// and W.-K. Ma, "The Gaussian mixture probability hypothesis density filter",
+/// IEEE Transactions on Signal Processing, 2006.
+///
+/// New targets appear according to the birth intensity, which is added to the
+/// predicted intensity every frame.
+pub struct GmPhdFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+{
+    transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+    observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+    birth_intensity: Vec<GaussianComponent<R, SS>>,
+    parameters: GmPhdParameters<R>,
+    components: Vec<GaussianComponent<R, SS>>,
+}
+
+impl<'a, R, SS, OS> GmPhdFilter<'a, R, SS, OS>
+where
+    R: RealField,
+    SS: DimName,
+    OS: DimName + DimMin<OS, Output = OS>,
+    DefaultAllocator: Allocator<R, SS, SS>,
+    DefaultAllocator: Allocator<R, SS>,
+    DefaultAllocator: Allocator<R, OS, SS>,
+    DefaultAllocator: Allocator<R, SS, OS>,
+    DefaultAllocator: Allocator<R, OS, OS>,
+    DefaultAllocator: Allocator<R, OS>,
+    DefaultAllocator: Allocator<(usize, usize), OS>,
+{
+    /// Initialize a new `GmPhdFilter` struct with an empty intensity.
+    pub fn new(
+        transition_model: &'a dyn TransitionModelLinearNoControl<R, SS>,
+        observation_model: &'a dyn ObservationModelLinear<R, SS, OS>,
+        birth_intensity: Vec<GaussianComponent<R, SS>>,
+        parameters: GmPhdParameters<R>,
+    ) -> Self {
+        Self {
+            transition_model,
+            observation_model,
+            birth_intensity,
+            parameters,
+            components: Vec::new(),
+        }
+    }
+
+    /// Get the components of the current intensity.
+    #[inline]
+    pub fn components(&self) -> &[GaussianComponent<R, SS>] {
+        &self.components
+    }
+
+    /// Replace the components of the current intensity, e.g. to restore a
+    /// checkpoint taken with [`components`](#method.components).
+    pub fn set_components(&mut self, components: Vec<GaussianComponent<R, SS>>) {
+        self.components = components;
+    }
+
+    /// Get the expected number of targets, the sum of the component weights.
+    pub fn expected_target_count(&self) -> R {
+        self.components
+            .iter()
+            .fold(R::zero(), |acc, c| acc + c.weight)
+    }
+
+    /// Predict, update with the detections, and then prune and merge.
+    pub fn step(&mut self, detections: &[OVector<R, OS>]) -> Result<(), Error> {
+        self.predict();
+        self.update(detections)?;
+        self.prune_and_merge()
+    }
+
+    /// Predict the intensity forward one time step and add the birth intensity.
+    pub fn predict(&mut self) {
+        let p_s = self.parameters.survival_probability;
+        let transition_model = self.transition_model;
+        for component in self.components.iter_mut() {
+            component.weight *= p_s;
+            component.estimate = transition_model.predict(&component.estimate);
+        }
+        self.components.extend(self.birth_intensity.iter().cloned());
+    }
+
+    /// Update the predicted intensity with the detections of one frame.
+    pub fn update(&mut self, detections: &[OVector<R, OS>]) -> Result<(), Error> {
+        check_detections(detections)?;
+        let p_d = self.parameters.detection_probability;
+        let mut updated = Vec::with_capacity(self.components.len() * (detections.len() + 1));
+
+        // missed detections
+        for component in self.components.iter() {
+            updated.push(GaussianComponent::new(
+                component.weight * (R::one() - p_d),
+                component.estimate.clone(),
+            ));
+        }
+
+        // detections
+        for detection in detections.iter() {
+            let start = updated.len();
+            let mut total = self.parameters.clutter_intensity;
+            for component in self.components.iter() {
+                let innovation = self
+                    .observation_model
+                    .innovation(&component.estimate, detection)?;
+                let weight = p_d * component.weight * innovation.log_likelihood().exp();
+                total += weight;
+                let estimate = self.observation_model.update(
+                    &component.estimate,
+                    detection,
+                    CoverianceUpdateMethod::OptimalKalmanForcedSymmetric,
+                )?;
+                updated.push(GaussianComponent::new(weight, estimate));
+            }
+            for component in updated[start..].iter_mut() {
+                component.weight /= total;
+            }
+        }
+        self.components = updated;
+        Ok(())
+    }
+
+    /// Prune components of low weight and merge nearby components.
+    ///
+    /// At most `max_components` components, those of highest weight, are kept.
+    pub fn prune_and_merge(&mut self) -> Result<(), Error> {
+        let prune_threshold = self.parameters.prune_threshold;
+        let mut remaining: Vec<_> = self
+            .components
+            .drain(..)
+            .filter(|c| c.weight >= prune_threshold)
+            .collect();
+        let mut merged = Vec::new();
+        while !remaining.is_empty() {
+            let mut best = 0;
+            for (i, c) in remaining.iter().enumerate() {
+                if c.weight > remaining[best].weight {
+                    best = i;
+                }
+            }
+            // As in Table II of Vo and Ma, the distance of each component is
+            // measured with its own covariance.
+            let center = remaining[best].estimate.state().clone();
+            let mut close = Vec::new();
+            let mut far = Vec::new();
+            for c in remaining.into_iter() {
+                let chol = na::linalg::Cholesky::new(c.estimate.covariance().clone())
+                    .ok_or(ErrorKind::CovarianceNotPositiveSemiDefinite)?;
+                let d = c.estimate.state() - &center;
+                if d.dot(&chol.solve(&d)) <= self.parameters.merge_threshold {
+                    close.push(c);
+                } else {
+                    far.push(c);
+                }
+            }
+            let weights: Vec<R> = close.iter().map(|c| c.weight).collect();
+            let estimates: Vec<_> = close.into_iter().map(|c| c.estimate).collect();
+            let weight = weights.iter().fold(R::zero(), |acc, w| acc + *w);
+            merged.push(GaussianComponent::new(
+                weight,
+                StateAndCovariance::mixture(&weights, &estimates),
+            ));
+            remaining = far;
+        }
+        merged.sort_by(|a, b| b.weight.partial_cmp(&a.weight).unwrap());
+        merged.truncate(self.parameters.max_components);
+        self.components = merged;
+        Ok(())
+    }
+
+    /// Extract target state estimates from the intensity.
+    ///
+    /// Each component with a weight of at least `extraction_threshold` yields
+    /// as many targets as its weight rounded to the nearest integer (but at
+    /// least one).
+    pub fn extract_states(&self) -> Vec<StateAndCovariance<R, SS>> {
+        let mut states = Vec::new();
+        for component in self.components.iter() {
+            if component.weight >= self.parameters.extraction_threshold {
+                let mut n = component.weight.round();
+                loop {
+                    states.push(component.estimate.clone());
+                    n -= R::one();
+                    if n < R::one() {
+                        break;
+                    }
+                }
+            }
+        }
+        states
+    }
+}
+
+#[test]
+fn test_gm_phd() {
+    use crate::{LinearObservationModel, LinearTransitionModel};
+    use na::{OMatrix, U2};
+
+    let transition_model = LinearTransitionModel::<f64, U2>::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::identity() * 0.01,
+    );
+    let observation_model = LinearObservationModel::<f64, U2, U2>::new(
+        OMatrix::<f64, U2, U2>::identity(),
+        OMatrix::<f64, U2, U2>::identity() * 0.01,
+    );
+    let birth = vec![
+        GaussianComponent::new(
+            0.1,
+            StateAndCovariance::new(
+                OVector::<f64, U2>::new(0.0, 0.0),
+                OMatrix::<f64, U2, U2>::identity(),
+            ),
+        ),
+        GaussianComponent::new(
+            0.1,
+            StateAndCovariance::new(
+                OVector::<f64, U2>::new(10.0, 10.0),
+                OMatrix::<f64, U2, U2>::identity(),
+            ),
+        ),
+    ];
+    let parameters = GmPhdParameters {
+        survival_probability: 0.99,
+        detection_probability: 0.95,
+        clutter_intensity: 1e-3,
+        prune_threshold: 1e-5,
+        merge_threshold: 4.0,
+        max_components: 50,
+        extraction_threshold: 0.5,
+    };
+    let mut phd = GmPhdFilter::new(&transition_model, &observation_model, birth, parameters);
+
+    // Two stationary targets and one clutter detection which moves around.
+    for t in 0..10 {
+        let clutter = OVector::<f64, U2>::new(-20.0 + 3.0 * t as f64, 25.0);
+        let detections = vec![
+            OVector::<f64, U2>::new(0.3, -0.2),
+            clutter,
+            OVector::<f64, U2>::new(9.8, 10.1),
+        ];
+        phd.step(&detections).unwrap();
+    }
+    assert!((phd.expected_target_count() - 2.0).abs() < 0.2);
+    let mut states = phd.extract_states();
+    assert_eq!(states.len(), 2);
+    states.sort_by(|a, b| a.state()[0].partial_cmp(&b.state()[0]).unwrap());
+    assert!((states[0].state() - OVector::<f64, U2>::new(0.3, -0.2)).norm() < 0.05);
+    assert!((states[1].state() - OVector::<f64, U2>::new(9.8, 10.1)).norm() < 0.05);
+    assert!(phd.components().len() <= 50);
+}